@@ -0,0 +1,71 @@
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion, Throughput};
+use rusty_chip8::{
+    chip::Chip, instructions::Instruction, platform::Headless, recompile::Recompiler,
+};
+
+const ROMS: [&str; 8] = [
+    "IBM_Logo.ch8",
+    "Zero.ch8",
+    "Trip8.ch8",
+    "Sierpinski.ch8",
+    "Maze.ch8",
+    "Stars.ch8",
+    "particle_demo.ch8",
+    "space_invaders.ch8",
+];
+const INSTRUCTIONS: u64 = 10_000;
+
+fn load(rom: &str) -> Chip {
+    let mut chip = Chip::new();
+    chip.trace = false;
+    chip.rng = fastrand::Rng::with_seed(0xC8);
+    chip.load(format!("{}/roms/{rom}", env!("CARGO_MANIFEST_DIR")))
+        .unwrap();
+    chip
+}
+
+// Instructions per second for each bundled rom through the recompiler, the
+// decoded instruction cache (`step`) and decoding every instruction
+// (`interpret`).
+fn instructions_per_second(c: &mut Criterion) {
+    let mut group = c.benchmark_group("roms");
+    group.throughput(Throughput::Elements(INSTRUCTIONS));
+    let platform = Headless::default();
+    for rom in ROMS {
+        group.bench_with_input(BenchmarkId::new("recompile", rom), rom, |b, rom| {
+            let mut chip = load(rom);
+            let mut buffer = vec![0u32; 64 * 32];
+            let mut recompiler = Recompiler::new();
+            b.iter(|| {
+                chip.reset();
+                recompiler
+                    .run(&mut chip, INSTRUCTIONS as usize, &mut buffer, &platform)
+                    .unwrap();
+            });
+        });
+        group.bench_with_input(BenchmarkId::new("step", rom), rom, |b, rom| {
+            let mut chip = load(rom);
+            let mut buffer = vec![0u32; 64 * 32];
+            b.iter(|| {
+                chip.reset();
+                (0..INSTRUCTIONS).for_each(|_| chip.step(&mut buffer, &platform).unwrap());
+            });
+        });
+        group.bench_with_input(BenchmarkId::new("interpret", rom), rom, |b, rom| {
+            let mut chip = load(rom);
+            let mut buffer = vec![0u32; 64 * 32];
+            b.iter(|| {
+                chip.reset();
+                for _ in 0..INSTRUCTIONS {
+                    let pc = chip.pc as usize;
+                    let instruction = Instruction::new(&chip.mem[pc..pc + 2]);
+                    chip.interpret(instruction, &mut buffer, &platform).unwrap();
+                }
+            });
+        });
+    }
+    group.finish();
+}
+
+criterion_group!(benches, instructions_per_second);
+criterion_main!(benches);
@@ -0,0 +1,24 @@
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use rusty_chip8::{dump, instructions::Instruction, op::Op, profile, variant::Variant};
+
+const VARIANTS: [Variant; 4] = [
+    Variant::Chip8,
+    Variant::Chip8x,
+    Variant::Hires,
+    Variant::Megachip,
+];
+
+// Every opcode decodes and disassembles, in every variant.
+fuzz_target!(|data: &[u8]| {
+    for (n, bytes) in data.chunks_exact(2).enumerate() {
+        let instruction = Instruction::new(bytes);
+        for variant in VARIANTS {
+            let pc = variant.start().wrapping_add(2 * n as u16);
+            let _ = Op::decode_at(instruction.opcode, variant, pc);
+            let _ = dump::mnemonic_for(&instruction, variant);
+        }
+        let _ = profile::class(instruction.opcode);
+    }
+});
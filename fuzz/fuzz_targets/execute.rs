@@ -0,0 +1,89 @@
+#![no_main]
+
+use arbitrary::Arbitrary;
+use libfuzzer_sys::fuzz_target;
+use rusty_chip8::{
+    chip::Chip, op::Op, platform::Headless, quirks::Quirks, recompile::Recompiler, variant::Variant,
+};
+
+const CYCLES_PER_FRAME: usize = 11;
+const VARIANTS: [Variant; 4] = [
+    Variant::Chip8,
+    Variant::Chip8x,
+    Variant::Hires,
+    Variant::Megachip,
+];
+
+#[derive(Arbitrary, Debug)]
+struct Input {
+    /// Picks from `VARIANTS`.
+    variant: u8,
+    quirks: [bool; 5],
+    /// The keys held during each frame, one bit per key.
+    keys: Vec<u16>,
+    rom: Vec<u8>,
+}
+
+fn chip(input: &Input) -> Option<Chip> {
+    let [shift, vf_reset, memory, jumping, clipping] = input.quirks;
+    let mut chip = Chip::new();
+    chip.trace = false;
+    chip.rng = fastrand::Rng::with_seed(0);
+    chip.variant = VARIANTS[input.variant as usize % VARIANTS.len()];
+    chip.start = chip.variant.start();
+    chip.quirks = Quirks {
+        shift,
+        vf_reset,
+        memory,
+        jumping,
+        clipping,
+    };
+    chip.load_bytes(&input.rom).ok()?;
+    Some(chip)
+}
+
+// Any rom and input runs without panicking, and the interpreter and the
+// recompiler agree on the outcome.
+fuzz_target!(|input: Input| {
+    let (Some(mut interpreted), Some(mut recompiled)) = (chip(&input), chip(&input)) else {
+        return;
+    };
+    let mut recompiler = Recompiler::new();
+    // CHIP-8 roms that happen to start with 1260 get the hi-res display.
+    let (width, height) = interpreted.variant.size();
+    let mut interpreted_buffer = vec![0u32; width * height];
+    let mut recompiled_buffer = vec![0u32; width * height];
+
+    for keys in input.keys.iter().take(256) {
+        let mut platform = Headless::default();
+        for (key, held) in platform.keys.iter_mut().enumerate() {
+            *held = keys & (1 << key) != 0;
+        }
+        let expected = interpreted.frame(CYCLES_PER_FRAME, &mut interpreted_buffer, &platform);
+        let actual = recompiler.frame(
+            &mut recompiled,
+            CYCLES_PER_FRAME,
+            &mut recompiled_buffer,
+            &platform,
+        );
+        assert_eq!(expected, actual);
+        assert_eq!(interpreted.pc, recompiled.pc);
+        assert_eq!(interpreted.v, recompiled.v);
+        assert!(interpreted_buffer == recompiled_buffer);
+        if expected.is_err() {
+            break;
+        }
+    }
+    // Once at the end, as MegaChip has 16M of it.
+    assert!(interpreted.mem == recompiled.mem);
+
+    // A 0NNN machine code routine is skipped over, in every variant.
+    if let Some(mut chip) = chip(&input) {
+        let pc = chip.pc;
+        if matches!(chip.decode(pc), Ok(Op::Sys(_))) {
+            chip.step(&mut interpreted_buffer, &Headless::default())
+                .unwrap();
+            assert_eq!(chip.pc, pc + 2);
+        }
+    }
+});
@@ -0,0 +1,71 @@
+use std::io::{self, Write};
+
+use crate::chip::Chip;
+
+/// Sound is unsigned 8-bit mono at this many samples a second.
+pub const RATE: u32 = 44_100;
+/// Samples in one 60 Hz frame.
+pub const FRAME: usize = RATE as usize / 60;
+pub const SILENCE: u8 = 0x80;
+
+/// Where the chip's sound goes, a frame at a time: a file, or a pipe into a
+/// player such as `aplay -t raw -f U8 -r 44100`.
+pub struct Speaker {
+    out: Box<dyn Write>,
+    frame: Vec<u8>,
+}
+
+impl Speaker {
+    pub fn new(out: impl Write + 'static) -> Speaker {
+        Speaker {
+            out: Box::new(out),
+            frame: vec![SILENCE; FRAME],
+        }
+    }
+
+    /// Plays the next frame of the chip's sound.
+    pub fn play(&mut self, chip: &mut Chip) -> io::Result<()> {
+        self.frame.fill(SILENCE);
+        chip.audio(&mut self.frame);
+        self.out.write_all(&self.frame)?;
+        self.out.flush()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        io::{self, Write},
+        sync::{Arc, Mutex},
+    };
+
+    use super::{Speaker, FRAME, SILENCE};
+    use crate::chip::Chip;
+
+    #[derive(Clone, Default)]
+    struct Shared(Arc<Mutex<Vec<u8>>>);
+
+    impl Write for Shared {
+        fn write(&mut self, bytes: &[u8]) -> io::Result<usize> {
+            self.0.lock().unwrap().extend_from_slice(bytes);
+            Ok(bytes.len())
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn test_silence() {
+        let out = Shared::default();
+        let mut speaker = Speaker::new(out.clone());
+
+        speaker.play(&mut Chip::new()).unwrap();
+        speaker.play(&mut Chip::new()).unwrap();
+
+        let written = out.0.lock().unwrap();
+        assert_eq!(written.len(), 2 * FRAME);
+        assert!(written.iter().all(|&s| s == SILENCE));
+    }
+}
@@ -0,0 +1,164 @@
+use std::error::Error;
+
+use serde_json::Value;
+
+use crate::{
+    config::{parse_colour, RomConfig},
+    octo::{self, AsmError},
+    quirks::Quirks,
+};
+
+/// An Octo cartridge: a GIF whose pixels carry the program's source and the
+/// options it was shared with.
+pub struct Cartridge {
+    pub source: String,
+    pub options: Value,
+}
+
+impl Cartridge {
+    pub fn is_cartridge(bytes: &[u8]) -> bool {
+        bytes.starts_with(b"GIF87a") || bytes.starts_with(b"GIF89a")
+    }
+
+    /// The payload is in the low nibble of each pixel's colour index, two
+    /// pixels to a byte: a 32-bit big-endian length, then that many bytes of
+    /// JSON with the source in `program` and the settings in `options`.
+    pub fn decode(bytes: &[u8]) -> Result<Cartridge, Box<dyn Error>> {
+        let mut options = gif::DecodeOptions::new();
+        options.set_color_output(gif::ColorOutput::Indexed);
+        let mut decoder = options.read_info(bytes)?;
+        let frame = decoder
+            .read_next_frame()?
+            .ok_or("the cartridge has no image")?;
+        let payload: Vec<u8> = frame
+            .buffer
+            .chunks_exact(2)
+            .map(|pixels| (pixels[0] & 0xF) << 4 | pixels[1] & 0xF)
+            .collect();
+        let (len, json) = payload
+            .split_first_chunk::<4>()
+            .ok_or("the cartridge is too small")?;
+        let json = json
+            .get(..u32::from_be_bytes(*len) as usize)
+            .ok_or("the cartridge's program is cut short")?;
+        let mut payload: Value = serde_json::from_slice(json)?;
+        let source = payload["program"]
+            .as_str()
+            .ok_or("the cartridge has no program")?
+            .to_string();
+        Ok(Cartridge {
+            source,
+            options: payload["options"].take(),
+        })
+    }
+
+    pub fn rom(&self) -> Result<Vec<u8>, AsmError> {
+        Ok(octo::assemble(&self.source)?.rom)
+    }
+
+    /// Octo's tick rate, colours and quirks as rom settings. Quirks Octo does
+    /// not mention are off, as they are in Octo.
+    pub fn config(&self) -> RomConfig {
+        let options = &self.options;
+        let quirk = |name: &str| options[name].as_bool().unwrap_or(false);
+        let colour = |name: &str| parse_colour(options[name].as_str()?);
+        RomConfig {
+            quirks: Some(Quirks {
+                shift: quirk("shiftQuirks"),
+                vf_reset: quirk("logicQuirks"),
+                // Octo's quirk is leaving I alone.
+                memory: !quirk("loadStoreQuirks"),
+                jumping: quirk("jumpQuirks"),
+                clipping: quirk("clipQuirks"),
+            }),
+            // Rates too fast to count are left to the default.
+            ips: options["tickrate"]
+                .as_u64()
+                .and_then(|rate| u32::try_from(rate).ok()?.checked_mul(60)),
+            palette: colour("backgroundColor")
+                .zip(colour("fillColor"))
+                .map(Into::into),
+            ..RomConfig::default()
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::borrow::Cow;
+
+    use super::Cartridge;
+    use crate::quirks::Quirks;
+
+    /// Hides `json` in a GIF the way Octo does.
+    fn cartridge(json: &str) -> Vec<u8> {
+        let mut payload = (json.len() as u32).to_be_bytes().to_vec();
+        payload.extend_from_slice(json.as_bytes());
+        let (width, height) = (64u16, (payload.len() as u16 * 2).div_ceil(64));
+        // A label in the high nibbles, as if there were a picture.
+        let mut pixels: Vec<u8> = (0..width as usize * height as usize)
+            .map(|i| (i % 7) as u8 * 0x10)
+            .collect();
+        for (i, byte) in payload.iter().enumerate() {
+            pixels[i * 2] |= byte >> 4;
+            pixels[i * 2 + 1] |= byte & 0xF;
+        }
+        let palette: Vec<u8> = (0..=255u8).flat_map(|i| [i, i, i]).collect();
+
+        let mut gif = Vec::new();
+        let mut encoder = gif::Encoder::new(&mut gif, width, height, &palette).unwrap();
+        let frame = gif::Frame {
+            width,
+            height,
+            buffer: Cow::Owned(pixels),
+            ..gif::Frame::default()
+        };
+        encoder.write_frame(&frame).unwrap();
+        drop(encoder);
+        gif
+    }
+
+    #[test]
+    fn test_decode() {
+        let gif = cartridge(
+            r##"{"key": "", "program": ": main\n  v0 := 1\n  jump main",
+                "options": {"tickrate": 20, "shiftQuirks": true, "loadStoreQuirks": true,
+                "backgroundColor": "#996600", "fillColor": "#FFCC00"}}"##,
+        );
+        assert!(Cartridge::is_cartridge(&gif));
+
+        let cartridge = Cartridge::decode(&gif).unwrap();
+        let config = cartridge.config();
+
+        assert_eq!(cartridge.rom().unwrap(), [0x60, 0x01, 0x12, 0x00]);
+        assert_eq!(config.ips, Some(1200));
+        assert_eq!(config.palette, Some([0x996600, 0xFFCC00]));
+        assert_eq!(
+            config.quirks,
+            Some(Quirks {
+                shift: true,
+                vf_reset: false,
+                memory: false,
+                jumping: false,
+                clipping: false,
+            })
+        );
+    }
+
+    #[test]
+    fn test_bad_cartridges() {
+        assert!(Cartridge::decode(b"GIF89a").is_err());
+        assert!(Cartridge::decode(&cartridge("{\"options\": {}}")).is_err());
+        assert!(Cartridge::decode(&cartridge("not json")).is_err());
+    }
+
+    #[test]
+    fn test_tickrate_out_of_range() {
+        for rate in ["4294967296", "100000000"] {
+            let json = format!(r#"{{"program": "", "options": {{"tickrate": {rate}}}}}"#);
+            let cartridge = Cartridge::decode(&cartridge(&json)).unwrap();
+
+            assert_eq!(cartridge.config().ips, None);
+        }
+    }
+}
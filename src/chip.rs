@@ -1,21 +1,161 @@
-use std::fmt::Display;
+use std::{error::Error, fmt::Display, io, path::Path};
 
-use minifb::{Key, Window};
+use zip::result::ZipError;
 
-use crate::{dump, instructions::Instruction};
+use crate::{
+    cartridge::Cartridge,
+    chip8x::Colours,
+    coverage::Coverage,
+    dump,
+    instructions::Instruction,
+    megachip::{Megachip, Sample},
+    op::Op,
+    platform::Platform,
+    profile::Profiler,
+    quirks::Quirks,
+    rom,
+    variant::Variant,
+};
 
 const MEM_SIZE: usize = 4096;
 const START_MEM: u16 = 0x200;
 
+#[derive(Debug)]
+pub enum LoadError {
+    Io(io::Error),
+    TooLarge { size: usize, max: usize },
+    BadAddress(u16),
+    Cartridge(Box<dyn Error>),
+    Zip(ZipError),
+    Member(String),
+}
+
+impl Display for LoadError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            LoadError::Io(e) => write!(f, "{e}"),
+            LoadError::TooLarge { size, max } => {
+                write!(f, "rom is {size} bytes, only {max} fit in memory")
+            }
+            LoadError::BadAddress(start) => {
+                write!(f, "load address {start:#05X} is outside memory")
+            }
+            LoadError::Cartridge(e) => write!(f, "bad Octo cartridge: {e}"),
+            LoadError::Zip(e) => write!(f, "bad zip archive: {e}"),
+            LoadError::Member(message) => write!(f, "{message}"),
+        }
+    }
+}
+
+impl Error for LoadError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            LoadError::Io(e) => Some(e),
+            LoadError::Cartridge(e) => Some(e.as_ref()),
+            LoadError::Zip(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl From<io::Error> for LoadError {
+    fn from(value: io::Error) -> Self {
+        LoadError::Io(value)
+    }
+}
+
+impl From<ZipError> for LoadError {
+    fn from(value: ZipError) -> Self {
+        LoadError::Zip(value)
+    }
+}
+
+/// A write by the instruction at `pc` into `address`, which had already run as
+/// code.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct CodeWrite {
+    pub pc: u16,
+    pub address: u16,
+}
+
+/// Something the program did that real hardware has no sensible answer to.
+/// The instruction at `pc` is left unexecuted.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Fault {
+    StackOverflow {
+        pc: u16,
+    },
+    StackUnderflow {
+        pc: u16,
+    },
+    /// An instruction needed memory past the end, starting at `address`.
+    OutOfMemory {
+        pc: u16,
+        address: usize,
+    },
+    /// PC left memory.
+    BadPc {
+        pc: u16,
+    },
+}
+
+impl Display for Fault {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Fault::StackOverflow { pc } => write!(f, "stack overflow at {pc:#05X}"),
+            Fault::StackUnderflow { pc } => write!(f, "return with an empty stack at {pc:#05X}"),
+            Fault::OutOfMemory { pc, address } => {
+                write!(
+                    f,
+                    "{pc:#05X} accessed memory past the end at {address:#05X}"
+                )
+            }
+            Fault::BadPc { pc } => write!(f, "PC left memory at {pc:#05X}"),
+        }
+    }
+}
+
+impl Error for Fault {}
+
 pub struct Chip {
     pub v: [u8; 16],
-    pub i: u16,
+    /// 24 bits wide for MegaChip, 12 bits are enough otherwise.
+    pub i: u32,
     pub stack: [u16; 8],
     pub sp: usize,
     pub st: u8,
     pub dt: u8,
     pub pc: u16,
-    pub mem: [u8; MEM_SIZE],
+    /// 4K, or 16M for MegaChip. Code has to be in the first 4K either way.
+    pub mem: Vec<u8>,
+    pub trace: bool,
+    pub rng: fastrand::Rng,
+    pub quirks: Quirks,
+    /// Set before loading the rom, which clears decoded instructions. Loading a
+    /// rom with a hi-res header at 0x200 turns `Chip8` into `Hires`, until the
+    /// next rom is loaded.
+    pub variant: Variant,
+    /// The CHIP-8X colour board, which `buffer` does not hold.
+    pub colours: Colours,
+    /// The last byte written to the CHIP-8X I/O port.
+    pub output: u8,
+    /// MegaChip's colour display and sample, drawn to once 0011 turns it on.
+    pub mega: Megachip,
+    /// Where the rom is loaded and execution starts, 0x600 on the ETI-660.
+    pub start: u16,
+    pub profiler: Option<Profiler>,
+    pub coverage: Option<Coverage>,
+    /// Collects writes into already executed code while set, for debuggers to
+    /// drain.
+    pub code_writes: Option<Vec<CodeWrite>>,
+    executed: [bool; MEM_SIZE],
+    // Instructions already decoded, by address. Anything that writes to memory
+    // must clear the entries it overlaps.
+    decoded: Vec<Option<Op>>,
+    code_generation: u64,
+    rom: Vec<u8>,
+    // The variant the last rom turned `Chip8` into, if it did.
+    detected: Option<Variant>,
 }
 
 impl Default for Chip {
@@ -54,329 +194,571 @@ impl Chip {
             st: 0,
             dt: 0,
             pc: START_MEM,
-            mem: [0; MEM_SIZE],
+            mem: vec![0; MEM_SIZE],
+            trace: true,
+            rng: fastrand::Rng::new(),
+            quirks: Quirks::default(),
+            variant: Variant::default(),
+            colours: Colours::default(),
+            output: 0,
+            mega: Megachip::default(),
+            start: START_MEM,
+            profiler: None,
+            coverage: None,
+            code_writes: None,
+            executed: [false; MEM_SIZE],
+            decoded: vec![None; MEM_SIZE],
+            code_generation: 0,
+            rom: Vec::new(),
+            detected: None,
         }
     }
 
-    pub fn load(&mut self, filepath: String) -> Result<(), Box<dyn std::error::Error>> {
-        let mut buffer = std::fs::read(filepath)?;
-        if (buffer.len() & 1) == 1 {
-            buffer.push(0x00);
-        }
+    /// Loads a rom file, which `rom::read` finds.
+    pub fn load(&mut self, filepath: impl AsRef<Path>) -> Result<(), LoadError> {
+        self.load_contents(&rom::read(filepath.as_ref())?)
+    }
 
-        for (dst, src) in self.mem[(START_MEM as usize)..].iter_mut().zip(&buffer) {
-            *dst = *src
+    /// Loads the contents of a rom file: a rom, or an Octo cartridge whose
+    /// program is assembled.
+    pub fn load_contents(&mut self, contents: &[u8]) -> Result<(), LoadError> {
+        if Cartridge::is_cartridge(contents) {
+            let rom = Cartridge::decode(contents)
+                .and_then(|cartridge| Ok(cartridge.rom()?))
+                .map_err(LoadError::Cartridge)?;
+            return self.load_bytes(&rom);
         }
-
-        Ok(())
+        self.load_bytes(contents)
     }
 
-    pub fn interpret(&mut self, instruction: Instruction, buffer: &mut [u32], window: &Window) {
-        dump::decode(&instruction, self.pc);
-        match instruction.f_nibble {
-            0x0 => {
-                if instruction.x == 0x00 {
-                    match instruction.nn {
-                        0xE0 => self.cls(buffer),
-                        0xEE => self.rts(),
-                        _ => eprintln!("UNKNOWN 0"),
-                    }
-                } else {
-                    eprintln!("UNKNOWN 0");
-                }
-            }
-            0x1 => self.jump(instruction),
-            0x6 => self.mvi(instruction),
-            0x7 => self.adi(instruction),
-            0xA => self.mvi(instruction),
-            0xD => self.draw(instruction, buffer),
-            0x3 => self.skip_eq(instruction),
-            0x5 => self.skip_eq(instruction),
-            0x4 => self.skip_ne(instruction),
-            0x9 => self.skip_ne(instruction),
-            0x2 => self.call(instruction),
-            0x8 => self.eight_inst(instruction),
-            0xC => self.rndmsk(instruction),
-            0xF => self.f_inst(instruction, &window),
-            0xE => self.skipkey(instruction, window),
-            _ => todo!(),
+    pub fn load_bytes(&mut self, rom: &[u8]) -> Result<(), LoadError> {
+        if self.detected.take() == Some(self.variant) {
+            self.variant = Variant::Chip8;
         }
-        if self.dt > 0 {
-            self.dt -= 1
+        if self.variant == Variant::Chip8 && self.start == START_MEM {
+            self.detected = Variant::detect(rom);
+            self.variant = self.detected.unwrap_or(self.variant);
         }
-        if self.st > 0 {
-            self.st -= 1
+        let max = self
+            .variant
+            .memory_size()
+            .checked_sub(self.start as usize)
+            .ok_or(LoadError::BadAddress(self.start))?;
+        if rom.len() > max {
+            return Err(LoadError::TooLarge {
+                size: rom.len(),
+                max,
+            });
         }
+
+        self.rom.clear();
+        self.rom.extend_from_slice(rom);
+        self.reset();
+        Ok(())
     }
 
-    fn cls(&mut self, buffer: &mut [u32]) {
-        buffer.fill(0u32);
-        self.pc += 0x02;
+    pub fn rom(&self) -> &[u8] {
+        &self.rom
     }
-    fn jump(&mut self, instruction: Instruction) {
-        self.pc = instruction.nnn;
-    }
-
-    fn eight_inst(&mut self, instruction: Instruction) {
-        match instruction.l_nibble {
-            0x0 => self.v[instruction.x as usize] = self.v[instruction.y as usize],
-            0x1 => self.v[instruction.x as usize] |= self.v[instruction.y as usize],
-            0x2 => self.v[instruction.x as usize] &= self.v[instruction.y as usize],
-            0x3 => self.v[instruction.x as usize] ^= self.v[instruction.y as usize],
-            0x4 => {
-                self.v[instruction.x as usize] = {
-                    self.v[instruction.x as usize]
-                        .checked_add(self.v[instruction.y as usize])
-                        .unwrap_or_else(|| {
-                            self.v[0xF] = 1;
-                            self.v[instruction.x as usize] + self.v[instruction.y as usize]
-                        })
-                }
-            }
-            0x5 => {
-                self.v[instruction.x as usize] = {
-                    self.v[0xF] = 1;
-                    self.v[instruction.x as usize]
-                        .checked_sub(self.v[instruction.y as usize])
-                        .unwrap_or_else(|| {
-                            self.v[0xF] = 0;
-                            self.v[instruction.x as usize] - self.v[instruction.y as usize]
-                        })
-                }
-            }
-            0x6 => {
-                #[cfg(not(feature = "shift"))]
-                {
-                    self.v[instruction.x as usize] = self.v[instruction.y as usize];
-                }
-                self.v[0xF] = self.v[instruction.x as usize] & 1;
-                self.v[instruction.x as usize] >>= 1;
-            }
-            0x7 => {
-                self.v[instruction.x as usize] = {
-                    self.v[0xF] = 1;
-                    self.v[instruction.y as usize]
-                        .checked_sub(self.v[instruction.x as usize])
-                        .unwrap_or_else(|| {
-                            self.v[0xF] = 0;
-                            self.v[instruction.y as usize] - self.v[instruction.x as usize]
-                        })
-                }
-            }
-            0xE => {
-                #[cfg(not(feature = "shift"))]
-                {
-                    self.v[instruction.x as usize] = self.v[instruction.y as usize];
-                }
-                self.v[0xF] = self.v[instruction.x as usize] & (1 << 7);
-                self.v[instruction.x as usize] <<= 1;
-            }
-            _ => eprintln!("UNKNOWN 8"),
+
+    /// Puts the chip back in its power-on state with the last loaded rom in
+    /// memory. The display buffer is owned by the caller and is not cleared.
+    pub fn reset(&mut self) {
+        self.v.fill(0);
+        self.i = 0;
+        self.stack.fill(0);
+        self.sp = 0;
+        self.st = 0;
+        self.dt = 0;
+        self.pc = self.start;
+        self.colours = Colours::default();
+        self.output = 0;
+        self.mega = Megachip::default();
+        let size = self.variant.memory_size();
+        if self.mem.len() == size {
+            self.mem.fill(0);
+        } else {
+            // Only a change of variant resizes memory, and zeroed memory from
+            // the allocator is far quicker than resizing up to 16M.
+            self.mem = vec![0; size];
         }
-        self.pc += 0x02
+        self.executed.fill(false);
+        self.decoded.fill(None);
+        self.code_generation += 1;
+        let start = self.start as usize;
+        self.mem[start..start + self.rom.len()].copy_from_slice(&self.rom);
     }
 
-    fn f_inst(&mut self, instruction: Instruction, window: &Window) {
-        match instruction.nn {
-            0x1E => {
-                self.adi(instruction);
-                return;
-            }
-            0x07 => self.v[instruction.x as usize] = self.dt,
-            0x15 => self.dt = self.v[instruction.x as usize],
-            0x18 => self.st = self.v[instruction.x as usize],
-            0x0A => {
-                if let Some(key) = window.get_keys().first() {
-                    self.v[instruction.x as usize] = Keypad(*key).into();
-                } else {
-                    self.pc -= 0x02;
-                }
-            }
-            0x55 => (0..=instruction.x)
-                .for_each(|x| self.mem[(self.i + x as u16) as usize] = self.v[x as usize]),
-            0x65 => (0..=instruction.x)
-                .for_each(|x| self.v[x as usize] = self.mem[(self.i + x as u16) as usize]),
-            _ => todo!(),
+    pub fn step(&mut self, buffer: &mut [u32], platform: &dyn Platform) -> Result<(), Fault> {
+        if self.trace {
+            println!("{}", self);
         }
-        self.pc += 0x02
+        let pc = self.pc as usize;
+        if pc + 1 >= MEM_SIZE {
+            return Err(Fault::BadPc { pc: self.pc });
+        }
+        if self.trace {
+            dump::decode(
+                &Instruction::new(&self.mem[pc..pc + 2]),
+                self.pc,
+                self.variant,
+            );
+        }
+        if let Some(profiler) = &mut self.profiler {
+            profiler.record(
+                self.pc,
+                u16::from_be_bytes([self.mem[pc], self.mem[pc + 1]]),
+            );
+        }
+        if let Some(coverage) = &mut self.coverage {
+            coverage.execute(self.pc);
+        }
+        let op = self.fetch(self.pc)?;
+        self.execute(op, buffer, platform)
     }
 
-    fn rndmsk(&mut self, instruction: Instruction) {
-        self.v[instruction.x as usize] = fastrand::u8(..) & instruction.nn;
-        self.pc += 0x02
+    /// The instruction at `pc`, decoded through the cache. Its bytes count as
+    /// code from now on, so writing to them is reported.
+    pub fn fetch(&mut self, pc: u16) -> Result<Op, Fault> {
+        let op = self.decode(pc)?;
+        self.mark_executed(pc);
+        Ok(op)
     }
 
-    fn mvi(&mut self, instruction: Instruction) {
-        match instruction.f_nibble {
-            0x6 => self.v[instruction.x as usize] = instruction.nn,
-            0xA => self.i = instruction.nnn,
-            _ => eprintln!("UNKNOWN MVI"),
+    /// The instruction at `pc`, decoded through the cache without counting as
+    /// run, for translating code before it runs.
+    pub fn decode(&mut self, pc: u16) -> Result<Op, Fault> {
+        let pc = pc as usize;
+        if pc + 1 >= MEM_SIZE {
+            return Err(Fault::BadPc { pc: pc as u16 });
         }
-        self.pc += 0x02;
+        Ok(*self.decoded[pc].get_or_insert_with(|| {
+            let opcode = u16::from_be_bytes([self.mem[pc], self.mem[pc + 1]]);
+            Op::decode_at(opcode, self.variant, pc as u16)
+        }))
     }
 
-    fn adi(&mut self, instruction: Instruction) {
-        match instruction.f_nibble {
-            0x7 => self.v[instruction.x as usize] += instruction.nn,
-            0xF => self.i += self.v[instruction.x as usize] as u16,
-            _ => eprintln!("UNKNOWN ADI"),
+    /// Counts the instruction at `pc` as run, as `fetch` does.
+    pub fn mark_executed(&mut self, pc: u16) {
+        let pc = pc as usize;
+        if pc + 1 < MEM_SIZE {
+            self.executed[pc] = true;
+            self.executed[pc + 1] = true;
         }
-        self.pc += 0x02;
     }
 
-    fn draw(&mut self, instruction: Instruction, buffer: &mut [u32]) {
-        let x = self.v[instruction.x as usize] & 63;
-        let y = self.v[instruction.y as usize] & 31;
-        self.v[0xF] = 0;
-        let n = instruction.l_nibble;
-
-        for row in 0..n {
-            let sprite_data = self.mem[self.i as usize + row as usize];
-            for col in 0..8 {
-                let sprite_pixel = (sprite_data >> (7 - col)) & 1;
-                let x = (x + col) & 63;
-                let y = (y + row) & 31;
-
-                let index = (y as usize) * 64 + x as usize;
-                let screen_pixel = &mut buffer[index];
+    /// Changes whenever memory that was fetched as code may have been
+    /// rewritten, so translations of it can be thrown away.
+    pub fn code_generation(&self) -> u64 {
+        self.code_generation
+    }
 
-                if sprite_pixel == 1 {
-                    if *screen_pixel == from_u8_rgb(255, 255, 255) {
-                        self.v[0xF] = 1;
-                        *screen_pixel = 0u32;
-                    } else {
-                        *screen_pixel = from_u8_rgb(255, 255, 255)
-                    }
-                }
-            }
+    /// Runs `instruction` as if it were at PC, without caching it.
+    pub fn interpret(
+        &mut self,
+        instruction: Instruction,
+        buffer: &mut [u32],
+        platform: &dyn Platform,
+    ) -> Result<(), Fault> {
+        if self.trace {
+            dump::decode(&instruction, self.pc, self.variant);
         }
-
-        self.pc += 0x02;
+        self.execute(
+            Op::decode_at(instruction.opcode, self.variant, self.pc),
+            buffer,
+            platform,
+        )
     }
 
-    fn skip_eq(&mut self, instruction: Instruction) {
-        match instruction.f_nibble {
-            0x3 => {
-                if self.v[instruction.x as usize] == instruction.nn {
+    pub fn execute(
+        &mut self,
+        op: Op,
+        buffer: &mut [u32],
+        platform: &dyn Platform,
+    ) -> Result<(), Fault> {
+        match op {
+            Op::Cls if self.mega.enabled => self.mega.present(buffer),
+            Op::Cls => buffer.fill(0u32),
+            Op::Ret => {
+                if self.sp == 0 {
+                    return Err(Fault::StackUnderflow { pc: self.pc });
+                }
+                self.pc = self.stack[self.sp - 1];
+                self.stack[self.sp - 1] = 0u16;
+                self.sp -= 1;
+                return Ok(());
+            }
+            // Machine code routines cannot run here, so they are skipped.
+            Op::Sys(_) => {
+                if self.trace {
+                    println!("UNKNOWN 0");
+                }
+            }
+            Op::Jump(nnn) => {
+                self.pc = nnn;
+                return Ok(());
+            }
+            Op::Call(nnn) => {
+                if self.sp == self.stack.len() {
+                    return Err(Fault::StackOverflow { pc: self.pc });
+                }
+                self.stack[self.sp] = self.pc + 0x2;
+                self.sp += 1;
+                self.pc = nnn;
+                return Ok(());
+            }
+            Op::JumpOffset { x, nnn } => {
+                let offset = if self.quirks.jumping {
+                    self.v[x]
+                } else {
+                    self.v[0]
+                };
+                self.pc = nnn + offset as u16;
+                return Ok(());
+            }
+            Op::SkipEqImm { x, nn } => {
+                if self.v[x] == nn {
                     self.pc += 0x02
                 }
             }
-            0x5 => {
-                if self.v[instruction.x as usize] == self.v[instruction.y as usize] {
+            Op::SkipNeImm { x, nn } => {
+                if self.v[x] != nn {
                     self.pc += 0x02
                 }
             }
-            _ => eprintln!("UNKNOWN SKIP.EQ"),
-        }
-        self.pc += 0x02
-    }
-
-    fn skip_ne(&mut self, instruction: Instruction) {
-        match instruction.f_nibble {
-            0x4 => {
-                if self.v[instruction.x as usize] != instruction.nn {
+            Op::SkipEq { x, y } => {
+                if self.v[x] == self.v[y] {
                     self.pc += 0x02
                 }
             }
-            0x9 => {
-                if self.v[instruction.x as usize] != self.v[instruction.y as usize] {
+            Op::SkipNe { x, y } => {
+                if self.v[x] != self.v[y] {
                     self.pc += 0x02
                 }
             }
-            _ => eprintln!("UNKNOWN SKIP.NE"),
-        }
-        self.pc += 0x02
-    }
-
-    fn skipkey(&mut self, instruction: Instruction, window: &Window) {
-        let keypad: Keypad = self.v[instruction.x as usize].into();
-        match instruction.nn {
-            0x9E => {
-                if window.is_key_down(keypad.0) {
+            Op::LoadImm { x, nn } => self.v[x] = nn,
+            Op::AddImm { x, nn } => self.v[x] = self.v[x].wrapping_add(nn),
+            Op::Move { x, y } => self.v[x] = self.v[y],
+            Op::Or { x, y } => {
+                self.v[x] |= self.v[y];
+                if self.quirks.vf_reset {
+                    self.v[0xF] = 0;
+                }
+            }
+            Op::And { x, y } => {
+                self.v[x] &= self.v[y];
+                if self.quirks.vf_reset {
+                    self.v[0xF] = 0;
+                }
+            }
+            Op::Xor { x, y } => {
+                self.v[x] ^= self.v[y];
+                if self.quirks.vf_reset {
+                    self.v[0xF] = 0;
+                }
+            }
+            // VF is written after the result, so it holds the flag even when
+            // it is also the destination.
+            Op::Add { x, y } => {
+                let (sum, carry) = self.v[x].overflowing_add(self.v[y]);
+                self.v[x] = sum;
+                self.v[0xF] = carry as u8;
+            }
+            Op::Sub { x, y } => {
+                let (difference, borrow) = self.v[x].overflowing_sub(self.v[y]);
+                self.v[x] = difference;
+                self.v[0xF] = !borrow as u8;
+            }
+            Op::SubReverse { x, y } => {
+                let (difference, borrow) = self.v[y].overflowing_sub(self.v[x]);
+                self.v[x] = difference;
+                self.v[0xF] = !borrow as u8;
+            }
+            Op::ShiftRight { x, y } => {
+                let value = if self.quirks.shift {
+                    self.v[x]
+                } else {
+                    self.v[y]
+                };
+                self.v[x] = value >> 1;
+                self.v[0xF] = value & 1;
+            }
+            Op::ShiftLeft { x, y } => {
+                let value = if self.quirks.shift {
+                    self.v[x]
+                } else {
+                    self.v[y]
+                };
+                self.v[x] = value << 1;
+                self.v[0xF] = value >> 7;
+            }
+            Op::LoadI(nnn) => self.i = nnn as u32,
+            Op::Random { x, nn } => self.v[x] = self.rng.u8(..) & nn,
+            Op::Draw { x, y, .. } if self.mega.enabled => {
+                let len = self.mega.sprite_len();
+                self.check_memory(self.i, len)?;
+                if let Some(coverage) = &mut self.coverage {
+                    coverage.read(self.i, len);
+                }
+                let i = self.i as usize;
+                let collision = self.mega.draw(self.v[x], self.v[y], &self.mem[i..i + len]);
+                self.v[0xF] = collision as u8;
+            }
+            Op::Draw { x, y, n } => {
+                self.check_memory(self.i, n as usize)?;
+                self.draw(x, y, n, buffer);
+            }
+            Op::SkipKey(x) => {
+                if platform.is_key_down(self.v[x]) {
+                    self.pc += 0x02
+                }
+            }
+            Op::SkipNoKey(x) => {
+                let key = self.v[x];
+                if !platform.is_key_down(key) {
+                    if self.trace {
+                        println!("Not pressed {}", key);
+                    }
+                    self.pc += 0x02
+                }
+            }
+            Op::GetDelay(x) => self.v[x] = self.dt,
+            Op::WaitKey(x) => match platform.pressed_key() {
+                Some(key) => self.v[x] = key,
+                None => return Ok(()),
+            },
+            Op::CycleBackground => self.colours.cycle_background(),
+            Op::AddColour { x, y } => {
+                self.v[x] = ((self.v[x] & 0x77) + (self.v[y] & 0x77)) & 0x77;
+            }
+            Op::SetColour { x, y, n } => {
+                let vertical = self.v[(x + 1) & 0xF];
+                self.colours.fill(self.v[x], vertical, n, self.v[y]);
+            }
+            Op::SkipKey2(x) => {
+                if platform.is_second_key_down(self.v[x]) {
                     self.pc += 0x02
                 }
             }
-            0xA1 => {
-                if !window.is_key_down(keypad.0) {
-                    println!("Not pressed {}", self.v[instruction.x as usize]);
+            Op::SkipNoKey2(x) => {
+                if !platform.is_second_key_down(self.v[x]) {
                     self.pc += 0x02
                 }
             }
-            _ => eprintln!("UKNOWN E"),
+            Op::Output(x) => self.output = self.v[x],
+            Op::Input(x) => match platform.read_port() {
+                Some(value) => self.v[x] = value,
+                None => return Ok(()),
+            },
+            Op::MegaOff => self.mega.enabled = false,
+            Op::MegaOn => self.mega.enable(),
+            Op::LoadLongI(nn) => {
+                let pc = self.pc as usize;
+                if pc + 3 >= MEM_SIZE {
+                    return Err(Fault::BadPc { pc: self.pc });
+                }
+                let low = u16::from_be_bytes([self.mem[pc + 2], self.mem[pc + 3]]);
+                self.i = (nn as u32) << 16 | low as u32;
+                self.pc += 0x04;
+                return Ok(());
+            }
+            Op::Palette(nn) => {
+                let len = nn as usize * 4;
+                self.check_memory(self.i, len)?;
+                let i = self.i as usize;
+                self.mega.load_palette(&self.mem[i..i + len]);
+            }
+            Op::SpriteWidth(nn) => self.mega.set_sprite_size(Some(nn), None),
+            Op::SpriteHeight(nn) => self.mega.set_sprite_size(None, Some(nn)),
+            Op::ScreenAlpha(nn) => self.mega.set_alpha(nn),
+            Op::PlaySample(n) => {
+                self.check_memory(self.i, 6)?;
+                let i = self.i as usize;
+                let sample = Sample::new(&self.mem[i..i + 6], self.i + 6, n == 0);
+                self.check_memory(sample.start, sample.len as usize)?;
+                self.mega.sample = Some(sample);
+            }
+            Op::StopSample => self.mega.sample = None,
+            Op::Blend(n) => self.mega.set_blend(n),
+            Op::CollisionColour(nn) => self.mega.set_collision(nn),
+            Op::SetDelay(x) => self.dt = self.v[x],
+            Op::SetSound(x) => self.st = self.v[x],
+            Op::AddI(x) => self.i = self.i.wrapping_add(self.v[x] as u32),
+            Op::Bcd(x) => {
+                self.check_memory(self.i, 3)?;
+                let (value, i) = (self.v[x], self.i as usize);
+                self.store(i, value / 100);
+                self.store(i + 1, value / 10 % 10);
+                self.store(i + 2, value % 10);
+                if let Some(coverage) = &mut self.coverage {
+                    coverage.write(self.i, 3);
+                }
+            }
+            Op::Store(x) => {
+                self.check_memory(self.i, x + 1)?;
+                for n in 0..=x {
+                    self.store(self.i as usize + n, self.v[n]);
+                }
+                if let Some(coverage) = &mut self.coverage {
+                    coverage.write(self.i, x + 1);
+                }
+                if self.quirks.memory {
+                    self.i = self.i.wrapping_add(x as u32 + 1);
+                }
+            }
+            Op::Load(x) => {
+                self.check_memory(self.i, x + 1)?;
+                for n in 0..=x {
+                    self.v[n] = self.mem[self.i as usize + n];
+                }
+                if let Some(coverage) = &mut self.coverage {
+                    coverage.read(self.i, x + 1);
+                }
+                if self.quirks.memory {
+                    self.i = self.i.wrapping_add(x as u32 + 1);
+                }
+            }
+            Op::Unknown(_) => {
+                if self.trace {
+                    println!("Opcode not implemented yet!.");
+                }
+            }
         }
-        self.pc += 0x02
+        self.pc += 0x02;
+        Ok(())
     }
 
-    fn call(&mut self, instruction: Instruction) {
-        self.stack[self.sp] = self.pc + 0x2;
-        self.sp += 1;
-        self.pc = instruction.nnn;
+    fn check_memory(&self, address: u32, len: usize) -> Result<(), Fault> {
+        if address as usize + len > self.mem.len() {
+            return Err(Fault::OutOfMemory {
+                pc: self.pc,
+                address: address as usize + len - 1,
+            });
+        }
+        Ok(())
     }
 
-    fn rts(&mut self) {
-        self.pc = self.stack[self.sp - 1];
-        self.stack[self.sp - 1] = 0u16;
-        self.sp -= 1;
+    /// Writes the sound of the next 1/60 s into `out`, which `audio::Speaker`
+    /// fills with silence first: the MegaChip sample that is playing, if any.
+    pub fn audio(&mut self, out: &mut [u8]) {
+        if let Some(sample) = &mut self.mega.sample {
+            if !sample.play(&self.mem, out) {
+                self.mega.sample = None;
+            }
+        }
     }
 
-    fn not_implemented(&mut self) {
-        println!("Opcode not implemented yet!.");
-        self.pc += 0x02;
+    /// Counts the delay and sound timers down, at 60 Hz.
+    pub fn tick_timers(&mut self) {
+        if self.dt > 0 {
+            self.dt -= 1
+        }
+        if self.st > 0 {
+            self.st -= 1
+        }
     }
-}
 
-pub struct Keypad(Key);
-
-impl From<u8> for Keypad {
-    fn from(value: u8) -> Self {
-        match value {
-            0x0 => Keypad(Key::Key1),
-            0x1 => Keypad(Key::Key2),
-            0x2 => Keypad(Key::Key3),
-            0x3 => Keypad(Key::Key4),
-            0x4 => Keypad(Key::Q),
-            0x5 => Keypad(Key::W),
-            0x6 => Keypad(Key::E),
-            0x7 => Keypad(Key::R),
-            0x8 => Keypad(Key::A),
-            0x9 => Keypad(Key::S),
-            0xA => Keypad(Key::D),
-            0xB => Keypad(Key::F),
-            0xC => Keypad(Key::Z),
-            0xD => Keypad(Key::X),
-            0xE => Keypad(Key::C),
-            0xF => Keypad(Key::V),
-            _ => panic!("can't convert illegal key {}", value),
+    /// Runs one 60 Hz frame of `cycles` instructions.
+    pub fn frame(
+        &mut self,
+        cycles: usize,
+        buffer: &mut [u32],
+        platform: &dyn Platform,
+    ) -> Result<(), Fault> {
+        for _ in 0..cycles {
+            self.step(buffer, platform)?;
         }
+        self.tick_timers();
+        Ok(())
     }
-}
 
-impl From<Keypad> for u8 {
-    fn from(value: Keypad) -> Self {
-        match value.0 {
-            Key::Key1 => 0x0,
-            Key::Key2 => 0x1,
-            Key::Key3 => 0x2,
-            Key::Key4 => 0x3,
-            Key::Q => 0x4,
-            Key::W => 0x5,
-            Key::E => 0x6,
-            Key::R => 0x7,
-            Key::A => 0x8,
-            Key::S => 0x9,
-            Key::D => 0xA,
-            Key::F => 0xB,
-            Key::Z => 0xC,
-            Key::X => 0xD,
-            Key::C => 0xE,
-            Key::V => 0xF,
-            _ => panic!("can't convert illegal key {:#?}", value.0),
+    /// Writes `bytes` at `address` from outside the program, e.g. a debugger.
+    /// Writing to `mem` directly would leave stale decoded instructions behind.
+    pub fn write(&mut self, address: usize, bytes: &[u8]) {
+        self.mem[address..address + bytes.len()].copy_from_slice(bytes);
+        self.invalidate(address, bytes.len());
+        self.code_generation += 1;
+    }
+
+    // An instruction starting the byte before `start` overlaps it too. True
+    // if any of them had been decoded.
+    fn invalidate(&mut self, start: usize, len: usize) -> bool {
+        let start = start.saturating_sub(1).min(MEM_SIZE);
+        let end = (start + len + 1).min(MEM_SIZE);
+        let decoded = self.decoded[start..end].iter().any(Option::is_some);
+        self.decoded[start..end].fill(None);
+        decoded
+    }
+
+    /// Every write to memory by an instruction goes through here, so code that
+    /// rewrites itself is noticed.
+    fn store(&mut self, index: usize, value: u8) {
+        self.mem[index] = value;
+        // Code translated ahead of running it is stale too, but only code
+        // that has run counts as rewriting itself.
+        if self.invalidate(index, 1) {
+            self.code_generation += 1;
+        }
+        if index < MEM_SIZE && self.executed[index] {
+            self.code_generation += 1;
+            let write = CodeWrite {
+                pc: self.pc,
+                address: index as u16,
+            };
+            if self.trace {
+                println!(
+                    "Self-modifying code: {:04X} wrote to {:04X}",
+                    write.pc, write.address
+                );
+            }
+            if let Some(writes) = &mut self.code_writes {
+                writes.push(write);
+            }
+        }
+    }
+
+    fn draw(&mut self, x: usize, y: usize, n: u8, buffer: &mut [u32]) {
+        let (width, height) = self.variant.plane();
+        // MegaChip's lores plane is scaled up to fill its 256x192 display, as
+        // in its SCHIP-compatible mode.
+        let (stride, rows) = self.variant.size();
+        let (scale_x, scale_y) = (stride / width, rows / height);
+        let x = self.v[x] as usize % width;
+        let y = self.v[y] as usize % height;
+        self.v[0xF] = 0;
+        if let Some(coverage) = &mut self.coverage {
+            coverage.read(self.i, n as usize);
+        }
+
+        for row in 0..n as usize {
+            let sprite_data = self.mem[self.i as usize + row];
+            for col in 0..8 {
+                let sprite_pixel = (sprite_data >> (7 - col)) & 1;
+                if self.quirks.clipping && (x + col >= width || y + row >= height) {
+                    continue;
+                }
+                let x = (x + col) % width;
+                let y = (y + row) % height;
+
+                let index = y * scale_y * stride + x * scale_x;
+                if sprite_pixel == 1 {
+                    let pixel = if buffer[index] == from_u8_rgb(255, 255, 255) {
+                        self.v[0xF] = 1;
+                        0u32
+                    } else {
+                        from_u8_rgb(255, 255, 255)
+                    };
+                    for row in buffer[index..].chunks_mut(stride).take(scale_y) {
+                        row[..scale_x].fill(pixel);
+                    }
+                }
+            }
         }
     }
 }
 
 #[inline]
-fn from_u8_rgb(r: u8, g: u8, b: u8) -> u32 {
+pub(crate) fn from_u8_rgb(r: u8, g: u8, b: u8) -> u32 {
     let (r, g, b) = (r as u32, g as u32, b as u32);
     (r << 16) | (g << 8) | b
 }
@@ -384,18 +766,20 @@ fn from_u8_rgb(r: u8, g: u8, b: u8) -> u32 {
 #[cfg(test)]
 mod tests {
 
-    use super::{Chip, Instruction};
-    use minifb::{Window, WindowOptions};
+    use super::{Chip, CodeWrite, Fault, Instruction, LoadError, Variant};
+    use crate::{platform::Headless, quirks::Profile};
 
     #[test]
     fn test_jump() {
         let mut chip8 = Chip::new();
         let mut buffer = vec![0u32, 64 * 32];
-        chip8.interpret(
-            Instruction::new(&[0x12, 0x28]),
-            &mut buffer,
-            &Window::new("Test", 1, 1, WindowOptions::default()).unwrap(),
-        );
+        chip8
+            .interpret(
+                Instruction::new(&[0x12, 0x28]),
+                &mut buffer,
+                &Headless::default(),
+            )
+            .unwrap();
 
         assert_eq!(chip8.pc, 0x228);
     }
@@ -405,11 +789,13 @@ mod tests {
         let mut chip8 = Chip::new();
 
         let mut buffer = vec![0u32, 64 * 32];
-        chip8.interpret(
-            Instruction::new(&[0x60, 0x0C]),
-            &mut buffer,
-            &Window::new("Test", 1, 1, WindowOptions::default()).unwrap(),
-        );
+        chip8
+            .interpret(
+                Instruction::new(&[0x60, 0x0C]),
+                &mut buffer,
+                &Headless::default(),
+            )
+            .unwrap();
 
         assert_eq!(chip8.v[0], 0x0C);
     }
@@ -419,11 +805,13 @@ mod tests {
         let mut chip8 = Chip::new();
 
         let mut buffer = vec![0u32, 64 * 32];
-        chip8.interpret(
-            Instruction::new(&[0xA2, 0x2A]),
-            &mut buffer,
-            &Window::new("Test", 1, 1, WindowOptions::default()).unwrap(),
-        );
+        chip8
+            .interpret(
+                Instruction::new(&[0xA2, 0x2A]),
+                &mut buffer,
+                &Headless::default(),
+            )
+            .unwrap();
 
         assert_eq!(chip8.i, 0x22A);
     }
@@ -433,12 +821,434 @@ mod tests {
         let mut chip8 = Chip::new();
 
         let mut buffer = vec![0u32, 64 * 32];
-        chip8.interpret(
-            Instruction::new(&[0x70, 0x09]),
-            &mut buffer,
-            &Window::new("Test", 1, 1, WindowOptions::default()).unwrap(),
-        );
+        chip8
+            .interpret(
+                Instruction::new(&[0x70, 0x09]),
+                &mut buffer,
+                &Headless::default(),
+            )
+            .unwrap();
 
         assert_eq!(chip8.v[0], 0x09);
     }
+
+    #[test]
+    fn test_quirk_vf_reset() {
+        let mut chip8 = Chip::new();
+        chip8.quirks = Profile::Chip8.quirks();
+        chip8.v[0xF] = 1;
+
+        let mut buffer = vec![0u32, 64 * 32];
+        chip8
+            .interpret(
+                Instruction::new(&[0x80, 0x11]),
+                &mut buffer,
+                &Headless::default(),
+            )
+            .unwrap();
+
+        assert_eq!(chip8.v[0xF], 0);
+    }
+
+    #[test]
+    fn test_quirk_memory() {
+        let mut chip8 = Chip::new();
+        chip8.quirks = Profile::Chip8.quirks();
+        chip8.i = 0x300;
+
+        let mut buffer = vec![0u32, 64 * 32];
+        chip8
+            .interpret(
+                Instruction::new(&[0xF3, 0x55]),
+                &mut buffer,
+                &Headless::default(),
+            )
+            .unwrap();
+
+        assert_eq!(chip8.i, 0x304);
+    }
+
+    #[test]
+    fn test_quirk_jumping() {
+        let mut chip8 = Chip::new();
+        chip8.quirks = Profile::Schip.quirks();
+        chip8.v[0] = 0x10;
+        chip8.v[2] = 0x04;
+
+        let mut buffer = vec![0u32, 64 * 32];
+        chip8
+            .interpret(
+                Instruction::new(&[0xB2, 0x20]),
+                &mut buffer,
+                &Headless::default(),
+            )
+            .unwrap();
+
+        assert_eq!(chip8.pc, 0x224);
+    }
+
+    #[test]
+    fn test_load_too_large() {
+        let mut chip8 = Chip::new();
+
+        let err = chip8.load_bytes(&[0u8; 0xE01]).unwrap_err();
+
+        assert!(matches!(
+            err,
+            LoadError::TooLarge {
+                size: 0xE01,
+                max: 0xE00
+            }
+        ));
+    }
+
+    #[test]
+    fn test_load_address() {
+        let mut chip8 = Chip::new();
+        chip8.start = 0x600;
+
+        chip8.load_bytes(&[0x12, 0x34, 0x56]).unwrap();
+
+        assert_eq!(chip8.pc, 0x600);
+        assert_eq!(chip8.mem[0x600..0x603], [0x12, 0x34, 0x56]);
+        assert_eq!(chip8.mem[0x200], 0);
+    }
+
+    #[test]
+    fn test_reset() {
+        let mut chip8 = Chip::new();
+        chip8.load_bytes(&[0x60, 0x0C]).unwrap();
+
+        let mut buffer = vec![0u32, 64 * 32];
+        chip8.step(&mut buffer, &Headless::default()).unwrap();
+        chip8.mem[0x300] = 0xFF;
+        chip8.reset();
+
+        assert_eq!(chip8.v[0], 0);
+        assert_eq!(chip8.pc, 0x200);
+        assert_eq!(chip8.mem[0x300], 0);
+        assert_eq!(chip8.mem[0x200..0x202], [0x60, 0x0C]);
+    }
+
+    #[test]
+    fn test_sys_is_skipped() {
+        let mut chip8 = Chip::new();
+        chip8.load_bytes(&[0x01, 0x23]).unwrap();
+        chip8.trace = false;
+
+        let mut buffer = vec![0u32, 64 * 32];
+        chip8.step(&mut buffer, &Headless::default()).unwrap();
+
+        assert_eq!(chip8.pc, 0x202);
+    }
+
+    #[test]
+    fn test_reset_keeps_memory() {
+        let mut chip8 = Chip::new();
+        chip8.load_bytes(&[0x60, 0x0C]).unwrap();
+        let mem = chip8.mem.as_ptr();
+
+        chip8.reset();
+
+        assert_eq!(chip8.mem.as_ptr(), mem);
+    }
+
+    #[test]
+    fn test_timers_tick_per_frame() {
+        let mut chip8 = Chip::new();
+        chip8
+            .load_bytes(&[0x60, 0x0C, 0x61, 0x0C, 0x62, 0x0C])
+            .unwrap();
+        chip8.trace = false;
+        chip8.dt = 5;
+
+        let mut buffer = vec![0u32, 64 * 32];
+        chip8.frame(3, &mut buffer, &Headless::default()).unwrap();
+
+        assert_eq!(chip8.pc, 0x206);
+        assert_eq!(chip8.dt, 4);
+    }
+
+    #[test]
+    fn test_bcd() {
+        let mut chip8 = Chip::new();
+        chip8.i = 0x300;
+        chip8.v[2] = 254;
+
+        let mut buffer = vec![0u32, 64 * 32];
+        chip8
+            .interpret(
+                Instruction::new(&[0xF2, 0x33]),
+                &mut buffer,
+                &Headless::default(),
+            )
+            .unwrap();
+
+        assert_eq!(chip8.mem[0x300..0x303], [2, 5, 4]);
+        assert_eq!(chip8.pc, 0x202);
+    }
+
+    #[test]
+    fn test_code_write() {
+        let mut chip8 = Chip::new();
+        chip8.trace = false;
+        chip8.code_writes = Some(Vec::new());
+        // MVI I, $200; MOVM (I), V0-V0
+        chip8
+            .load_bytes(&[0xA2, 0x00, 0xF0, 0x55, 0xA3, 0x00, 0xF0, 0x55])
+            .unwrap();
+
+        let mut buffer = vec![0u32, 64 * 32];
+        (0..4).for_each(|_| chip8.step(&mut buffer, &Headless::default()).unwrap());
+
+        assert_eq!(
+            chip8.code_writes,
+            Some(vec![CodeWrite {
+                pc: 0x202,
+                address: 0x200
+            }])
+        );
+    }
+
+    #[test]
+    fn test_decoded_cache_invalidated() {
+        let mut chip8 = Chip::new();
+        chip8.trace = false;
+        chip8
+            .load_bytes(&[0x61, 0x0A, 0xA2, 0x01, 0x60, 0x42, 0xF0, 0x55, 0x12, 0x00])
+            .unwrap();
+        let mut buffer = [0u32; 64 * 32];
+        let platform = Headless::default();
+
+        (0..6).for_each(|_| chip8.step(&mut buffer, &platform).unwrap());
+        assert_eq!(chip8.v[1], 0x42);
+
+        chip8.write(0x205, &[0x07]);
+        (0..5).for_each(|_| chip8.step(&mut buffer, &platform).unwrap());
+        assert_eq!(chip8.v[1], 0x07);
+    }
+
+    #[test]
+    fn test_faults() {
+        let run = |rom: &[u8], i: u32| {
+            let mut chip8 = Chip::new();
+            chip8.trace = false;
+            chip8.load_bytes(rom).unwrap();
+            chip8.i = i;
+            let mut buffer = [0u32; 64 * 32];
+            let fault = (0..20)
+                .find_map(|_| chip8.step(&mut buffer, &Headless::default()).err())
+                .unwrap();
+            (fault, chip8.pc)
+        };
+
+        assert_eq!(
+            run(&[0x22, 0x00], 0),
+            (Fault::StackOverflow { pc: 0x200 }, 0x200)
+        );
+        assert_eq!(
+            run(&[0x00, 0xEE], 0),
+            (Fault::StackUnderflow { pc: 0x200 }, 0x200)
+        );
+        assert_eq!(
+            run(&[0xFF, 0x65], 0xFF8),
+            (
+                Fault::OutOfMemory {
+                    pc: 0x200,
+                    address: 0x1007
+                },
+                0x200
+            )
+        );
+        assert_eq!(
+            run(&[0x60, 0x10, 0xBF, 0xF0], 0),
+            (Fault::BadPc { pc: 0x1000 }, 0x1000)
+        );
+    }
+
+    #[test]
+    fn test_chip8x() {
+        let mut chip8 = Chip::new();
+        chip8.trace = false;
+        chip8.variant = Variant::Chip8x;
+        chip8.start = Variant::Chip8x.start();
+        // V0 = 0x35, V1 = 0x42, 5011, F0F8, E3F2 (not taken), E3F2 (taken),
+        // 1000 skipped, F2FB.
+        chip8
+            .load_bytes(&[
+                0x60, 0x35, 0x61, 0x42, 0x50, 0x11, 0xF0, 0xF8, 0xE3, 0xF2, 0xE3, 0xF2, 0x10, 0x00,
+                0xF2, 0xFB,
+            ])
+            .unwrap();
+        let mut buffer = [0u32; 64 * 32];
+        let mut platform = Headless::default();
+
+        for _ in 0..5 {
+            chip8.step(&mut buffer, &platform).unwrap();
+        }
+        assert_eq!(chip8.v[0], 0x77);
+        assert_eq!(chip8.output, 0x77);
+        assert_eq!(chip8.pc, 0x30A);
+
+        platform.second_keys[0] = true;
+        chip8.step(&mut buffer, &platform).unwrap();
+        assert_eq!(chip8.pc, 0x30E);
+
+        chip8.step(&mut buffer, &platform).unwrap();
+        assert_eq!(chip8.pc, 0x30E);
+        platform.port = Some(0x99);
+        chip8.step(&mut buffer, &platform).unwrap();
+        assert_eq!((chip8.v[2], chip8.pc), (0x99, 0x310));
+    }
+
+    #[test]
+    fn test_hires() {
+        let mut chip8 = Chip::new();
+        chip8.trace = false;
+        let mut rom = vec![0u8; 0xD0];
+        rom[..2].copy_from_slice(&[0x12, 0x60]);
+        // V1 = 40, I = 0x2CC, draw 2 rows at (0, 40), then at (0, 63).
+        rom[0xC0..0xCE].copy_from_slice(&[
+            0x61, 0x28, 0xA2, 0xCC, 0xD0, 0x12, 0x61, 0x3F, 0xD0, 0x12, 0x00, 0x00, 0x80, 0x80,
+        ]);
+        chip8.load_bytes(&rom).unwrap();
+        assert_eq!(chip8.variant, Variant::Hires);
+
+        let (width, height) = chip8.variant.size();
+        let mut buffer = vec![0u32; width * height];
+        for _ in 0..6 {
+            chip8.step(&mut buffer, &Headless::default()).unwrap();
+        }
+
+        let lit: Vec<usize> = (0..buffer.len()).filter(|&p| buffer[p] != 0).collect();
+        assert_eq!(lit, [0, 40 * 64, 41 * 64, 63 * 64]);
+    }
+
+    #[test]
+    fn test_hires_only_at_start() {
+        let mut chip8 = Chip::new();
+        chip8.trace = false;
+        let mut rom = vec![0u8; 0xC2];
+        rom[..2].copy_from_slice(&[0x12, 0x60]);
+        rom[0xC0..].copy_from_slice(&[0x12, 0x60]);
+        chip8.load_bytes(&rom).unwrap();
+        let mut buffer = vec![0u32; 64 * 64];
+        for _ in 0..2 {
+            chip8.step(&mut buffer, &Headless::default()).unwrap();
+        }
+        assert_eq!(chip8.pc, 0x260);
+
+        chip8.load_bytes(&[0x00, 0xE0]).unwrap();
+        assert_eq!(chip8.variant, Variant::Chip8);
+        chip8.variant = Variant::Hires;
+        chip8.load_bytes(&[0x00, 0xE0]).unwrap();
+        assert_eq!(chip8.variant, Variant::Hires);
+    }
+
+    #[test]
+    fn test_megachip() {
+        let mut chip8 = Chip::new();
+        chip8.trace = false;
+        chip8.variant = Variant::Megachip;
+        let mut rom = vec![0u8; 0x25];
+        rom[..0x18].copy_from_slice(&[
+            0x60, 0x42, // V0 = 0x42
+            0x01, 0x12, 0x34, 0x56, // I = 0x123456
+            0xF0, 0x55, // store V0 at I
+            0x00, 0x11, // MegaChip on
+            0x03, 0x01, 0x04, 0x01, // 1x1 sprites
+            0xA2, 0x20, 0x02, 0x01, // one palette entry from 0x220
+            0xA2, 0x24, 0xD0, 0x00, // draw the sprite at 0x224 at (V0, V0)
+            0x00, 0xE0, // show it
+        ]);
+        rom[0x20..].copy_from_slice(&[0xFF, 0x12, 0x34, 0x56, 0x01]);
+        chip8.load_bytes(&rom).unwrap();
+        assert_eq!(chip8.mem.len(), 0x100_0000);
+
+        let (width, height) = chip8.variant.size();
+        let mut buffer = vec![0u32; width * height];
+        for _ in 0..3 {
+            chip8.step(&mut buffer, &Headless::default()).unwrap();
+        }
+        assert_eq!((chip8.i, chip8.pc), (0x123456, 0x208));
+        assert_eq!(chip8.mem[0x123456], 0x42);
+
+        for _ in 0..9 {
+            chip8.step(&mut buffer, &Headless::default()).unwrap();
+        }
+        let lit: Vec<usize> = (0..buffer.len()).filter(|&p| buffer[p] != 0).collect();
+        assert_eq!(lit, [0x42 * 256 + 0x42]);
+        assert_eq!(buffer[0x42 * 256 + 0x42], 0x123456);
+    }
+
+    #[test]
+    fn test_megachip_lores_fills_the_display() {
+        let mut chip8 = Chip::new();
+        chip8.trace = false;
+        chip8.variant = Variant::Megachip;
+        // V0 = 1, I = 0x20A, draw one row at (1, 1) twice.
+        chip8
+            .load_bytes(&[
+                0x60, 0x01, 0xA2, 0x0A, 0xD0, 0x01, 0xD0, 0x01, 0x12, 0x08, 0x80,
+            ])
+            .unwrap();
+        let mut buffer = vec![0u32; 256 * 192];
+        for _ in 0..3 {
+            chip8.step(&mut buffer, &Headless::default()).unwrap();
+        }
+
+        let lit: Vec<(usize, usize)> = (0..buffer.len())
+            .filter(|&p| buffer[p] != 0)
+            .map(|p| (p % 256, p / 256))
+            .collect();
+        let block: Vec<(usize, usize)> =
+            (6..12).flat_map(|y| (4..8).map(move |x| (x, y))).collect();
+        assert_eq!(lit, block);
+
+        chip8.step(&mut buffer, &Headless::default()).unwrap();
+        assert!(buffer.iter().all(|&p| p == 0));
+        assert_eq!(chip8.v[0xF], 1);
+    }
+
+    #[test]
+    fn test_sample_past_the_end() {
+        let mut chip8 = Chip::new();
+        chip8.trace = false;
+        chip8.variant = Variant::Megachip;
+        // A header at 0xFFFFF0 claiming 256 samples, which run off the end.
+        chip8
+            .load_bytes(&[0x01, 0xFF, 0xFF, 0xF0, 0x06, 0x01])
+            .unwrap();
+        chip8.mem[0xFFFFF0..0xFFFFF6].copy_from_slice(&[0x1F, 0x40, 0x00, 0x01, 0x00, 0x00]);
+        let mut buffer = vec![0u32; 256 * 192];
+
+        chip8.step(&mut buffer, &Headless::default()).unwrap();
+        let fault = chip8.step(&mut buffer, &Headless::default());
+
+        assert!(matches!(fault, Err(Fault::OutOfMemory { pc: 0x204, .. })));
+        assert_eq!(chip8.mega.sample, None);
+        assert_eq!(chip8.pc, 0x204);
+    }
+
+    #[test]
+    fn test_sample_plays_to_its_end() {
+        let mut chip8 = Chip::new();
+        chip8.trace = false;
+        chip8.variant = Variant::Megachip;
+        // I := 0x206, play once: 2 samples at 44100 Hz.
+        chip8
+            .load_bytes(&[
+                0xA2, 0x06, 0x06, 0x01, 0x12, 0x04, 0xAC, 0x44, 0x00, 0x00, 0x02, 0x00, 0x10, 0x20,
+            ])
+            .unwrap();
+        let mut buffer = vec![0u32; 256 * 192];
+        chip8.step(&mut buffer, &Headless::default()).unwrap();
+        chip8.step(&mut buffer, &Headless::default()).unwrap();
+
+        let mut out = [0x80; 4];
+        chip8.audio(&mut out);
+
+        assert_eq!(out, [0x10, 0x20, 0x80, 0x80]);
+        assert_eq!(chip8.mega.sample, None);
+    }
 }
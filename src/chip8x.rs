@@ -0,0 +1,103 @@
+// Foreground colours, by the 3-bit number CHIP-8X programs use: black, red,
+// blue, violet, green, yellow, aqua and white.
+const PALETTE: [u32; 8] = [
+    0x000000, 0xFF0000, 0x0000FF, 0xFF00FF, 0x00FF00, 0xFFFF00, 0x00FFFF, 0xFFFFFF,
+];
+// The background colours 02A0 steps through: blue, black, green and red.
+const BACKGROUNDS: [u32; 4] = [0x0000FF, 0x000000, 0x00FF00, 0xFF0000];
+const COLUMNS: usize = 8;
+const ROWS: usize = 32;
+// BXY0 colours zones four rows high.
+const ZONE_ROWS: usize = 4;
+const RED: u8 = 1;
+
+/// The VP-590 colour board: a background colour for the whole screen and a
+/// foreground colour for each row of each 8 pixel wide column.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Colours {
+    background: usize,
+    foreground: [u8; COLUMNS * ROWS],
+}
+
+impl Default for Colours {
+    fn default() -> Self {
+        Colours {
+            background: 0,
+            foreground: [RED; COLUMNS * ROWS],
+        }
+    }
+}
+
+impl Colours {
+    /// 02A0.
+    pub fn cycle_background(&mut self) {
+        self.background = (self.background + 1) % BACKGROUNDS.len();
+    }
+
+    /// BXYN. `horizontal` is VX, whose low nibble is the first column and
+    /// high nibble the number of columns after it. For BXY0 `vertical` is
+    /// VX+1, zones of four rows encoded the same way; otherwise it is the
+    /// first of `n` rows.
+    pub fn fill(&mut self, horizontal: u8, vertical: u8, n: u8, colour: u8) {
+        let rows: Vec<usize> = if n == 0 {
+            (0..=vertical as usize >> 4)
+                .flat_map(|zone| {
+                    let top = ((vertical as usize & 0xF) + zone) * ZONE_ROWS;
+                    top..top + ZONE_ROWS
+                })
+                .collect()
+        } else {
+            (0..n as usize).map(|row| vertical as usize + row).collect()
+        };
+        for row in rows {
+            for column in 0..=horizontal as usize >> 4 {
+                let column = ((horizontal as usize & 0xF) + column) % COLUMNS;
+                self.foreground[row % ROWS * COLUMNS + column] = colour & 0x7;
+            }
+        }
+    }
+
+    /// The colours to show for a 64x32 buffer of lit and unlit pixels.
+    pub fn paint(&self, buffer: &[u32]) -> Vec<u32> {
+        buffer
+            .iter()
+            .enumerate()
+            .map(|(index, &pixel)| {
+                if pixel == 0 {
+                    BACKGROUNDS[self.background]
+                } else {
+                    let (row, x) = (index / 64, index % 64);
+                    PALETTE[self.foreground[row * COLUMNS + x / 8] as usize]
+                }
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Colours, BACKGROUNDS, PALETTE};
+
+    #[test]
+    fn test_fill() {
+        let mut colours = Colours::default();
+        let mut buffer = vec![1u32; 64 * 32];
+        buffer[0] = 0;
+
+        // Columns 1-2, zones 1-2 (rows 4-11) yellow.
+        colours.fill(0x11, 0x11, 0, 5);
+        // Column 7, rows 30-32 (wrapping to 0) aqua.
+        colours.fill(0x07, 30, 3, 6);
+        colours.cycle_background();
+        let screen = colours.paint(&buffer);
+
+        assert_eq!(screen[0], BACKGROUNDS[1]);
+        assert_eq!(screen[64 + 8], PALETTE[1]);
+        assert_eq!(screen[4 * 64 + 8], PALETTE[5]);
+        assert_eq!(screen[11 * 64 + 23], PALETTE[5]);
+        assert_eq!(screen[12 * 64 + 8], PALETTE[1]);
+        assert_eq!(screen[4 * 64 + 24], PALETTE[1]);
+        assert_eq!(screen[30 * 64 + 63], PALETTE[6]);
+        assert_eq!(screen[63], PALETTE[6]);
+    }
+}
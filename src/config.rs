@@ -0,0 +1,258 @@
+use std::{
+    error::Error,
+    path::{Path, PathBuf},
+};
+
+use clap::ValueEnum;
+use toml::{Table, Value};
+
+use crate::{
+    quirks::{Profile, Quirks},
+    variant::Variant,
+};
+
+/// Settings kept for one rom, in `game.ch8.toml` next to `game.ch8` or in
+/// `game.ch8.toml` in the config directory. Anything left out is up to the
+/// command line, the rom database or the defaults.
+///
+/// ```toml
+/// variant = "chip8"
+/// quirks = "schip"            # or a table of quirks, see below
+/// ips = 700                   # or cycles_per_frame = 11
+/// palette = ["#102030", "#F0E000"]
+/// scale = 8
+///
+/// [keys]                      # CHIP-8 key = keyboard key
+/// 5 = "Up"
+/// 8 = "Down"
+/// ```
+///
+/// A quirks table starts from a profile, or the default quirks without one:
+///
+/// ```toml
+/// [quirks]
+/// profile = "chip8"
+/// shift = true
+/// ```
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct RomConfig {
+    pub variant: Option<Variant>,
+    pub quirks: Option<Quirks>,
+    /// Instructions per second.
+    pub ips: Option<u32>,
+    /// Background and foreground.
+    pub palette: Option<[u32; 2]>,
+    /// CHIP-8 keys and the names of keyboard keys bound to them.
+    pub keys: Vec<(u8, String)>,
+    pub scale: Option<usize>,
+}
+
+impl RomConfig {
+    /// The config file for the rom at `rom`, if there is one.
+    pub fn find(rom: &Path) -> Option<PathBuf> {
+        let mut name = rom.file_name()?.to_os_string();
+        name.push(".toml");
+        let sidecar = rom.with_file_name(&name);
+        if sidecar.is_file() {
+            return Some(sidecar);
+        }
+        let central = config_dir()?.join(name);
+        central.is_file().then_some(central)
+    }
+
+    pub fn load(path: &Path) -> Result<RomConfig, Box<dyn Error>> {
+        RomConfig::parse(&std::fs::read_to_string(path)?)
+    }
+
+    pub fn parse(text: &str) -> Result<RomConfig, Box<dyn Error>> {
+        let table: Table = text.parse()?;
+        let mut config = RomConfig::default();
+        for (name, value) in &table {
+            match name.as_str() {
+                "variant" => config.variant = Some(value_enum(value)?),
+                "quirks" => config.quirks = Some(quirks(value)?),
+                "ips" => config.ips = Some(number(value)?),
+                "cycles_per_frame" => {
+                    let cycles: u32 = number(value)?;
+                    let ips = cycles
+                        .checked_mul(60)
+                        .ok_or(format!("{cycles} is out of range"))?;
+                    config.ips = Some(ips);
+                }
+                "palette" => config.palette = Some(palette(value)?),
+                "keys" => config.keys = keys(value)?,
+                "scale" => config.scale = Some(number(value)?),
+                _ => return Err(format!("unknown setting `{name}`").into()),
+            }
+        }
+        Ok(config)
+    }
+
+    /// These settings, with `other`'s filling in what these leave out.
+    pub fn or(self, other: RomConfig) -> RomConfig {
+        RomConfig {
+            variant: self.variant.or(other.variant),
+            quirks: self.quirks.or(other.quirks),
+            ips: self.ips.or(other.ips),
+            palette: self.palette.or(other.palette),
+            keys: if self.keys.is_empty() {
+                other.keys
+            } else {
+                self.keys
+            },
+            scale: self.scale.or(other.scale),
+        }
+    }
+}
+
+// $XDG_CONFIG_HOME/rusty-chip8, or ~/.config/rusty-chip8.
+fn config_dir() -> Option<PathBuf> {
+    let base = std::env::var_os("XDG_CONFIG_HOME")
+        .map(PathBuf::from)
+        .or_else(|| Some(PathBuf::from(std::env::var_os("HOME")?).join(".config")))?;
+    Some(base.join("rusty-chip8"))
+}
+
+/// "#RRGGBB" as 0xRRGGBB.
+pub fn parse_colour(text: &str) -> Option<u32> {
+    let hex = text.strip_prefix('#')?;
+    (hex.len() == 6)
+        .then(|| u32::from_str_radix(hex, 16).ok())
+        .flatten()
+}
+
+fn value_enum<T: ValueEnum>(value: &Value) -> Result<T, Box<dyn Error>> {
+    let name = value.as_str().ok_or("expected a name")?;
+    Ok(T::from_str(name, true)?)
+}
+
+fn number<T: TryFrom<i64>>(value: &Value) -> Result<T, Box<dyn Error>> {
+    let number = value.as_integer().ok_or("expected a number")?;
+    T::try_from(number).map_err(|_| format!("{number} is out of range").into())
+}
+
+fn quirks(value: &Value) -> Result<Quirks, Box<dyn Error>> {
+    let table = match value {
+        Value::String(_) => return Ok(value_enum::<Profile>(value)?.quirks()),
+        Value::Table(table) => table,
+        _ => return Err("expected a profile or a table of quirks".into()),
+    };
+    let mut quirks = match table.get("profile") {
+        Some(profile) => value_enum::<Profile>(profile)?.quirks(),
+        None => Quirks::default(),
+    };
+    for (name, value) in table {
+        let quirk = match name.as_str() {
+            "profile" => continue,
+            "shift" => &mut quirks.shift,
+            "vf_reset" => &mut quirks.vf_reset,
+            "memory" => &mut quirks.memory,
+            "jumping" => &mut quirks.jumping,
+            "clipping" => &mut quirks.clipping,
+            _ => return Err(format!("unknown quirk `{name}`").into()),
+        };
+        *quirk = value.as_bool().ok_or("expected true or false")?;
+    }
+    Ok(quirks)
+}
+
+fn palette(value: &Value) -> Result<[u32; 2], Box<dyn Error>> {
+    let colours: Vec<u32> = value
+        .as_array()
+        .ok_or("expected a list of colours")?
+        .iter()
+        .map(|colour| colour.as_str().and_then(parse_colour))
+        .collect::<Option<_>>()
+        .ok_or("expected colours like \"#RRGGBB\"")?;
+    let [background, foreground] = colours[..] else {
+        return Err("expected a background and a foreground colour".into());
+    };
+    Ok([background, foreground])
+}
+
+fn keys(value: &Value) -> Result<Vec<(u8, String)>, Box<dyn Error>> {
+    let table = value.as_table().ok_or("expected a table of keys")?;
+    table
+        .iter()
+        .map(|(key, name)| {
+            let key = u8::from_str_radix(key, 16)
+                .ok()
+                .filter(|&key| key < 16)
+                .ok_or_else(|| format!("`{key}` is not a CHIP-8 key"))?;
+            let name = name.as_str().ok_or("expected a key name")?;
+            Ok((key, name.to_string()))
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::RomConfig;
+    use crate::{quirks::Profile, variant::Variant};
+
+    #[test]
+    fn test_parse() {
+        let config = RomConfig::parse(
+            r##"
+            variant = "chip8x"
+            cycles_per_frame = 20
+            palette = ["#000000", "#00ff00"]
+            scale = 8
+
+            [quirks]
+            profile = "schip"
+            clipping = false
+
+            [keys]
+            5 = "Up"
+            A = "Space"
+            "##,
+        )
+        .unwrap();
+
+        let mut quirks = Profile::Schip.quirks();
+        quirks.clipping = false;
+        assert_eq!(
+            config,
+            RomConfig {
+                variant: Some(Variant::Chip8x),
+                quirks: Some(quirks),
+                ips: Some(1200),
+                palette: Some([0x000000, 0x00FF00]),
+                keys: vec![(5, "Up".to_string()), (0xA, "Space".to_string())],
+                scale: Some(8),
+            }
+        );
+        assert_eq!(
+            RomConfig::parse("quirks = \"chip8\"").unwrap().quirks,
+            Some(Profile::Chip8.quirks())
+        );
+    }
+
+    #[test]
+    fn test_or() {
+        let config = RomConfig::parse("ips = 500").unwrap();
+        let other = RomConfig::parse("ips = 1000\nscale = 4\nkeys = { 5 = \"Up\" }").unwrap();
+
+        let merged = config.or(other);
+
+        assert_eq!(merged.ips, Some(500));
+        assert_eq!(merged.scale, Some(4));
+        assert_eq!(merged.keys, [(5, "Up".to_string())]);
+    }
+
+    #[test]
+    fn test_errors() {
+        for text in [
+            "speed = 10",
+            "quirks = { wrap = true }",
+            "palette = [\"#000000\"]",
+            "keys = { G = \"Up\" }",
+            "scale = -1",
+            "variant = \"schip\"",
+            "cycles_per_frame = 100000000",
+        ] {
+            assert!(RomConfig::parse(text).is_err(), "{text}");
+        }
+    }
+}
@@ -0,0 +1,482 @@
+use std::{collections::HashMap, error::Error, fmt::Display, fs, path::Path};
+
+use crate::{
+    chip::{Chip, Fault},
+    octo,
+    platform::Headless,
+    quirks::Profile,
+};
+
+const WIDTH: usize = 64;
+
+const CYCLES_PER_FRAME: usize = 30;
+
+// The suite reads this address to pick a platform instead of asking for a key.
+const PLATFORM_SELECT: usize = 0x1FF;
+
+// Screens checked by eye against the suite's documentation. Only the IBM logo
+// is here so far; the other ROMs need their goldens recorded from a build
+// whose screens show every check passing. Until then `BUNDLED_TESTS` catch
+// flag bugs that only those ROMs would show.
+const BUNDLED: &str = include_str!("../roms/timendus.txt");
+
+pub struct Test {
+    pub rom: &'static str,
+    pub frames: usize,
+    /// Whether the ROM's platform menu is answered through `PLATFORM_SELECT`.
+    pub select: bool,
+    /// The profiles the ROM can pass under.
+    pub profiles: &'static [Profile],
+    /// Octo source for a ROM that comes with the emulator rather than the
+    /// suite's directory.
+    pub source: Option<&'static str>,
+    /// How many ticks a passing screen shows, for ROMs whose result is read
+    /// from `TICK` and `CROSS` glyphs rather than a golden hash.
+    pub checks: usize,
+}
+
+// The 4x3 glyphs the bundled ROMs draw for a passing and a failing check.
+const TICK: [u8; 3] = [0x10, 0xA0, 0x40];
+const CROSS: [u8; 3] = [0xA0, 0x40, 0xA0];
+
+/// The non-interactive ROMs of the Timendus CHIP-8 test suite. The keypad and
+/// beep tests need someone at the keyboard and are left out. The quirks ROM
+/// also tests SUPER-CHIP and XO-CHIP instructions this emulator lacks, so it
+/// only targets CHIP-8.
+pub const TIMENDUS: [Test; 5] = [
+    Test {
+        rom: "1-chip8-logo.ch8",
+        frames: 60,
+        select: false,
+        profiles: &Profile::ALL,
+        source: None,
+        checks: 0,
+    },
+    Test {
+        rom: "2-ibm-logo.ch8",
+        frames: 60,
+        select: false,
+        profiles: &Profile::ALL,
+        source: None,
+        checks: 0,
+    },
+    Test {
+        rom: "3-corax+.ch8",
+        frames: 300,
+        select: false,
+        profiles: &Profile::ALL,
+        source: None,
+        checks: 0,
+    },
+    Test {
+        rom: "4-flags.ch8",
+        frames: 300,
+        select: false,
+        profiles: &Profile::ALL,
+        source: None,
+        checks: 0,
+    },
+    Test {
+        rom: "5-quirks.ch8",
+        frames: 1_000,
+        select: true,
+        profiles: &[Profile::Chip8],
+        source: None,
+        checks: 0,
+    },
+];
+
+/// ROMs shipped with the emulator that check what the suite only shows, run
+/// alongside it. `vf-order.8o` fails if an 8XYN writes VF before VX.
+pub const BUNDLED_TESTS: [Test; 1] = [Test {
+    rom: "vf-order.8o",
+    frames: 10,
+    select: false,
+    profiles: &Profile::ALL,
+    source: Some(include_str!("../roms/vf-order.8o")),
+    checks: 8,
+}];
+
+fn tests() -> impl Iterator<Item = &'static Test> {
+    TIMENDUS.iter().chain(&BUNDLED_TESTS)
+}
+
+pub enum Status {
+    Pass,
+    Fail {
+        expected: u64,
+    },
+    /// A screen of ticks and crosses with `failed` checks not ticked.
+    Checks {
+        failed: usize,
+    },
+    Unrecorded,
+    Missing,
+    Faulted(Fault),
+}
+
+impl Display for Status {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Status::Pass => write!(f, "pass"),
+            Status::Fail { .. } => write!(f, "FAIL"),
+            Status::Checks { failed } => write!(f, "FAIL ({failed})"),
+            Status::Unrecorded => write!(f, "new"),
+            Status::Missing => write!(f, "missing"),
+            Status::Faulted(fault) => write!(f, "FAULT ({fault})"),
+        }
+    }
+}
+
+pub struct Outcome {
+    pub rom: &'static str,
+    pub profile: Profile,
+    pub status: Status,
+    pub screen: Vec<u32>,
+}
+
+impl Outcome {
+    pub fn hash(&self) -> u64 {
+        hash(&self.screen)
+    }
+}
+
+/// Display hashes known to be correct, keyed by ROM and profile. The file
+/// holds one `<rom> <profile> <hash>` entry per line; `#` starts a comment.
+/// A hash is only worth recording once its screen has been checked by eye.
+#[derive(Default)]
+pub struct Golden(HashMap<(String, String), u64>);
+
+impl Golden {
+    pub fn bundled() -> Golden {
+        BUNDLED.parse().expect("the bundled goldens are valid")
+    }
+
+    /// The bundled goldens, with those in `path` on top if it exists.
+    pub fn load(path: &Path) -> Result<Golden, Box<dyn Error>> {
+        let mut golden = Golden::bundled();
+        if path.exists() {
+            let file: Golden = fs::read_to_string(path)?.parse()?;
+            golden.0.extend(file.0);
+        }
+        Ok(golden)
+    }
+
+    pub fn save(&self, path: &Path) -> Result<(), Box<dyn Error>> {
+        fs::write(path, self.to_string())?;
+        Ok(())
+    }
+
+    pub fn get(&self, rom: &str, profile: Profile) -> Option<u64> {
+        self.0
+            .get(&(rom.to_string(), profile.name().to_string()))
+            .copied()
+    }
+
+    pub fn insert(&mut self, rom: &str, profile: Profile, hash: u64) {
+        self.0
+            .insert((rom.to_string(), profile.name().to_string()), hash);
+    }
+}
+
+impl std::str::FromStr for Golden {
+    type Err = Box<dyn Error>;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut golden = Golden::default();
+        for (n, line) in s.lines().enumerate() {
+            let line = line.split('#').next().unwrap_or_default().trim();
+            if line.is_empty() {
+                continue;
+            }
+            let fields: Vec<&str> = line.split_whitespace().collect();
+            let [rom, profile, hash] = fields[..] else {
+                return Err(format!("line {}: expected `<rom> <profile> <hash>`", n + 1).into());
+            };
+            let hash = u64::from_str_radix(hash.trim_start_matches("0x"), 16)
+                .map_err(|e| format!("line {}: {e}", n + 1))?;
+            golden
+                .0
+                .insert((rom.to_string(), profile.to_string()), hash);
+        }
+        Ok(golden)
+    }
+}
+
+impl Display for Golden {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let mut entries: Vec<_> = self.0.iter().collect();
+        entries.sort();
+        entries
+            .iter()
+            .try_for_each(|((rom, profile), hash)| writeln!(f, "{rom} {profile} {hash:#018x}"))
+    }
+}
+
+/// Runs every suite ROM found in `dir`, and the bundled ones, under each of
+/// `profiles` they target.
+pub fn run(dir: &Path, profiles: &[Profile], golden: &Golden) -> Vec<Outcome> {
+    let mut outcomes = Vec::new();
+    for test in tests() {
+        for &profile in profiles.iter().filter(|p| test.profiles.contains(p)) {
+            let path = dir.join(test.rom);
+            if test.source.is_none() && !path.exists() {
+                outcomes.push(Outcome {
+                    rom: test.rom,
+                    profile,
+                    status: Status::Missing,
+                    screen: Vec::new(),
+                });
+                continue;
+            }
+
+            let mut chip = Chip::new();
+            chip.trace = false;
+            chip.quirks = profile.quirks();
+            chip.rng = fastrand::Rng::with_seed(0);
+            let loaded = match test.source {
+                Some(source) => {
+                    let rom = octo::assemble(source)
+                        .expect("the bundled tests assemble")
+                        .rom;
+                    chip.load_bytes(&rom)
+                }
+                None => chip.load(&path),
+            };
+            let result = loaded.map(|()| {
+                if test.select {
+                    chip.write(PLATFORM_SELECT, &[select(profile)]);
+                }
+                run_headless(&mut chip, test.frames, CYCLES_PER_FRAME)
+            });
+
+            let (status, screen) = match result {
+                Ok(Ok(screen)) if test.checks > 0 => {
+                    let (ticks, crosses) = checks(&screen);
+                    let failed = crosses + test.checks.saturating_sub(ticks);
+                    let status = match failed {
+                        0 => Status::Pass,
+                        _ => Status::Checks { failed },
+                    };
+                    (status, screen)
+                }
+                Ok(Ok(screen)) => {
+                    let status = match golden.get(test.rom, profile) {
+                        Some(expected) if expected == hash(&screen) => Status::Pass,
+                        Some(expected) => Status::Fail { expected },
+                        None => Status::Unrecorded,
+                    };
+                    (status, screen)
+                }
+                Ok(Err(fault)) => (Status::Faulted(fault), Vec::new()),
+                Err(_) => (Status::Missing, Vec::new()),
+            };
+            outcomes.push(Outcome {
+                rom: test.rom,
+                profile,
+                status,
+                screen,
+            });
+        }
+    }
+    outcomes
+}
+
+/// Formats the outcomes as a table with one row per ROM and one column per
+/// profile, with `-` where the ROM does not target the profile.
+pub fn report(outcomes: &[Outcome], profiles: &[Profile]) -> String {
+    let mut report = format!("{:<20}", "");
+    profiles
+        .iter()
+        .for_each(|p| report.push_str(&format!("{:<10}", p.name())));
+    report.push('\n');
+
+    for test in tests() {
+        report.push_str(&format!("{:<20}", test.rom));
+        for &profile in profiles {
+            if let Some(outcome) = outcomes
+                .iter()
+                .find(|o| o.rom == test.rom && o.profile == profile)
+            {
+                report.push_str(&format!("{:<10}", outcome.status.to_string()));
+            } else {
+                report.push_str(&format!("{:<10}", "-"));
+            }
+        }
+        report.push('\n');
+    }
+
+    let passed = outcomes
+        .iter()
+        .filter(|o| matches!(o.status, Status::Pass))
+        .count();
+    report.push_str(&format!("\n{passed}/{} passed", outcomes.len()));
+    report
+}
+
+/// Counts the `TICK` and `CROSS` glyphs drawn on a 64-pixel-wide screen.
+pub fn checks(screen: &[u32]) -> (usize, usize) {
+    let height = screen.len() / WIDTH;
+    let glyph_at = |x: usize, y: usize, glyph: &[u8; 3]| {
+        glyph.iter().enumerate().all(|(row, bits)| {
+            (0..4).all(|col| {
+                let lit = screen[(y + row) * WIDTH + x + col] != 0;
+                lit == (bits & (0x80 >> col) != 0)
+            })
+        })
+    };
+    let mut counts = (0, 0);
+    for y in 0..height.saturating_sub(2) {
+        for x in 0..WIDTH - 3 {
+            counts.0 += glyph_at(x, y, &TICK) as usize;
+            counts.1 += glyph_at(x, y, &CROSS) as usize;
+        }
+    }
+    counts
+}
+
+fn select(profile: Profile) -> u8 {
+    match profile {
+        Profile::Chip8 => 1,
+        Profile::Schip => 2,
+        Profile::Xochip => 3,
+    }
+}
+
+pub fn run_headless(
+    chip: &mut Chip,
+    frames: usize,
+    cycles_per_frame: usize,
+) -> Result<Vec<u32>, Fault> {
+    let (width, height) = chip.variant.size();
+    let mut buffer = vec![0u32; width * height];
+    let platform = Headless::default();
+    for _ in 0..frames {
+        chip.frame(cycles_per_frame, &mut buffer, &platform)?;
+    }
+    Ok(buffer)
+}
+
+/// FNV-1a over which pixels are lit, so the hash does not depend on colour.
+pub fn hash(buffer: &[u32]) -> u64 {
+    buffer.iter().fold(0xcbf29ce484222325, |h, &pixel| {
+        (h ^ (pixel != 0) as u64).wrapping_mul(0x100000001b3)
+    })
+}
+
+pub fn render(buffer: &[u32]) -> String {
+    buffer
+        .chunks(WIDTH)
+        .map(|row| {
+            row.iter()
+                .map(|&pixel| if pixel != 0 { '#' } else { '.' })
+                .collect::<String>()
+        })
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::Path;
+
+    use super::{checks, hash, run, run_headless, Golden, Status, CROSS, CYCLES_PER_FRAME, TICK};
+    use crate::{chip::Chip, octo, platform::Headless, quirks::Profile};
+
+    #[test]
+    fn test_golden_roundtrip() {
+        let golden: Golden =
+            "# comment\n3-corax+.ch8 chip8 0x00000000000000ff\n\n4-flags.ch8 schip 1a # trailing\n"
+                .parse()
+                .unwrap();
+
+        assert_eq!(golden.get("3-corax+.ch8", Profile::Chip8), Some(0xFF));
+        assert_eq!(golden.get("4-flags.ch8", Profile::Schip), Some(0x1A));
+        assert_eq!(golden.get("4-flags.ch8", Profile::Chip8), None);
+
+        let reparsed: Golden = golden.to_string().parse().unwrap();
+        assert_eq!(reparsed.get("4-flags.ch8", Profile::Schip), Some(0x1A));
+    }
+
+    #[test]
+    fn test_bundled_ibm_logo() {
+        let rom = format!("{}/roms/IBM_Logo.ch8", env!("CARGO_MANIFEST_DIR"));
+        let mut chip = Chip::new();
+        chip.trace = false;
+        chip.load(rom).unwrap();
+
+        let screen = run_headless(&mut chip, 60, CYCLES_PER_FRAME).unwrap();
+
+        let golden = Golden::bundled();
+        for profile in Profile::ALL {
+            assert_eq!(golden.get("2-ibm-logo.ch8", profile), Some(hash(&screen)));
+        }
+    }
+
+    #[test]
+    fn test_quirks_rom_targets_chip8() {
+        let outcomes = run(Path::new("/nonexistent"), &Profile::ALL, &Golden::default());
+
+        let quirks: Vec<_> = outcomes
+            .iter()
+            .filter(|o| o.rom == "5-quirks.ch8")
+            .map(|o| o.profile)
+            .collect();
+        assert_eq!(quirks, [Profile::Chip8]);
+        assert_eq!(outcomes.len(), 16);
+    }
+
+    #[test]
+    fn test_vf_order() {
+        let outcomes = run(Path::new("/nonexistent"), &Profile::ALL, &Golden::default());
+
+        let bundled: Vec<_> = outcomes.iter().filter(|o| o.rom == "vf-order.8o").collect();
+        assert_eq!(bundled.len(), 3);
+        assert!(bundled.iter().all(|o| matches!(o.status, Status::Pass)));
+    }
+
+    #[test]
+    fn test_vf_written_first_is_caught() {
+        // 8FFE with VF written before VX leaves the shifted value in VF, which
+        // the ROM's last check must show as a cross.
+        let rom = octo::assemble(include_str!("../roms/vf-order.8o"))
+            .unwrap()
+            .rom;
+        let mut chip = Chip::new();
+        chip.trace = false;
+        chip.load_bytes(&rom).unwrap();
+        let mut buffer = vec![0u32; 64 * 32];
+        let platform = Headless::default();
+        while u16::from_be_bytes([chip.mem[chip.pc as usize], chip.mem[chip.pc as usize + 1]])
+            != 0x8F3E
+        {
+            chip.step(&mut buffer, &platform).unwrap();
+        }
+        chip.pc += 2;
+        chip.v[0xF] = 0x02;
+        for _ in 0..20 {
+            chip.step(&mut buffer, &platform).unwrap();
+        }
+
+        assert_eq!(checks(&buffer), (7, 1));
+    }
+
+    #[test]
+    fn test_checks() {
+        let mut screen = vec![0u32; 64 * 32];
+        for (x, glyph) in [(0, TICK), (10, CROSS), (20, TICK)] {
+            for (row, bits) in glyph.iter().enumerate() {
+                for col in 0..4 {
+                    screen[(5 + row) * 64 + x + col] = (bits & (0x80 >> col) != 0) as u32;
+                }
+            }
+        }
+
+        assert_eq!(checks(&screen), (2, 1));
+    }
+
+    #[test]
+    fn test_golden_bad_line() {
+        assert!("3-corax+.ch8 chip8".parse::<Golden>().is_err());
+    }
+}
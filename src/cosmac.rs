@@ -0,0 +1,546 @@
+use std::{ops::Range, path::Path};
+
+use crate::{
+    chip::{from_u8_rgb, Chip, Fault, LoadError},
+    platform::Platform,
+};
+
+// The interpreter lives in the first two pages of RAM, the monitor in the
+// 512 bytes mirrored through 0x8000-0xFFFF.
+const IMAGE_SIZE: usize = 0x200;
+const RAM_MASK: u16 = 0x0FFF;
+const ROM_MASK: u16 = 0x01FF;
+
+// The CDP1861 draws 262 lines of 14 machine cycles each per 60 Hz frame.
+const LINE: u32 = 14;
+const FRAME: u32 = 262 * LINE;
+// Lines the display fetches 8 bytes for by DMA.
+const DISPLAY: Range<u32> = 80..208;
+// INT is raised two lines before the first DMA, EF1 four lines before the
+// display starts and ends.
+const INTERRUPT: Range<u32> = 78..80;
+const EF1_START: Range<u32> = 76..80;
+const EF1_END: Range<u32> = 204..208;
+const DMA_BYTES: u32 = 8;
+
+// Where the 4K VIP's CHIP-8 interpreter keeps V0-VF, and the 1802 registers it
+// uses for the CHIP-8 PC, I and the timers (delay in the high byte).
+const VARIABLES: usize = 0x0EF0;
+const PC: usize = 0x5;
+const I: usize = 0xA;
+const TIMERS: usize = 0x8;
+
+/// The COSMAC VIP at the machine level: an RCA CDP1802 running the original
+/// CHIP-8 interpreter out of the VIP's RAM, with the CDP1861 display. The rom
+/// and the interpreter share `chip.mem`, which is the VIP's 4K of RAM.
+///
+/// Like `Recompiler`, it runs frames of a `Chip`, but the only state it keeps
+/// in the chip is memory. After every frame the registers the interpreter keeps
+/// in RAM and in 1802 registers are copied into `v`, `i`, `pc`, `dt` and `st`
+/// so frontends and debuggers can show them; writing them has no effect.
+pub struct Cosmac {
+    interpreter: Vec<u8>,
+    monitor: Vec<u8>,
+    booted: bool,
+    r: [u16; 16],
+    p: usize,
+    x: usize,
+    d: u8,
+    df: bool,
+    t: u8,
+    q: bool,
+    ie: bool,
+    idle: bool,
+    // The monitor is also mapped at 0x0000 from reset until the first access
+    // with A15 set.
+    rom_low: bool,
+    key: u8,
+    display: bool,
+    // Machine cycle within the current frame, and the display line that last
+    // got its DMA and whether INT was raised this frame.
+    cycle: u32,
+    dma_line: Option<u32>,
+    interrupted: bool,
+}
+
+impl Cosmac {
+    /// Takes the CHIP-8 interpreter as it sits at 0x0000 in RAM and the VIP
+    /// monitor rom.
+    pub fn new(interpreter: &[u8], monitor: &[u8]) -> Result<Cosmac, LoadError> {
+        for image in [interpreter, monitor] {
+            if image.len() > IMAGE_SIZE {
+                return Err(LoadError::TooLarge {
+                    size: image.len(),
+                    max: IMAGE_SIZE,
+                });
+            }
+        }
+        Ok(Cosmac {
+            interpreter: interpreter.to_vec(),
+            monitor: monitor.to_vec(),
+            booted: false,
+            r: [0; 16],
+            p: 0,
+            x: 0,
+            d: 0,
+            df: false,
+            t: 0,
+            q: false,
+            ie: true,
+            idle: false,
+            rom_low: true,
+            key: 0,
+            display: false,
+            cycle: 0,
+            dma_line: None,
+            interrupted: false,
+        })
+    }
+
+    pub fn load(
+        interpreter: impl AsRef<Path>,
+        monitor: impl AsRef<Path>,
+    ) -> Result<Cosmac, LoadError> {
+        Cosmac::new(&std::fs::read(interpreter)?, &std::fs::read(monitor)?)
+    }
+
+    /// Runs one 60 Hz frame. The VIP is reset and the interpreter copied into
+    /// memory the first time.
+    pub fn frame(
+        &mut self,
+        chip: &mut Chip,
+        buffer: &mut [u32],
+        platform: &dyn Platform,
+    ) -> Result<(), Fault> {
+        if !self.booted {
+            self.reset();
+            chip.mem[..self.interpreter.len()].copy_from_slice(&self.interpreter);
+        }
+
+        while self.cycle < FRAME {
+            let line = self.cycle / LINE;
+            if self.display && DISPLAY.contains(&line) && self.dma_line != Some(line) {
+                self.dma(line, &chip.mem, buffer);
+            } else if self.display && self.ie && !self.interrupted && INTERRUPT.contains(&line) {
+                self.interrupt();
+            } else if self.idle {
+                self.cycle += 1;
+            } else {
+                self.cycle += self.execute(&mut chip.mem, platform);
+            }
+        }
+        self.cycle -= FRAME;
+        self.dma_line = None;
+        self.interrupted = false;
+
+        chip.v.copy_from_slice(&chip.mem[VARIABLES..VARIABLES + 16]);
+        chip.pc = self.r[PC] & RAM_MASK;
+        chip.i = (self.r[I] & RAM_MASK) as u32;
+        [chip.dt, chip.st] = self.r[TIMERS].to_be_bytes();
+        Ok(())
+    }
+
+    fn reset(&mut self) {
+        self.booted = true;
+        self.r[0] = 0;
+        self.p = 0;
+        self.x = 0;
+        self.q = false;
+        self.ie = true;
+        self.idle = false;
+        self.rom_low = true;
+        self.display = false;
+        self.cycle = 0;
+        self.dma_line = None;
+        self.interrupted = false;
+    }
+
+    // Eight bytes from R0 for one display line. The interpreter shows each row
+    // on four lines, so the first of them is what ends up in the buffer.
+    fn dma(&mut self, line: u32, mem: &[u8], buffer: &mut [u32]) {
+        let row = (line - DISPLAY.start) as usize;
+        for column in 0..DMA_BYTES as usize {
+            let byte = self.read(mem, self.r[0]);
+            self.r[0] = self.r[0].wrapping_add(1);
+            if row.is_multiple_of(4) {
+                for bit in 0..8 {
+                    let on = byte & (0x80 >> bit) != 0;
+                    buffer[row / 4 * 64 + column * 8 + bit] =
+                        if on { from_u8_rgb(255, 255, 255) } else { 0 };
+                }
+            }
+        }
+        self.dma_line = Some(line);
+        self.cycle += DMA_BYTES;
+        self.idle = false;
+    }
+
+    fn interrupt(&mut self) {
+        self.t = (self.x << 4 | self.p) as u8;
+        self.p = 1;
+        self.x = 2;
+        self.ie = false;
+        self.interrupted = true;
+        self.idle = false;
+        self.cycle += 1;
+    }
+
+    fn read(&mut self, mem: &[u8], address: u16) -> u8 {
+        if address & 0x8000 != 0 {
+            self.rom_low = false;
+        }
+        if address & 0x8000 != 0 || self.rom_low {
+            self.monitor
+                .get((address & ROM_MASK) as usize)
+                .copied()
+                .unwrap_or(0xFF)
+        } else {
+            mem[(address & RAM_MASK) as usize]
+        }
+    }
+
+    fn write(&self, mem: &mut [u8], address: u16, value: u8) {
+        if address & 0x8000 == 0 {
+            mem[(address & RAM_MASK) as usize] = value;
+        }
+    }
+
+    // The byte at R(P), moving past it.
+    fn immediate(&mut self, mem: &[u8]) -> u8 {
+        let value = self.read(mem, self.r[self.p]);
+        self.r[self.p] = self.r[self.p].wrapping_add(1);
+        value
+    }
+
+    fn flag(&self, n: u8, platform: &dyn Platform) -> bool {
+        let line = self.cycle / LINE;
+        match n {
+            1 => self.display && (EF1_START.contains(&line) || EF1_END.contains(&line)),
+            // EF3 is the key selected by the last OUT 2. EF2 is the cassette
+            // input and EF4 the IN button, neither of which is wired up.
+            3 => platform.is_key_down(self.key),
+            _ => false,
+        }
+    }
+
+    // The condition tested by short branches 3N and long branches CN.
+    fn condition(&self, n: u8, platform: &dyn Platform) -> bool {
+        match n & 0x7 {
+            0 => true,
+            1 => self.q,
+            2 => self.d == 0,
+            3 => self.df,
+            ef => self.flag(ef - 3, platform),
+        }
+    }
+
+    // D = a - b - borrow, with DF set when nothing was borrowed.
+    fn subtract(&mut self, a: u8, b: u8, borrow: bool) {
+        let difference = a as i16 - b as i16 - borrow as i16;
+        self.d = difference as u8;
+        self.df = difference >= 0;
+    }
+
+    fn add(&mut self, a: u8, carry: bool) {
+        let sum = a as u16 + self.d as u16 + carry as u16;
+        self.d = sum as u8;
+        self.df = sum > 0xFF;
+    }
+
+    /// Executes one instruction and returns the machine cycles it took.
+    fn execute(&mut self, mem: &mut [u8], platform: &dyn Platform) -> u32 {
+        let opcode = self.immediate(mem);
+        let n = (opcode & 0xF) as usize;
+        match opcode >> 4 {
+            0x0 if n == 0 => self.idle = true,
+            0x0 => self.d = self.read(mem, self.r[n]),
+            0x1 => self.r[n] = self.r[n].wrapping_add(1),
+            0x2 => self.r[n] = self.r[n].wrapping_sub(1),
+            0x3 => {
+                let target = self.immediate(mem);
+                // 38 is SKP, which never branches and so skips the byte.
+                if self.condition(n as u8, platform) != (n >= 8) {
+                    self.r[self.p] = self.r[self.p] & 0xFF00 | target as u16;
+                }
+            }
+            0x4 => {
+                self.d = self.read(mem, self.r[n]);
+                self.r[n] = self.r[n].wrapping_add(1);
+            }
+            0x5 => self.write(mem, self.r[n], self.d),
+            0x6 => self.input_output(n, mem),
+            0x7 => self.control(n, mem),
+            0x8 => self.d = self.r[n] as u8,
+            0x9 => self.d = (self.r[n] >> 8) as u8,
+            0xA => self.r[n] = self.r[n] & 0xFF00 | self.d as u16,
+            0xB => self.r[n] = self.r[n] & 0x00FF | (self.d as u16) << 8,
+            0xC => {
+                self.long(n as u8, mem, platform);
+                return 3;
+            }
+            0xD => self.p = n,
+            0xE => self.x = n,
+            _ => self.arithmetic(n, mem),
+        }
+        2
+    }
+
+    fn input_output(&mut self, n: usize, mem: &mut [u8]) {
+        match n {
+            0 => self.r[self.x] = self.r[self.x].wrapping_add(1),
+            // Not an instruction on the 1802.
+            8 => {}
+            1..=7 => {
+                let value = self.read(mem, self.r[self.x]);
+                self.r[self.x] = self.r[self.x].wrapping_add(1);
+                match n {
+                    1 => self.display = false,
+                    2 => self.key = value & 0xF,
+                    _ => {}
+                }
+            }
+            _ => {
+                if n == 9 {
+                    self.display = true;
+                }
+                // Nothing drives the bus, which reads as zero.
+                self.d = 0;
+                self.write(mem, self.r[self.x], 0);
+            }
+        }
+    }
+
+    fn control(&mut self, n: usize, mem: &mut [u8]) {
+        let rx = self.r[self.x];
+        match n {
+            0x0 | 0x1 => {
+                let value = self.read(mem, rx);
+                self.r[self.x] = rx.wrapping_add(1);
+                self.x = (value >> 4) as usize;
+                self.p = (value & 0xF) as usize;
+                self.ie = n == 0;
+            }
+            0x2 => {
+                self.d = self.read(mem, rx);
+                self.r[self.x] = rx.wrapping_add(1);
+            }
+            0x3 => {
+                self.write(mem, rx, self.d);
+                self.r[self.x] = rx.wrapping_sub(1);
+            }
+            0x4 => {
+                let value = self.read(mem, rx);
+                self.add(value, self.df);
+            }
+            0x5 => {
+                let value = self.read(mem, rx);
+                self.subtract(value, self.d, !self.df);
+            }
+            0x6 => {
+                let carry = self.df;
+                self.df = self.d & 1 != 0;
+                self.d = self.d >> 1 | (carry as u8) << 7;
+            }
+            0x7 => {
+                let value = self.read(mem, rx);
+                self.subtract(self.d, value, !self.df);
+            }
+            0x8 => self.write(mem, rx, self.t),
+            0x9 => {
+                self.t = (self.x << 4 | self.p) as u8;
+                self.write(mem, self.r[2], self.t);
+                self.x = self.p;
+                self.r[2] = self.r[2].wrapping_sub(1);
+            }
+            0xA => self.q = false,
+            0xB => self.q = true,
+            0xC => {
+                let value = self.immediate(mem);
+                self.add(value, self.df);
+            }
+            0xD => {
+                let value = self.immediate(mem);
+                self.subtract(value, self.d, !self.df);
+            }
+            0xE => {
+                let carry = self.df;
+                self.df = self.d & 0x80 != 0;
+                self.d = self.d << 1 | carry as u8;
+            }
+            _ => {
+                let value = self.immediate(mem);
+                self.subtract(self.d, value, !self.df);
+            }
+        }
+    }
+
+    // Long branches, long skips and NOP.
+    fn long(&mut self, n: u8, mem: &[u8], platform: &dyn Platform) {
+        let pc = self.r[self.p];
+        let skip = matches!(n, 0x5..=0x8 | 0xC..=0xF);
+        let taken = match n {
+            0x4 => return,
+            0xC => self.ie,
+            0x5..=0x7 => !self.condition(n - 4, platform),
+            0xD..=0xF => self.condition(n - 0xC, platform),
+            0x8 => true,
+            _ => self.condition(n, platform) != (n >= 8),
+        };
+        if skip {
+            if taken {
+                self.r[self.p] = pc.wrapping_add(2);
+            }
+        } else if taken {
+            let high = self.read(mem, pc);
+            let low = self.read(mem, pc.wrapping_add(1));
+            self.r[self.p] = u16::from_be_bytes([high, low]);
+        } else {
+            self.r[self.p] = pc.wrapping_add(2);
+        }
+    }
+
+    // F0-FF: the ALU on M(R(X)) or, from F8 on, an immediate byte.
+    fn arithmetic(&mut self, n: usize, mem: &[u8]) {
+        let value = match n {
+            0x6 | 0xE => 0,
+            0x0..=0x7 => self.read(mem, self.r[self.x]),
+            _ => self.immediate(mem),
+        };
+        match n & 0x7 {
+            0x0 => self.d = value,
+            0x1 => self.d |= value,
+            0x2 => self.d &= value,
+            0x3 => self.d ^= value,
+            0x4 => self.add(value, false),
+            0x5 => self.subtract(value, self.d, false),
+            0x6 if n == 0x6 => {
+                self.df = self.d & 1 != 0;
+                self.d >>= 1;
+            }
+            0x6 => {
+                self.df = self.d & 0x80 != 0;
+                self.d <<= 1;
+            }
+            _ => self.subtract(self.d, value, false),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Cosmac;
+    use crate::{chip::Chip, platform::Headless};
+
+    // Jumps from the reset vector into ROM, which unmaps it from 0x0000, and
+    // from there to the "interpreter" in RAM.
+    const MONITOR: [u8; 6] = [0xC0, 0x80, 0x03, 0xC0, 0x00, 0x00];
+
+    fn run(program: &[u8], frames: usize) -> (Chip, Vec<u32>) {
+        let mut chip = Chip::new();
+        chip.trace = false;
+        let mut cosmac = Cosmac::new(program, &MONITOR).unwrap();
+        let mut buffer = vec![0u32; 64 * 32];
+        for _ in 0..frames {
+            cosmac
+                .frame(&mut chip, &mut buffer, &Headless::default())
+                .unwrap();
+        }
+        (chip, buffer)
+    }
+
+    #[test]
+    fn test_alu() {
+        #[rustfmt::skip]
+        let program = [
+            0xF8, 0x03, 0xB3, 0xF8, 0x00, 0xA3, // R3 = 0x0300
+            0xE3,                               // SEX 3
+            0xF8, 0xF0, 0xFC, 0x20,             // D = 0xF0 + 0x20, DF = 1
+            0x73,                               // STXD: 0x10 at 0x300
+            0xF8, 0x05, 0x7C, 0x01,             // D = 5 + 1 + DF
+            0x73,                               // 0x07 at 0x2FF
+            0xF8, 0x05, 0xFF, 0x06,             // D = 5 - 6, DF = 0
+            0x73,                               // 0xFF at 0x2FE
+            0xF8, 0x81, 0xF6,                   // SHR: D = 0x40, DF = 1
+            0x73,                               // 0x40 at 0x2FD
+            0xF8, 0x80, 0x7E,                   // SHLC: D = 0x01, DF = 1
+            0x73,                               // 0x01 at 0x2FC
+            0xF8, 0x00, 0x32, 0x23,             // BZ 0x23
+            0x00,                               // not reached
+            0xF8, 0x00, 0xC6,                   // LSNZ not taken
+            0xF8, 0x42, 0x73,                   // 0x42 at 0x2FB
+            0x00,                               // IDL
+        ];
+
+        let (chip, _) = run(&program, 1);
+
+        assert_eq!(
+            chip.mem[0x2FB..=0x300],
+            [0x42, 0x01, 0x40, 0xFF, 0x07, 0x10]
+        );
+    }
+
+    #[test]
+    fn test_chip_registers() {
+        #[rustfmt::skip]
+        let program = [
+            0xF8, 0x0E, 0xB3, 0xF8, 0xF3, 0xA3, // R3 = 0x0EF3
+            0xF8, 0x2A, 0x53,                   // V3 = 0x2A
+            0xF8, 0x02, 0xB5, 0xF8, 0x34, 0xA5, // PC = 0x234
+            0xF8, 0x03, 0xBA, 0xF8, 0x00, 0xAA, // I = 0x300
+            0xF8, 0x10, 0xB8,                   // DT = 0x10
+            0x00,                               // IDL
+        ];
+
+        let (chip, _) = run(&program, 1);
+
+        assert_eq!(chip.v[3], 0x2A);
+        assert_eq!(chip.pc, 0x234);
+        assert_eq!(chip.i, 0x300);
+        assert_eq!(chip.dt, 0x10);
+    }
+
+    #[test]
+    fn test_display() {
+        let mut program = vec![0u8; 0x32];
+        #[rustfmt::skip]
+        let start = [
+            0xF8, 0x00, 0xB3, 0xF8, 0x09, 0xA3, // R3 = 0x0009
+            0xD3,                               // SEP 3
+            0x00, 0x00,
+            0xF8, 0x00, 0xB1, 0xF8, 0x30, 0xA1, // R1 = 0x0030
+            0xF8, 0x0E, 0xB2, 0xF8, 0xCF, 0xA2, // R2 = 0x0ECF
+            0xE2, 0x69,                         // display on
+            0x30, 0x17,                         // spin
+        ];
+        program[..start.len()].copy_from_slice(&start);
+        #[rustfmt::skip]
+        let interrupt = [
+            0x70,                               // 002F: RET
+            0x22, 0x78,                         // 0030: DEC R2, SAV
+            0xF8, 0x0F, 0xB0, 0xF8, 0x00, 0xA0, // R0 = 0x0F00
+            0x30, 0x2F,                         // BR 002F
+        ];
+        program.truncate(0x2F);
+        program.extend_from_slice(&interrupt);
+
+        let mut chip = Chip::new();
+        chip.trace = false;
+        chip.mem[0xF00] = 0x80;
+        chip.mem[0xF20] = 0x01;
+        let mut cosmac = Cosmac::new(&program, &MONITOR).unwrap();
+        let mut buffer = vec![0u32; 64 * 32];
+        for _ in 0..2 {
+            cosmac
+                .frame(&mut chip, &mut buffer, &Headless::default())
+                .unwrap();
+        }
+
+        let lit: Vec<usize> = (0..64 * 3).filter(|&p| buffer[p] != 0).collect();
+        assert_eq!(lit, [0, 64 + 7]);
+    }
+
+    #[test]
+    fn test_image_too_large() {
+        assert!(Cosmac::new(&[0; 0x201], &MONITOR).is_err());
+    }
+}
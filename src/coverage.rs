@@ -0,0 +1,180 @@
+use std::fmt::Write;
+
+use crate::{dump, instructions::Instruction, variant::Variant};
+
+const MEM_SIZE: usize = 4096;
+
+const EXECUTED: u8 = 1;
+const READ: u8 = 2;
+const WRITTEN: u8 = 4;
+
+/// Remembers how each byte of memory has been used: run as an instruction,
+/// read as data (DXYN, FX65) or written (FX33, FX55).
+pub struct Coverage {
+    flags: Vec<u8>,
+}
+
+impl Default for Coverage {
+    fn default() -> Self {
+        Self::new(MEM_SIZE)
+    }
+}
+
+impl Coverage {
+    /// Coverage of `size` bytes, all of the chip's memory.
+    pub fn new(size: usize) -> Coverage {
+        Coverage {
+            flags: vec![0; size],
+        }
+    }
+
+    pub fn execute(&mut self, pc: u16) {
+        self.mark(pc as usize, 2, EXECUTED);
+    }
+
+    pub fn read(&mut self, address: u32, len: usize) {
+        self.mark(address as usize, len, READ);
+    }
+
+    pub fn write(&mut self, address: u32, len: usize) {
+        self.mark(address as usize, len, WRITTEN);
+    }
+
+    pub fn is_executed(&self, address: usize) -> bool {
+        self.has(address, EXECUTED)
+    }
+
+    pub fn is_read(&self, address: usize) -> bool {
+        self.has(address, READ)
+    }
+
+    pub fn is_written(&self, address: usize) -> bool {
+        self.has(address, WRITTEN)
+    }
+
+    fn has(&self, address: usize, flag: u8) -> bool {
+        self.flags
+            .get(address)
+            .is_some_and(|flags| flags & flag != 0)
+    }
+
+    fn mark(&mut self, address: usize, len: usize, flag: u8) {
+        let start = address.min(self.flags.len());
+        let end = (start + len).min(self.flags.len());
+        self.flags[start..end]
+            .iter_mut()
+            .for_each(|flags| *flags |= flag);
+    }
+
+    /// A disassembly of `mem[start..end]` where each word is marked with how
+    /// it was used, disassembled for `variant`. Words that never ran are shown
+    /// as data bytes.
+    pub fn annotated(&self, mem: &[u8], start: usize, end: usize, variant: Variant) -> String {
+        let mut listing = String::new();
+        let mut pc = start;
+        while pc < end {
+            let Some(bytes) = mem.get(pc..pc + 2) else {
+                break;
+            };
+            let usage = |address: usize| {
+                format!(
+                    "{}{}{}",
+                    if self.is_executed(address) { 'X' } else { '-' },
+                    if self.is_read(address) { 'R' } else { '-' },
+                    if self.is_written(address) { 'W' } else { '-' },
+                )
+            };
+            let text = if self.is_executed(pc) {
+                dump::mnemonic_for(&Instruction::new(bytes), variant)
+            } else {
+                format!("db ${:02X}, ${:02X}", bytes[0], bytes[1])
+            };
+            let _ = writeln!(
+                listing,
+                "  {pc:04X}:  {} {}  {:02X}{:02X}  {text}",
+                usage(pc),
+                usage(pc + 1),
+                bytes[0],
+                bytes[1]
+            );
+            pc += 2;
+        }
+        listing
+    }
+
+    /// All of memory as a binary PPM 64 pixels wide, one pixel per byte from
+    /// the top left. Green was executed, blue read and red written; bytes used
+    /// more than one way mix their colours.
+    pub fn image(&self) -> Vec<u8> {
+        let mut image = format!("P6\n64 {}\n255\n", self.flags.len() / 64).into_bytes();
+        for flags in &self.flags {
+            let on = |flag: u8| if flags & flag != 0 { 0xFF } else { 0x00 };
+            image.extend_from_slice(&[on(WRITTEN), on(EXECUTED), on(READ)]);
+        }
+        image
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Coverage;
+    use crate::variant::Variant;
+
+    #[test]
+    fn test_marks() {
+        let mut coverage = Coverage::new(4096);
+        coverage.execute(0x200);
+        coverage.read(0x300, 3);
+        coverage.write(0x301, 1);
+        coverage.write(0xFFF, 4);
+
+        assert!(coverage.is_executed(0x201));
+        assert!(!coverage.is_executed(0x202));
+        assert!(coverage.is_read(0x302) && !coverage.is_read(0x303));
+        assert!(coverage.is_written(0x301) && !coverage.is_written(0x300));
+        assert!(coverage.is_written(0xFFF));
+    }
+
+    #[test]
+    fn test_annotated() {
+        let mut mem = [0u8; 4096];
+        mem[0x200..0x204].copy_from_slice(&[0x12, 0x00, 0xAB, 0xCD]);
+        let mut coverage = Coverage::new(4096);
+        coverage.execute(0x200);
+        coverage.read(0x202, 2);
+
+        let listing = coverage.annotated(&mem, 0x200, 0x204, Variant::Chip8);
+        let lines: Vec<&str> = listing.lines().collect();
+
+        assert!(lines[0].starts_with("  0200:  X-- X--  1200"));
+        assert_eq!(lines[1], "  0202:  -R- -R-  ABCD  db $AB, $CD");
+    }
+
+    #[test]
+    fn test_image() {
+        let mut coverage = Coverage::new(4096);
+        coverage.execute(0);
+
+        let image = coverage.image();
+
+        assert_eq!(image.len(), 13 + 64 * 64 * 3);
+        assert_eq!(image[13..16], [0x00, 0xFF, 0x00]);
+    }
+
+    #[test]
+    fn test_megachip_memory() {
+        let mut coverage = Coverage::new(Variant::Megachip.memory_size());
+        coverage.write(0x123456, 2);
+
+        assert!(coverage.is_written(0x123457));
+        assert!(coverage.image().starts_with(b"P6\n64 262144\n"));
+
+        let mut mem = vec![0u8; 0x204];
+        mem[0x200..].copy_from_slice(&[0x00, 0x11, 0x00, 0xE0]);
+        coverage.execute(0x200);
+        coverage.execute(0x202);
+        let listing = coverage.annotated(&mem, 0x200, 0x204, Variant::Megachip);
+        assert!(listing.lines().all(|line| !line.contains('\x1b')));
+        assert!(!listing.contains("UNKNOWN"));
+    }
+}
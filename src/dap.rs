@@ -0,0 +1,804 @@
+use std::{
+    collections::HashSet,
+    error::Error,
+    fs,
+    io::{self, BufRead, BufReader, Read, Write},
+    path::Path,
+    sync::mpsc::{self, RecvTimeoutError},
+    thread,
+    time::Duration,
+};
+
+use clap::ValueEnum;
+use serde_json::{json, Value};
+
+use crate::{
+    chip::{Chip, Fault},
+    database::Database,
+    dump,
+    instructions::Instruction,
+    octo::{self, Program},
+    platform::Headless,
+    quirks::Profile,
+    variant::Variant,
+};
+
+const WIDTH: usize = 64;
+const HEIGHT: usize = 32;
+const CYCLES_PER_FRAME: usize = 11;
+const FRAME: Duration = Duration::from_nanos(1_000_000_000 / 60);
+
+const THREAD: u64 = 1;
+// The disassembly of the loaded rom, served to the editor as the program's
+// source. Line n holds the instruction at `start + 2 * (n - 1)`. Line
+// breakpoints go in this listing or, for a program launched from Octo source,
+// in that source.
+const LISTING: u64 = 1;
+// The most instructions one disassemble request returns, all of 4K.
+const MAX_DISASSEMBLY: i64 = 0x800;
+const REGISTERS: u64 = 1;
+const STACK: u64 = 2;
+
+/// Where a `next` or `stepOut` stops, checked after every instruction.
+enum Until {
+    Return { sp: usize },
+    Reach { pc: u16, sp: usize },
+}
+
+pub struct Session {
+    chip: Option<Chip>,
+    name: String,
+    // The Octo source the program was assembled from, if it was.
+    source: Option<(String, Program)>,
+    buffer: Vec<u32>,
+    line_breakpoints: HashSet<u16>,
+    source_breakpoints: HashSet<u16>,
+    instruction_breakpoints: HashSet<u16>,
+    stop_on_entry: bool,
+    running: bool,
+    until: Option<Until>,
+    done: bool,
+    seq: u64,
+}
+
+impl Default for Session {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Session {
+    pub fn new() -> Session {
+        Session {
+            chip: None,
+            name: String::new(),
+            source: None,
+            buffer: vec![0u32; WIDTH * HEIGHT],
+            line_breakpoints: HashSet::new(),
+            source_breakpoints: HashSet::new(),
+            instruction_breakpoints: HashSet::new(),
+            stop_on_entry: false,
+            running: false,
+            until: None,
+            done: false,
+            seq: 0,
+        }
+    }
+
+    pub fn is_running(&self) -> bool {
+        self.running
+    }
+
+    pub fn is_done(&self) -> bool {
+        self.done
+    }
+
+    /// Answers one request, returning its response and any events it causes.
+    pub fn handle(&mut self, request: &Value) -> Vec<Value> {
+        let command = request["command"].as_str().unwrap_or_default();
+        let args = &request["arguments"];
+        let mut events = Vec::new();
+
+        let body = match command {
+            "initialize" => {
+                events.push(self.event("initialized", json!({})));
+                Ok(json!({
+                    "supportsConfigurationDoneRequest": true,
+                    "supportsDisassembleRequest": true,
+                    "supportsInstructionBreakpoints": true,
+                    "supportsSteppingGranularity": true,
+                }))
+            }
+            "launch" => self.launch(args),
+            "setBreakpoints" => Ok(self.set_breakpoints(args)),
+            "setInstructionBreakpoints" => Ok(self.set_instruction_breakpoints(args)),
+            "setExceptionBreakpoints" => Ok(json!({})),
+            "configurationDone" => {
+                if self.stop_on_entry {
+                    events.push(self.stopped("entry"));
+                } else {
+                    self.running = true;
+                }
+                Ok(json!({}))
+            }
+            "threads" => Ok(json!({ "threads": [{ "id": THREAD, "name": self.name }] })),
+            "stackTrace" => self.chip().map(|_| self.stack_trace()),
+            "scopes" => Ok(json!({ "scopes": [
+                { "name": "Registers", "variablesReference": REGISTERS, "expensive": false },
+                { "name": "Stack", "variablesReference": STACK, "expensive": false },
+            ] })),
+            "variables" => self
+                .chip()
+                .map(|chip| variables(chip, args["variablesReference"].as_u64())),
+            "source" => self
+                .chip()
+                .map(|chip| json!({ "content": listing(chip), "mimeType": "text/x-chip8-asm" })),
+            "disassemble" => self.chip().and_then(|chip| disassemble(chip, args)),
+            "continue" => {
+                self.until = None;
+                self.running = true;
+                Ok(json!({ "allThreadsContinued": true }))
+            }
+            "next" | "stepIn" | "stepOut" => match self.chip.as_mut() {
+                Some(chip) => {
+                    let opcode = at(chip, chip.pc).map_or(0, |i| i.opcode);
+                    let until = match command {
+                        "stepOut" if chip.sp > 0 => Some(Until::Return { sp: chip.sp }),
+                        "next" if opcode >> 12 == 0x2 => Some(Until::Reach {
+                            pc: chip.pc + 2,
+                            sp: chip.sp,
+                        }),
+                        _ => None,
+                    };
+                    match until {
+                        Some(until) => {
+                            self.until = Some(until);
+                            self.running = true;
+                        }
+                        None => {
+                            let result = chip.step(&mut self.buffer, &Headless::default());
+                            events.append(&mut self.code_write_events());
+                            events.push(match result {
+                                Ok(()) => self.stopped("step"),
+                                Err(fault) => self.exception(fault),
+                            });
+                        }
+                    }
+                    Ok(json!({}))
+                }
+                None => Err("no rom is loaded".to_string()),
+            },
+            "pause" => {
+                self.running = false;
+                self.until = None;
+                events.push(self.stopped("pause"));
+                Ok(json!({}))
+            }
+            "disconnect" | "terminate" => {
+                self.done = true;
+                self.running = false;
+                events.push(self.event("terminated", json!({})));
+                Ok(json!({}))
+            }
+            _ => Err(format!("unsupported request {command}")),
+        };
+
+        let mut messages = vec![self.response(request, body)];
+        messages.append(&mut events);
+        messages.into_iter().map(|m| self.number(m)).collect()
+    }
+
+    /// Runs one 60 Hz frame, stopping early at a breakpoint or when a step
+    /// finishes, and returns the events that came up along the way.
+    pub fn run_frame(&mut self) -> Vec<Value> {
+        let mut events = Vec::new();
+        for _ in 0..CYCLES_PER_FRAME {
+            let Some(chip) = self.chip.as_mut() else {
+                return events;
+            };
+            if let Err(fault) = chip.step(&mut self.buffer, &Headless::default()) {
+                self.running = false;
+                self.until = None;
+                events.append(&mut self.code_write_events());
+                events.push(self.exception(fault));
+                break;
+            }
+            let reason = match self.until {
+                Some(Until::Return { sp }) if chip.sp < sp => Some("step"),
+                Some(Until::Reach { pc, sp }) if chip.pc == pc && chip.sp == sp => Some("step"),
+                _ if self.line_breakpoints.contains(&chip.pc)
+                    || self.source_breakpoints.contains(&chip.pc) =>
+                {
+                    Some("breakpoint")
+                }
+                _ if self.instruction_breakpoints.contains(&chip.pc) => {
+                    Some("instruction breakpoint")
+                }
+                _ => None,
+            };
+
+            events.append(&mut self.code_write_events());
+            if let Some(reason) = reason {
+                self.running = false;
+                self.until = None;
+                events.push(self.stopped(reason));
+                break;
+            }
+        }
+        if self.running {
+            if let Some(chip) = self.chip.as_mut() {
+                chip.tick_timers();
+            }
+        }
+        events.into_iter().map(|e| self.number(e)).collect()
+    }
+
+    fn code_write_events(&mut self) -> Vec<Value> {
+        let writes = self
+            .chip
+            .as_mut()
+            .and_then(|chip| chip.code_writes.as_mut())
+            .map(std::mem::take)
+            .unwrap_or_default();
+        writes
+            .iter()
+            .map(|w| {
+                self.event(
+                    "output",
+                    json!({
+                        "category": "console",
+                        "output": format!(
+                            "self-modifying code: {:#05X} wrote to {:#05X}\n",
+                            w.pc, w.address
+                        ),
+                    }),
+                )
+            })
+            .collect()
+    }
+
+    fn launch(&mut self, args: &Value) -> Result<Value, String> {
+        let program = args["program"].as_str().ok_or("launch needs a `program`")?;
+        let variant = args["variant"]
+            .as_str()
+            .map(|name| Variant::from_str(name, true))
+            .transpose()?;
+        let quirks = args["quirks"]
+            .as_str()
+            .map(|name| Profile::from_str(name, true))
+            .transpose()?;
+        let mut chip = Chip::new();
+        chip.trace = false;
+        chip.code_writes = Some(Vec::new());
+        chip.variant = variant.unwrap_or_default();
+        chip.start = chip.variant.start();
+        self.source = None;
+        if program.ends_with(".8o") {
+            let source = fs::read_to_string(program).map_err(|e| e.to_string())?;
+            let assembled = octo::assemble(&source).map_err(|e| format!("{program}: {e}"))?;
+            chip.load_bytes(&assembled.rom).map_err(|e| e.to_string())?;
+            self.source = Some((program.to_string(), assembled));
+        } else {
+            chip.load(program).map_err(|e| e.to_string())?;
+        }
+        // The same settings `emulate` picks: the rom database's, or with
+        // `auto` those the rom's code points to.
+        let auto = args["auto"].as_bool().unwrap_or(false);
+        Database::bundled()
+            .settle(&mut chip, auto, variant.is_some(), None)
+            .map_err(|e| e.to_string())?;
+        if let Some(quirks) = quirks {
+            chip.quirks = quirks.quirks();
+        }
+
+        self.name = Path::new(program)
+            .file_name()
+            .map(|n| n.to_string_lossy().into_owned())
+            .unwrap_or_else(|| program.to_string());
+        self.stop_on_entry = args["stopOnEntry"].as_bool().unwrap_or(false);
+        let (width, height) = chip.variant.size();
+        self.buffer = vec![0u32; width * height];
+        self.chip = Some(chip);
+        Ok(json!({}))
+    }
+
+    fn set_breakpoints(&mut self, args: &Value) -> Value {
+        let (start, end) = self.chip.as_ref().map_or((0x200, 0x200), |chip| {
+            (
+                chip.start as u64,
+                chip.start as u64 + chip.rom().len() as u64,
+            )
+        });
+        let listing = args["source"]["sourceReference"].as_u64() == Some(LISTING);
+        let program = self.source.as_ref().and_then(|(path, program)| {
+            let source = args["source"]["path"].as_str()?;
+            same_file(source, path).then_some(program)
+        });
+        let breakpoints = if listing {
+            &mut self.line_breakpoints
+        } else {
+            &mut self.source_breakpoints
+        };
+        breakpoints.clear();
+        let lines: Vec<Value> = args["breakpoints"]
+            .as_array()
+            .into_iter()
+            .flatten()
+            .map(|bp| {
+                let line = bp["line"].as_u64().unwrap_or(0);
+                let address = if listing {
+                    line.checked_sub(1)
+                        .map(|n| start + 2 * n)
+                        .filter(|&a| a < end)
+                        .map(|a| a as u16)
+                } else if let Some(program) = program {
+                    program.address(line as usize)
+                } else {
+                    return json!({
+                        "verified": false,
+                        "line": line,
+                        "message": "breakpoints go in the disassembly listing or the launched Octo source",
+                    });
+                };
+                match address {
+                    Some(address) => {
+                        breakpoints.insert(address);
+                        json!({ "verified": true, "line": line })
+                    }
+                    None => json!({ "verified": false, "line": line }),
+                }
+            })
+            .collect();
+        json!({ "breakpoints": lines })
+    }
+
+    fn set_instruction_breakpoints(&mut self, args: &Value) -> Value {
+        self.instruction_breakpoints.clear();
+        let breakpoints: Vec<Value> = args["breakpoints"]
+            .as_array()
+            .into_iter()
+            .flatten()
+            .map(|bp| {
+                let address = bp["instructionReference"]
+                    .as_str()
+                    .and_then(parse_address)
+                    .map(|a| a as i64 + bp["offset"].as_i64().unwrap_or(0))
+                    .filter(|a| (0..0x1000).contains(a));
+                match address {
+                    Some(address) => {
+                        self.instruction_breakpoints.insert(address as u16);
+                        json!({ "verified": true, "instructionReference": format!("{address:#05X}") })
+                    }
+                    None => json!({ "verified": false }),
+                }
+            })
+            .collect();
+        json!({ "breakpoints": breakpoints })
+    }
+
+    fn stack_trace(&self) -> Value {
+        let Some(chip) = &self.chip else {
+            return json!({ "stackFrames": [], "totalFrames": 0 });
+        };
+        let calls = chip.stack[..chip.sp.min(chip.stack.len())]
+            .iter()
+            .rev()
+            .map(|&ret| ret.wrapping_sub(2));
+        let frames: Vec<Value> = std::iter::once(chip.pc)
+            .chain(calls)
+            .enumerate()
+            .map(|(id, pc)| {
+                let (source, line) = self.location(chip, pc);
+                json!({
+                    "id": id,
+                    "name": at(chip, pc).map(|i| dump::mnemonic_for(&i, chip.variant)).unwrap_or_default(),
+                    "source": source,
+                    "line": line,
+                    "column": 1,
+                    "instructionPointerReference": format!("{pc:#05X}"),
+                })
+            })
+            .collect();
+        json!({ "totalFrames": frames.len(), "stackFrames": frames })
+    }
+
+    // An instruction's line in the Octo source it came from, or else in the
+    // listing.
+    fn location(&self, chip: &Chip, pc: u16) -> (Value, u64) {
+        if let Some((path, program)) = &self.source {
+            if let Some(line) = program.line(pc) {
+                return (json!({ "name": self.name, "path": path }), line as u64);
+            }
+        }
+        (
+            json!({ "name": format!("{}.s", self.name), "sourceReference": LISTING }),
+            line(chip, pc),
+        )
+    }
+
+    fn chip(&self) -> Result<&Chip, String> {
+        self.chip.as_ref().ok_or("no rom is loaded".to_string())
+    }
+
+    fn stopped(&self, reason: &str) -> Value {
+        self.event(
+            "stopped",
+            json!({ "reason": reason, "threadId": THREAD, "allThreadsStopped": true }),
+        )
+    }
+
+    fn exception(&self, fault: Fault) -> Value {
+        self.event(
+            "stopped",
+            json!({
+                "reason": "exception",
+                "description": fault.to_string(),
+                "threadId": THREAD,
+                "allThreadsStopped": true,
+            }),
+        )
+    }
+
+    fn number(&mut self, mut message: Value) -> Value {
+        self.seq += 1;
+        message["seq"] = json!(self.seq);
+        message
+    }
+
+    fn event(&self, event: &str, body: Value) -> Value {
+        json!({ "type": "event", "event": event, "body": body })
+    }
+
+    fn response(&self, request: &Value, body: Result<Value, String>) -> Value {
+        let mut response = json!({
+            "type": "response",
+            "request_seq": request["seq"],
+            "command": request["command"],
+            "success": body.is_ok(),
+        });
+        match body {
+            Ok(body) => response["body"] = body,
+            Err(message) => response["message"] = json!(message),
+        }
+        response
+    }
+}
+
+/// Speaks the debug adapter protocol over `input` and `output` until the
+/// editor disconnects.
+pub fn serve(
+    input: impl Read + Send + 'static,
+    mut output: impl Write,
+) -> Result<(), Box<dyn Error>> {
+    let (tx, rx) = mpsc::channel();
+    thread::spawn(move || {
+        let mut input = BufReader::new(input);
+        while let Ok(Some(message)) = read_message(&mut input) {
+            if tx.send(message).is_err() {
+                break;
+            }
+        }
+    });
+
+    let mut session = Session::new();
+    while !session.is_done() {
+        let request = if session.is_running() {
+            match rx.recv_timeout(FRAME) {
+                Ok(request) => Some(request),
+                Err(RecvTimeoutError::Timeout) => None,
+                Err(RecvTimeoutError::Disconnected) => break,
+            }
+        } else {
+            match rx.recv() {
+                Ok(request) => Some(request),
+                Err(_) => break,
+            }
+        };
+
+        let messages = match request {
+            Some(request) => session.handle(&request),
+            None => session.run_frame(),
+        };
+        for message in messages {
+            write_message(&mut output, &message)?;
+        }
+    }
+    Ok(())
+}
+
+fn read_message(input: &mut impl BufRead) -> io::Result<Option<Value>> {
+    let mut length = None;
+    loop {
+        let mut header = String::new();
+        if input.read_line(&mut header)? == 0 {
+            return Ok(None);
+        }
+        let header = header.trim();
+        if header.is_empty() {
+            break;
+        }
+        if let Some(value) = header.strip_prefix("Content-Length:") {
+            length = value.trim().parse::<usize>().ok();
+        }
+    }
+
+    let length = length.ok_or_else(|| io::Error::other("missing Content-Length"))?;
+    let mut body = vec![0u8; length];
+    input.read_exact(&mut body)?;
+    Ok(Some(serde_json::from_slice(&body)?))
+}
+
+fn write_message(output: &mut impl Write, message: &Value) -> io::Result<()> {
+    let body = message.to_string();
+    write!(output, "Content-Length: {}\r\n\r\n{body}", body.len())?;
+    output.flush()
+}
+
+fn at(chip: &Chip, pc: u16) -> Option<Instruction> {
+    let bytes = chip.mem.get(pc as usize..pc as usize + 2)?;
+    Some(Instruction::new(bytes))
+}
+
+fn same_file(a: &str, b: &str) -> bool {
+    match (fs::canonicalize(a), fs::canonicalize(b)) {
+        (Ok(a), Ok(b)) => a == b,
+        _ => a == b,
+    }
+}
+
+fn line(chip: &Chip, pc: u16) -> u64 {
+    (pc.saturating_sub(chip.start) / 2) as u64 + 1
+}
+
+fn listing(chip: &Chip) -> String {
+    let end = chip.start as usize + chip.rom().len();
+    (chip.start as usize..end)
+        .step_by(2)
+        .filter_map(|pc| {
+            let instruction = at(chip, pc as u16)?;
+            Some(format!(
+                "{pc:04X}  {:04X}  {}\n",
+                instruction.opcode,
+                dump::mnemonic_for(&instruction, chip.variant)
+            ))
+        })
+        .collect()
+}
+
+fn variables(chip: &Chip, reference: Option<u64>) -> Value {
+    let variable = |name: String, value: String| json!({ "name": name, "value": value, "variablesReference": 0 });
+    let variables: Vec<Value> = match reference {
+        Some(REGISTERS) => chip
+            .v
+            .iter()
+            .enumerate()
+            .map(|(n, v)| variable(format!("V{n:X}"), format!("{v:#04X}")))
+            .chain([
+                variable("I".to_string(), format!("{:#05X}", chip.i)),
+                variable("PC".to_string(), format!("{:#05X}", chip.pc)),
+                variable("SP".to_string(), chip.sp.to_string()),
+                variable("DT".to_string(), format!("{:#04X}", chip.dt)),
+                variable("ST".to_string(), format!("{:#04X}", chip.st)),
+            ])
+            .collect(),
+        Some(STACK) => chip.stack[..chip.sp.min(chip.stack.len())]
+            .iter()
+            .enumerate()
+            .map(|(n, ret)| variable(format!("[{n}]"), format!("{ret:#05X}")))
+            .collect(),
+        _ => Vec::new(),
+    };
+    json!({ "variables": variables })
+}
+
+fn disassemble(chip: &Chip, args: &Value) -> Result<Value, String> {
+    let base = args["memoryReference"]
+        .as_str()
+        .and_then(parse_address)
+        .ok_or("bad memoryReference")? as i64;
+    let start = base
+        .saturating_add(args["offset"].as_i64().unwrap_or(0))
+        .saturating_add(2i64.saturating_mul(args["instructionOffset"].as_i64().unwrap_or(0)));
+    let count = args["instructionCount"]
+        .as_i64()
+        .unwrap_or(0)
+        .min(MAX_DISASSEMBLY);
+
+    let instructions: Vec<Value> = (0..count)
+        .map(|n| start.saturating_add(2 * n))
+        .map(|address| {
+            let instruction = u16::try_from(address).ok().and_then(|pc| at(chip, pc));
+            match instruction {
+                Some(instruction) => json!({
+                    "address": format!("{address:#05X}"),
+                    "instructionBytes": format!("{:04X}", instruction.opcode),
+                    "instruction": dump::mnemonic_for(&instruction, chip.variant),
+                    "line": line(chip, address as u16),
+                }),
+                // The editor asks for whole pages around the program counter,
+                // which can run past either end of memory.
+                None => json!({
+                    "address": format!("{address:#05X}"),
+                    "instruction": "??",
+                    "presentationHint": "invalid",
+                }),
+            }
+        })
+        .collect();
+    Ok(json!({ "instructions": instructions }))
+}
+
+fn parse_address(s: &str) -> Option<u16> {
+    u16::from_str_radix(s.trim_start_matches("0x").trim_start_matches("0X"), 16).ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::{json, Value};
+
+    use super::Session;
+    use crate::{quirks::Profile, variant::Variant};
+
+    fn request(seq: u64, command: &str, arguments: Value) -> Value {
+        json!({ "seq": seq, "type": "request", "command": command, "arguments": arguments })
+    }
+
+    fn launched() -> Session {
+        let mut session = Session::new();
+        session.handle(&request(1, "initialize", json!({})));
+        let program = format!("{}/roms/IBM_Logo.ch8", env!("CARGO_MANIFEST_DIR"));
+        let messages = session.handle(&request(
+            2,
+            "launch",
+            json!({ "program": program, "stopOnEntry": true }),
+        ));
+        assert_eq!(messages[0]["success"], true);
+        session
+    }
+
+    #[test]
+    fn test_stop_on_entry() {
+        let mut session = launched();
+
+        let messages = session.handle(&request(3, "configurationDone", json!({})));
+
+        assert_eq!(messages[1]["event"], "stopped");
+        assert_eq!(messages[1]["body"]["reason"], "entry");
+        assert!(!session.is_running());
+    }
+
+    #[test]
+    fn test_line_breakpoint() {
+        let mut session = launched();
+        session.handle(&request(
+            3,
+            "setBreakpoints",
+            json!({ "source": { "sourceReference": 1 }, "breakpoints": [{ "line": 4 }] }),
+        ));
+        session.handle(&request(4, "continue", json!({ "threadId": 1 })));
+
+        let stopped = (0..10)
+            .flat_map(|_| session.run_frame())
+            .find(|e| e["event"] == "stopped")
+            .unwrap();
+        assert_eq!(stopped["body"]["reason"], "breakpoint");
+
+        let trace = session.handle(&request(5, "stackTrace", json!({ "threadId": 1 })));
+        assert_eq!(trace[0]["body"]["stackFrames"][0]["line"], 4);
+        assert_eq!(
+            trace[0]["body"]["stackFrames"][0]["instructionPointerReference"],
+            "0x206"
+        );
+    }
+
+    #[test]
+    fn test_breakpoints_outside_the_listing() {
+        let mut session = launched();
+
+        let messages = session.handle(&request(
+            3,
+            "setBreakpoints",
+            json!({ "source": { "path": "ibm.8o" }, "breakpoints": [{ "line": 4 }] }),
+        ));
+        assert_eq!(messages[0]["body"]["breakpoints"][0]["verified"], false);
+        // The IBM logo is 66 instructions long.
+        let messages = session.handle(&request(
+            4,
+            "setBreakpoints",
+            json!({ "source": { "sourceReference": 1 }, "breakpoints": [{ "line": 66 }, { "line": 67 }] }),
+        ));
+        let breakpoints = &messages[0]["body"]["breakpoints"];
+        assert_eq!(breakpoints[0]["verified"], true);
+        assert_eq!(breakpoints[1]["verified"], false);
+    }
+
+    #[test]
+    fn test_octo_source_breakpoint() {
+        let mut session = Session::new();
+        let program = format!("{}/roms/vf-order.8o", env!("CARGO_MANIFEST_DIR"));
+        session.handle(&request(1, "launch", json!({ "program": program })));
+
+        // Line 24 is the first instruction of `check`.
+        let messages = session.handle(&request(
+            2,
+            "setBreakpoints",
+            json!({ "source": { "path": program }, "breakpoints": [{ "line": 24 }, { "line": 22 }] }),
+        ));
+        let breakpoints = &messages[0]["body"]["breakpoints"];
+        assert_eq!(breakpoints[0]["verified"], true);
+        assert_eq!(breakpoints[1]["verified"], false);
+        session.handle(&request(3, "continue", json!({ "threadId": 1 })));
+
+        let stopped = (0..10)
+            .flat_map(|_| session.run_frame())
+            .find(|e| e["event"] == "stopped")
+            .unwrap();
+        assert_eq!(stopped["body"]["reason"], "breakpoint");
+
+        let trace = session.handle(&request(4, "stackTrace", json!({ "threadId": 1 })));
+        let frames = &trace[0]["body"]["stackFrames"];
+        assert_eq!(frames[0]["line"], 24);
+        assert_eq!(frames[0]["source"]["path"], program.as_str());
+        // The caller is the first `check`, on line 13.
+        assert_eq!(frames[1]["line"], 13);
+    }
+
+    #[test]
+    fn test_launch_settings() {
+        let mut session = Session::new();
+        let program = format!("{}/roms/IBM_Logo.ch8", env!("CARGO_MANIFEST_DIR"));
+        let messages = session.handle(&request(
+            1,
+            "launch",
+            json!({ "program": program, "variant": "chip8x", "quirks": "schip" }),
+        ));
+        assert_eq!(messages[0]["success"], true);
+        let chip = session.chip.as_ref().unwrap();
+        assert_eq!(chip.variant, Variant::Chip8x);
+        assert_eq!(chip.quirks, Profile::Schip.quirks());
+
+        let messages = session.handle(&request(
+            2,
+            "launch",
+            json!({ "program": program, "variant": "chip9" }),
+        ));
+        assert_eq!(messages[0]["success"], false);
+    }
+
+    #[test]
+    fn test_step_and_registers() {
+        let mut session = launched();
+        session.handle(&request(3, "next", json!({ "threadId": 1 })));
+        session.handle(&request(4, "next", json!({ "threadId": 1 })));
+
+        let messages = session.handle(&request(5, "variables", json!({ "variablesReference": 1 })));
+        let variables = &messages[0]["body"]["variables"];
+        assert_eq!(variables[16]["name"], "I");
+        assert_eq!(variables[16]["value"], "0x22A");
+        assert_eq!(variables[17]["value"], "0x204");
+    }
+
+    #[test]
+    fn test_disassemble() {
+        let mut session = launched();
+
+        let messages = session.handle(&request(
+            3,
+            "disassemble",
+            json!({ "memoryReference": "0x200", "instructionCount": 2 }),
+        ));
+
+        let instructions = &messages[0]["body"]["instructions"];
+        assert_eq!(instructions[1]["address"], "0x202");
+        assert_eq!(instructions[1]["instructionBytes"], "A22A");
+
+        let messages = session.handle(&request(
+            4,
+            "disassemble",
+            json!({ "memoryReference": "0x200", "instructionOffset": i64::MAX, "instructionCount": i64::MAX }),
+        ));
+        let instructions = messages[0]["body"]["instructions"].as_array().unwrap();
+        assert_eq!(instructions.len(), 0x800);
+        assert_eq!(instructions[0]["presentationHint"], "invalid");
+    }
+}
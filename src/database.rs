@@ -0,0 +1,252 @@
+use std::{collections::HashMap, error::Error, path::Path};
+
+use serde_json::Value;
+
+use crate::{
+    chip::{Chip, LoadError},
+    config::parse_colour,
+    detect::{self, Report},
+    quirks::{Profile, Quirks},
+    variant::Variant,
+};
+
+// The roms in `roms/`, in the format of the CHIP-8 community database's
+// programs.json, which `Database::load` reads in full.
+const BUNDLED: &str = include_str!("../roms/programs.json");
+
+/// Controls the database can bind to CHIP-8 keys, for the first player.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Action {
+    Up,
+    Down,
+    Left,
+    Right,
+    A,
+    B,
+}
+
+/// What the database says about one rom.
+#[derive(Clone, Debug, PartialEq)]
+pub struct RomInfo {
+    pub title: String,
+    pub authors: Vec<String>,
+    /// The community database's id of the platform the rom is run as, such
+    /// as "superchip".
+    pub platform: String,
+    pub variant: Variant,
+    pub quirks: Quirks,
+    /// Instructions per frame.
+    pub tickrate: Option<u32>,
+    /// Background and foreground.
+    pub colours: Option<[u32; 2]>,
+    pub keys: Vec<(Action, u8)>,
+}
+
+impl RomInfo {
+    /// "Title by Author" for window captions.
+    pub fn caption(&self) -> String {
+        if self.authors.is_empty() {
+            self.title.clone()
+        } else {
+            format!("{} by {}", self.title, self.authors.join(", "))
+        }
+    }
+}
+
+/// Where `Database::settle` found a rom's variant and quirks.
+pub enum Found {
+    Database(RomInfo),
+    Analysis(Report),
+}
+
+/// Roms by the SHA-1 of their contents.
+pub struct Database {
+    roms: HashMap<String, RomInfo>,
+}
+
+impl Database {
+    pub fn bundled() -> Database {
+        Database::parse(BUNDLED).expect("the bundled database is valid")
+    }
+
+    pub fn load(path: &Path) -> Result<Database, Box<dyn Error>> {
+        Database::parse(&std::fs::read_to_string(path)?)
+    }
+
+    /// Reads a programs.json: a list of programs, each with its roms keyed
+    /// by SHA-1.
+    pub fn parse(text: &str) -> Result<Database, Box<dyn Error>> {
+        let programs: Value = serde_json::from_str(text)?;
+        let programs = programs.as_array().ok_or("expected a list of programs")?;
+        let mut roms = HashMap::new();
+        for program in programs {
+            let title = program["title"].as_str().unwrap_or("Untitled");
+            let authors: Vec<String> = program["authors"]
+                .as_array()
+                .into_iter()
+                .flatten()
+                .filter_map(|author| Some(author.as_str()?.to_string()))
+                .collect();
+            for (hash, rom) in program["roms"].as_object().into_iter().flatten() {
+                let info = rom_info(title, &authors, rom);
+                roms.insert(hash.to_lowercase(), info);
+            }
+        }
+        Ok(Database { roms })
+    }
+
+    pub fn lookup(&self, rom: &[u8]) -> Option<&RomInfo> {
+        self.roms
+            .get(&sha1_smol::Sha1::from(rom).digest().to_string())
+    }
+
+    /// Gives the rom loaded in `chip` the variant and quirks this database
+    /// lists for it or, with `auto`, those its code points to. The rom is
+    /// reloaded for the variant found, unless `keep_variant`.
+    pub fn settle(
+        &self,
+        chip: &mut Chip,
+        auto: bool,
+        keep_variant: bool,
+        load_address: Option<u16>,
+    ) -> Result<Option<Found>, LoadError> {
+        let found = match self.lookup(chip.rom()) {
+            Some(info) => Found::Database(info.clone()),
+            None if auto => Found::Analysis(detect::analyze(chip.rom(), chip.start)),
+            None => return Ok(None),
+        };
+        let (variant, quirks) = match &found {
+            Found::Database(info) => (info.variant, info.quirks),
+            Found::Analysis(report) => (report.dialect.variant(), report.quirks),
+        };
+        chip.quirks = quirks;
+        if !keep_variant && variant != chip.variant {
+            let rom = chip.rom().to_vec();
+            chip.variant = variant;
+            chip.start = load_address.unwrap_or(variant.start());
+            chip.load_bytes(&rom)?;
+        }
+        Ok(Some(found))
+    }
+}
+
+fn rom_info(title: &str, authors: &[String], rom: &Value) -> RomInfo {
+    // The first platform listed is the one the rom is meant for.
+    let platform = rom["platforms"][0].as_str().unwrap_or("originalChip8");
+    let (variant, profile) = platform_settings(platform);
+    let mut quirks = profile.quirks();
+    let overrides = &rom["quirkyPlatforms"][platform];
+    let quirk = |name: &str| overrides[name].as_bool();
+    if let Some(shift) = quirk("shift") {
+        quirks.shift = shift;
+    }
+    if let Some(logic) = quirk("logic") {
+        quirks.vf_reset = logic;
+    }
+    if quirk("memoryLeaveIUnchanged") == Some(true) {
+        quirks.memory = false;
+    } else if quirk("memoryIncrementByX") == Some(true) {
+        quirks.memory = true;
+    }
+    if let Some(jump) = quirk("jump") {
+        quirks.jumping = jump;
+    }
+    if let Some(wrap) = quirk("wrap") {
+        quirks.clipping = !wrap;
+    }
+
+    let colour = |value: &Value| parse_colour(value.as_str()?);
+    let colours = rom["colors"]["pixels"]
+        .as_array()
+        .and_then(|pixels| Some([colour(pixels.first()?)?, colour(pixels.get(1)?)?]));
+    let keys = rom["keys"]
+        .as_object()
+        .into_iter()
+        .flatten()
+        .filter_map(|(name, key)| {
+            let action = match name.as_str() {
+                "up" => Action::Up,
+                "down" => Action::Down,
+                "left" => Action::Left,
+                "right" => Action::Right,
+                "a" => Action::A,
+                "b" => Action::B,
+                _ => return None,
+            };
+            Some((
+                action,
+                u8::try_from(key.as_u64()?).ok().filter(|&key| key < 16)?,
+            ))
+        })
+        .collect();
+
+    RomInfo {
+        title: title.to_string(),
+        authors: authors.to_vec(),
+        platform: platform.to_string(),
+        variant,
+        quirks,
+        tickrate: rom["tickrate"].as_u64().map(|rate| rate as u32),
+        colours,
+        keys,
+    }
+}
+
+// SUPER-CHIP and XO-CHIP roms run as CHIP-8 with their quirks.
+fn platform_settings(platform: &str) -> (Variant, Profile) {
+    match platform {
+        "chip8x" => (Variant::Chip8x, Profile::Chip8),
+        "megachip8" => (Variant::Megachip, Profile::Schip),
+        "chip48" | "superchip1" | "superchip" => (Variant::Chip8, Profile::Schip),
+        "xochip" => (Variant::Chip8, Profile::Xochip),
+        _ => (Variant::Chip8, Profile::Chip8),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Action, Database};
+    use crate::{quirks::Profile, variant::Variant};
+
+    #[test]
+    fn test_bundled() {
+        let database = Database::bundled();
+        let rom = std::fs::read(format!(
+            "{}/roms/space_invaders.ch8",
+            env!("CARGO_MANIFEST_DIR")
+        ))
+        .unwrap();
+
+        let info = database.lookup(&rom).unwrap();
+
+        assert_eq!(info.caption(), "Space Invaders by David Winter");
+        assert_eq!(info.variant, Variant::Chip8);
+        assert!(info.quirks.shift);
+        assert!(info.keys.contains(&(Action::A, 5)));
+        assert!(database.lookup(&rom[1..]).is_none());
+    }
+
+    #[test]
+    fn test_parse() {
+        // The SHA-1 of an empty rom.
+        let database = Database::parse(
+            r##"[{"title": "Empty", "roms": {"DA39A3EE5E6B4B0D3255BFEF95601890AFD80709": {
+                "platforms": ["superchip", "xochip"],
+                "tickrate": 30,
+                "colors": {"pixels": ["#101010", "#F0E000"]},
+                "keys": {"up": 2, "player2Up": 3, "down": 99}
+            }}}]"##,
+        )
+        .unwrap();
+
+        let info = database.lookup(&[]).unwrap();
+
+        assert_eq!(info.caption(), "Empty");
+        assert_eq!(info.platform, "superchip");
+        assert_eq!(info.quirks, Profile::Schip.quirks());
+        assert_eq!(info.tickrate, Some(30));
+        assert_eq!(info.colours, Some([0x101010, 0xF0E000]));
+        assert_eq!(info.keys, [(Action::Up, 2)]);
+        assert!(Database::parse("{}").is_err());
+    }
+}
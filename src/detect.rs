@@ -0,0 +1,348 @@
+use std::fmt::Display;
+
+use clap::ValueEnum;
+
+use crate::{
+    op::Op,
+    quirks::{Profile, Quirks},
+    variant::Variant,
+};
+
+/// The platforms a rom can be written for, from the least to the most
+/// specific, so the strongest evidence wins.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Dialect {
+    Chip8,
+    Hires,
+    Chip8x,
+    Schip,
+    Xochip,
+    Megachip,
+}
+
+impl Dialect {
+    pub fn name(self) -> &'static str {
+        match self {
+            Dialect::Chip8 => "CHIP-8",
+            Dialect::Hires => "hi-res CHIP-8",
+            Dialect::Chip8x => "CHIP-8X",
+            Dialect::Schip => "SUPER-CHIP",
+            Dialect::Xochip => "XO-CHIP",
+            Dialect::Megachip => "MegaChip",
+        }
+    }
+
+    /// The variant to run the rom as. SUPER-CHIP and XO-CHIP have no variant
+    /// of their own yet, so only their quirks apply.
+    pub fn variant(self) -> Variant {
+        match self {
+            Dialect::Chip8 | Dialect::Schip | Dialect::Xochip => Variant::Chip8,
+            Dialect::Hires => Variant::Hires,
+            Dialect::Chip8x => Variant::Chip8x,
+            Dialect::Megachip => Variant::Megachip,
+        }
+    }
+
+    /// MegaChip builds on SUPER-CHIP and keeps its quirks.
+    pub fn profile(self) -> Profile {
+        match self {
+            Dialect::Chip8 | Dialect::Hires | Dialect::Chip8x => Profile::Chip8,
+            Dialect::Schip | Dialect::Megachip => Profile::Schip,
+            Dialect::Xochip => Profile::Xochip,
+        }
+    }
+}
+
+/// What `analyze` found: the platform it settled on, the quirks to run it
+/// with and why.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Report {
+    pub dialect: Dialect,
+    /// The platform's quirks, changed where the code shows it needs others.
+    pub quirks: Quirks,
+    pub reasons: Vec<String>,
+}
+
+impl Display for Report {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let dialect = self.dialect;
+        let variant = dialect.variant().to_possible_value();
+        let changed = if self.quirks == dialect.profile().quirks() {
+            ""
+        } else {
+            ", changed as below"
+        };
+        writeln!(
+            f,
+            "Looks like {}: the {} variant with {} quirks{changed}",
+            dialect.name(),
+            variant.as_ref().map_or("", |value| value.get_name()),
+            dialect.profile().name()
+        )?;
+        if matches!(dialect, Dialect::Schip | Dialect::Xochip) {
+            writeln!(f, "  {} instructions are not emulated", dialect.name())?;
+        }
+        self.reasons
+            .iter()
+            .try_for_each(|reason| writeln!(f, "  {reason}"))
+    }
+}
+
+/// Guesses the platform `rom`, loaded at `start`, was written for. Only code
+/// reachable from `start` is looked at, so sprites and other data that
+/// happen to look like instructions are not counted.
+pub fn analyze(rom: &[u8], start: u16) -> Report {
+    let mut dialect = Dialect::Chip8;
+    let mut reasons = Vec::new();
+    let mut entry = start;
+    let hires = start == Variant::Chip8.start() && Variant::detect(rom) == Some(Variant::Hires);
+    if hires {
+        dialect = Dialect::Hires;
+        reasons.push(format!("${start:03X}: 1260 starts the hi-res interpreter"));
+        entry = 0x2C0;
+    }
+
+    let code = reachable(rom, start, entry);
+    // The platform comes first, as what an instruction writes depends on it.
+    let platform = code
+        .iter()
+        .filter_map(|&address| platform_only(fetch(rom, start, address)?, hires))
+        .map(|(found, _)| found)
+        .fold(dialect, Dialect::max);
+    let written = written(rom, start, &code, platform.variant());
+    let mut changes: Vec<Change> = Vec::new();
+    let mut seen = Vec::new();
+    for &address in &code {
+        let opcode = fetch(rom, start, address).unwrap_or_default();
+        let found = platform_only(opcode, hires)
+            .map(|(found, what)| (Some(found), format!("{what} needs {}", found.name())))
+            .or_else(|| {
+                let (change, what) = quirk_pattern(opcode, &written)?;
+                changes.push(change);
+                Some((None, what))
+            });
+        let Some((found, what)) = found else {
+            continue;
+        };
+        dialect = dialect.max(found.unwrap_or(Dialect::Chip8));
+        // One line for each kind of evidence is enough.
+        if !seen.contains(&what) {
+            reasons.push(format!("${address:03X}: {opcode:04X} {what}"));
+            seen.push(what);
+        }
+    }
+    if reasons.is_empty() {
+        reasons.push(format!(
+            "{} instructions reachable, none specific to another platform",
+            code.len()
+        ));
+    }
+    // Quirks the code needs hold whatever the platform.
+    let mut quirks = dialect.profile().quirks();
+    for change in changes {
+        change(&mut quirks);
+    }
+    Report {
+        dialect,
+        quirks,
+        reasons,
+    }
+}
+
+fn fetch(rom: &[u8], start: u16, address: u16) -> Option<u16> {
+    let offset = address.checked_sub(start)? as usize;
+    let bytes = rom.get(offset..offset + 2)?;
+    Some(u16::from_be_bytes([bytes[0], bytes[1]]))
+}
+
+// Addresses of the instructions control can reach from `entry`, in order.
+// Computed jumps are assumed to land on the first entry of their table.
+fn reachable(rom: &[u8], start: u16, entry: u16) -> Vec<u16> {
+    let mut visited = vec![false; rom.len()];
+    let mut pending = vec![entry];
+    while let Some(address) = pending.pop() {
+        let Some(opcode) = fetch(rom, start, address) else {
+            continue;
+        };
+        let offset = (address - start) as usize;
+        if visited[offset] {
+            continue;
+        }
+        visited[offset] = true;
+        // F000 and MegaChip's 01NN carry a second word.
+        let long = |address| {
+            fetch(rom, start, address).is_some_and(|op| op == 0xF000 || op & 0xFF00 == 0x0100)
+        };
+        let next = address.wrapping_add(if long(address) { 4 } else { 2 });
+        match Op::decode(opcode) {
+            Op::Ret => {}
+            Op::Sys(0x0FD) => {}
+            Op::Jump(nnn) | Op::JumpOffset { nnn, .. } => pending.push(nnn),
+            Op::Call(nnn) => pending.extend([next, nnn]),
+            Op::SkipEqImm { .. }
+            | Op::SkipNeImm { .. }
+            | Op::SkipEq { .. }
+            | Op::SkipNe { .. }
+            | Op::SkipKey(_)
+            | Op::SkipNoKey(_) => {
+                let skipped = next.wrapping_add(if long(next) { 4 } else { 2 });
+                pending.extend([next, skipped]);
+            }
+            _ => pending.push(next),
+        }
+    }
+    (0..rom.len())
+        .filter(|&offset| visited[offset])
+        .map(|offset| start + offset as u16)
+        .collect()
+}
+
+// Instructions only one platform has, and what they do there. In a hi-res
+// rom 0230 clears the screen rather than loading a MegaChip palette.
+fn platform_only(opcode: u16, hires: bool) -> Option<(Dialect, &'static str)> {
+    let n = opcode & 0xF;
+    Some(match opcode {
+        0x0230 if hires => (Dialect::Hires, "(hi-res clear screen)"),
+        0x00C1..=0x00CF => (Dialect::Schip, "(scroll down)"),
+        0x00FB => (Dialect::Schip, "(scroll right)"),
+        0x00FC => (Dialect::Schip, "(scroll left)"),
+        0x00FD => (Dialect::Schip, "(exit)"),
+        0x00FE => (Dialect::Schip, "(low resolution)"),
+        0x00FF => (Dialect::Schip, "(high resolution)"),
+        0x00D1..=0x00DF => (Dialect::Xochip, "(scroll up)"),
+        0x0010 | 0x0011 => (Dialect::Megachip, "(MegaChip mode)"),
+        0x02A0 => (Dialect::Chip8x, "(background colour)"),
+        0x0100..=0x09FF => (Dialect::Megachip, "(colour sprites, sound)"),
+        0xF000 => (Dialect::Xochip, "(16-bit I)"),
+        0xF002 => (Dialect::Xochip, "(audio pattern)"),
+        _ => match (opcode >> 12, opcode & 0xFF) {
+            (0xD, _) if n == 0 => (Dialect::Schip, "(16x16 sprite)"),
+            (0x5, _) if n == 1 => (Dialect::Chip8x, "(colour add)"),
+            (0x5, _) if n == 2 || n == 3 => (Dialect::Xochip, "(register range)"),
+            (0xE, 0xF2) | (0xE, 0xF5) => (Dialect::Chip8x, "(second keypad)"),
+            (0xF, 0xF8) | (0xF, 0xFB) => (Dialect::Chip8x, "(I/O port)"),
+            (0xF, 0x30) => (Dialect::Schip, "(big font)"),
+            (0xF, 0x75) | (0xF, 0x85) => (Dialect::Schip, "(flag registers)"),
+            (0xF, 0x01) => (Dialect::Xochip, "(plane select)"),
+            (0xF, 0x3A) => (Dialect::Xochip, "(pitch)"),
+            _ => return None,
+        },
+    })
+}
+
+// Registers some reachable instruction sets.
+fn written(rom: &[u8], start: u16, code: &[u16], variant: Variant) -> [bool; 16] {
+    let mut written = [false; 16];
+    for &address in code {
+        let opcode = fetch(rom, start, address).unwrap_or_default();
+        match Op::decode_for(opcode, variant) {
+            Op::LoadImm { x, .. }
+            | Op::AddImm { x, .. }
+            | Op::Move { x, .. }
+            | Op::Or { x, .. }
+            | Op::And { x, .. }
+            | Op::Xor { x, .. }
+            | Op::Random { x, .. }
+            | Op::GetDelay(x)
+            | Op::WaitKey(x)
+            | Op::Input(x)
+            | Op::AddColour { x, .. } => written[x] = true,
+            Op::Add { x, .. }
+            | Op::Sub { x, .. }
+            | Op::SubReverse { x, .. }
+            | Op::ShiftRight { x, .. }
+            | Op::ShiftLeft { x, .. } => {
+                written[x] = true;
+                written[0xF] = true;
+            }
+            Op::Draw { .. } => written[0xF] = true,
+            Op::Load(x) => written[..=x].fill(true),
+            _ => {}
+        }
+    }
+    written
+}
+
+// A change to the quirks a rom needs.
+type Change = fn(&mut Quirks);
+
+// Instructions that only make sense with a quirk set, given the registers the
+// rom never sets, and the change to the quirks.
+fn quirk_pattern(opcode: u16, written: &[bool; 16]) -> Option<(Change, String)> {
+    match Op::decode(opcode) {
+        Op::ShiftRight { x, y } | Op::ShiftLeft { x, y } if x != y && !written[y] => Some((
+            |quirks| quirks.shift = true,
+            format!("shifts V{x:X} in place, as V{y:X} is never set, so the shift quirk is on"),
+        )),
+        Op::JumpOffset { x, .. } if x != 0 && !written[0] && written[x] => Some((
+            |quirks| quirks.jumping = true,
+            format!("jumps by V{x:X}, as V0 is never set, so the jumping quirk is on"),
+        )),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{analyze, Dialect};
+    use crate::{quirks::Profile, variant::Variant};
+
+    #[test]
+    fn test_analyze() {
+        // 00FF 1202: high resolution, then loop.
+        let report = analyze(&[0x00, 0xFF, 0x12, 0x02], 0x200);
+        assert_eq!(report.dialect, Dialect::Schip);
+        assert_eq!(report.dialect.profile(), Profile::Schip);
+        assert!(report.reasons[0].contains("00FF"));
+
+        // F000 00FF FA01 1206: the word after F000 is not an instruction.
+        let report = analyze(&[0xF0, 0x00, 0x00, 0xFF, 0xFA, 0x01, 0x12, 0x06], 0x200);
+        assert_eq!(report.dialect, Dialect::Xochip);
+        assert_eq!(report.reasons.len(), 2);
+
+        // 1204 00FF 0011 1206: 00FF is never reached.
+        let report = analyze(&[0x12, 0x04, 0x00, 0xFF, 0x00, 0x11, 0x12, 0x06], 0x200);
+        assert_eq!(report.dialect, Dialect::Megachip);
+        assert_eq!(report.dialect.variant(), Variant::Megachip);
+    }
+
+    #[test]
+    fn test_quirk_patterns() {
+        // 8016 B210 1204: only V0 and VF are set, so shifting V1 into V0
+        // needs the quirk and jumping by V2 does not.
+        let report = analyze(&[0x80, 0x16, 0xB2, 0x10, 0x12, 0x04], 0x200);
+        assert_eq!(report.dialect, Dialect::Chip8);
+        assert!(report.quirks.shift);
+        assert!(!report.quirks.jumping);
+        assert_eq!(report.reasons.len(), 1);
+        assert!(report.reasons[0].contains("shift quirk is on"));
+
+        // 6205 8126 B210: V2 is set, so shifting it into V1 is plain CHIP-8,
+        // and jumping by it needs the quirk.
+        let report = analyze(&[0x62, 0x05, 0x81, 0x26, 0xB2, 0x10], 0x200);
+        assert_eq!(report.quirks.shift, Profile::Chip8.quirks().shift);
+        assert!(report.quirks.jumping);
+        assert!(report.to_string().contains("changed as below"));
+
+        // 02A0 F1FB 8016 1206: CHIP-8X reads V1 from its I/O port, so
+        // shifting it into V0 is plain CHIP-8X.
+        let report = analyze(&[0x02, 0xA0, 0xF1, 0xFB, 0x80, 0x16, 0x12, 0x06], 0x200);
+        assert_eq!(report.dialect, Dialect::Chip8x);
+        assert!(!report.quirks.shift);
+    }
+
+    #[test]
+    fn test_hires_clear() {
+        // 1260 at 0x200, then 0230 1200 from 0x2C0.
+        let mut rom = vec![0; 0xC4];
+        rom[..2].copy_from_slice(&[0x12, 0x60]);
+        rom[0xC0..].copy_from_slice(&[0x02, 0x30, 0x12, 0xC0]);
+        assert_eq!(analyze(&rom, 0x200).dialect, Dialect::Hires);
+
+        // Without the hi-res header 0230 loads a palette.
+        assert_eq!(
+            analyze(&[0x02, 0x30, 0x12, 0x00], 0x200).dialect,
+            Dialect::Megachip
+        );
+    }
+}
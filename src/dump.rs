@@ -1,152 +1,197 @@
 use colored::*;
-use std::fs;
+use std::{
+    io::{self, IsTerminal},
+    path::Path,
+};
 
-use crate::instructions::Instruction;
+use crate::{instructions::Instruction, op::Op, rom, variant::Variant};
 
-pub fn disasm(filepath: String) -> Result<(), Box<dyn std::error::Error>> {
-    let mut buffer = fs::read(&filepath)?;
+pub fn disasm(filepath: String, variant: Variant) -> Result<(), Box<dyn std::error::Error>> {
+    let mut buffer = rom::read(Path::new(&filepath))?;
     if (buffer.len() & 1) == 1 {
         buffer.push(0x00);
     }
-    let mut pc: u16 = 0x200;
+    let mut pc: u16 = variant.start();
     let mut iter = buffer.chunks(2);
 
     println!("Disassembly of {}:\n", &filepath);
     for _ in (0..buffer.len()).step_by(2) {
         let instruction = Instruction::new(iter.next().unwrap());
-        decode(&instruction, pc);
-        pc += 0x02;
+        decode(&instruction, pc, variant);
+        pc = pc.wrapping_add(0x02);
     }
     Ok(())
 }
 
-pub fn decode(instruct: &Instruction, pc: u16) {
-    print!("  {pc:04X}:\t\t {:04X}\t", instruct.opcode);
+pub fn decode(instruct: &Instruction, pc: u16, variant: Variant) {
+    let mut text = mnemonic_for(instruct, variant);
+    if io::stdout().is_terminal() {
+        text = coloured(&text);
+    }
+    println!("  {pc:04X}:\t\t {:04X}\t{text}", instruct.opcode);
+}
+
+pub fn mnemonic(instruct: &Instruction) -> String {
+    mnemonic_for(instruct, Variant::Chip8)
+}
+
+// Unknown instructions in red, and the names of the rest in yellow.
+fn coloured(text: &str) -> String {
+    if text.starts_with("UNKNOWN") {
+        return text.red().to_string();
+    }
+    match text.split_once(' ') {
+        Some((name, operands)) => format!("{:<10} {}", name.yellow(), operands.trim_start()),
+        None => text.yellow().to_string(),
+    }
+}
+
+pub fn mnemonic_for(instruct: &Instruction, variant: Variant) -> String {
+    padded_mnemonic(instruct, variant).trim_end().to_string()
+}
 
+fn padded_mnemonic(instruct: &Instruction, variant: Variant) -> String {
+    match (variant, instruct.opcode) {
+        (Variant::Hires, 0x1260) => return format!("{:<10}", "HIRES"),
+        (Variant::Hires, 0x0230) => return format!("{:<10}", "CLS"),
+        (Variant::Chip8x, _) => {
+            if let Some(text) = chip8x_mnemonic(instruct) {
+                return text;
+            }
+        }
+        (Variant::Megachip, _) => {
+            if let Some(text) = megachip_mnemonic(instruct) {
+                return text;
+            }
+        }
+        _ => {}
+    }
     match instruct.f_nibble {
         0x0 => {
             if instruct.opcode >> 12 == 0x00 {
                 match instruct.nn {
-                    0xE0 => println!("{:<10}", "CLS".yellow()),
-                    0xEE => println!("{:<10}", "RTS".yellow()),
-                    _ => println!("{}", "UNKNOWN 0".red()),
+                    0xE0 => format!("{:<10}", "CLS"),
+                    0xEE => format!("{:<10}", "RTS"),
+                    _ => "UNKNOWN 0".to_string(),
                 }
             } else {
-                println!("{}", "UNKNOWN 0".red())
+                "UNKNOWN 0".to_string()
             }
         }
-        0x1 => println!("{:<10} ${:03X}", "JUMP".yellow(), instruct.nnn,),
-        0x2 => println!("{:<10} ${:03X}", "CALL".yellow(), instruct.nnn,),
-        0x3 => println!(
-            "{:<10} V{:X}, #${:02X}",
-            "SKIP.EQ".yellow(),
-            instruct.x,
-            instruct.nn
-        ),
-        0x4 => println!(
-            "{:<10} V{:X}, #${:02X}",
-            "SKIP.NE".yellow(),
-            instruct.x,
-            instruct.nn
-        ),
-        0x5 => println!(
-            "{:<10} V{:X}, V{:X}",
-            "SKIP.EQ".yellow(),
-            instruct.x,
-            instruct.y
-        ),
-        0x6 => println!(
-            "{:<10} V{:X}, #${:02X}",
-            "MVI".yellow(),
-            instruct.x,
-            instruct.nn
-        ),
-        0x7 => println!(
-            "{:<10} V{:X}, #${:02X}",
-            "ADI".yellow(),
-            instruct.x,
-            instruct.nn
-        ),
+        0x1 => format!("{:<10} ${:03X}", "JUMP", instruct.nnn,),
+        0x2 => format!("{:<10} ${:03X}", "CALL", instruct.nnn,),
+        0x3 => format!("{:<10} V{:X}, #${:02X}", "SKIP.EQ", instruct.x, instruct.nn),
+        0x4 => format!("{:<10} V{:X}, #${:02X}", "SKIP.NE", instruct.x, instruct.nn),
+        0x5 => format!("{:<10} V{:X}, V{:X}", "SKIP.EQ", instruct.x, instruct.y),
+        0x6 => format!("{:<10} V{:X}, #${:02X}", "MVI", instruct.x, instruct.nn),
+        0x7 => format!("{:<10} V{:X}, #${:02X}", "ADI", instruct.x, instruct.nn),
         0x8 => match instruct.l_nibble {
-            0x0 => println!(
-                "{:<10} V{:X}, V{:X}",
-                "MOV".yellow(),
-                instruct.x,
-                instruct.y,
-            ),
-            0x1 => println!("{:<10} V{:X}, V{:X}", "OR".yellow(), instruct.x, instruct.y,),
-            0x2 => println!(
-                "{:<10} V{:X}, V{:X}",
-                "AND".yellow(),
-                instruct.x,
-                instruct.y,
-            ),
-            0x3 => println!(
-                "{:<10} V{:X}, V{:X}",
-                "XOR".yellow(),
-                instruct.x,
-                instruct.y,
-            ),
-            0x4 => println!(
-                "{:<10} V{:X}, V{:X}",
-                "ADD.".yellow(),
-                instruct.x,
-                instruct.y,
-            ),
-            0x5 => println!(
-                "{:<10} V{:X}, V{:X}",
-                "SUB.".yellow(),
-                instruct.x,
-                instruct.y,
-            ),
-            0x6 => println!("{:<10} V{:X}", "SHR.".yellow(), instruct.x,),
-            0x7 => println!(
-                "{:<10} V{:X}, V{:X}",
-                "SUBN.".yellow(),
-                instruct.x,
-                instruct.y,
-            ),
-            0xE => println!("{:<10} V{:X}", "SHL.".yellow(), instruct.x,),
-            _ => println!("{}", "UNKNOWN 8".red()),
+            0x0 => format!("{:<10} V{:X}, V{:X}", "MOV", instruct.x, instruct.y,),
+            0x1 => format!("{:<10} V{:X}, V{:X}", "OR", instruct.x, instruct.y,),
+            0x2 => format!("{:<10} V{:X}, V{:X}", "AND", instruct.x, instruct.y,),
+            0x3 => format!("{:<10} V{:X}, V{:X}", "XOR", instruct.x, instruct.y,),
+            0x4 => format!("{:<10} V{:X}, V{:X}", "ADD.", instruct.x, instruct.y,),
+            0x5 => format!("{:<10} V{:X}, V{:X}", "SUB.", instruct.x, instruct.y,),
+            0x6 => format!("{:<10} V{:X}", "SHR.", instruct.x,),
+            0x7 => format!("{:<10} V{:X}, V{:X}", "SUBN.", instruct.x, instruct.y,),
+            0xE => format!("{:<10} V{:X}", "SHL.", instruct.x,),
+            _ => "UNKNOWN 8".to_string(),
         },
-        0x9 => println!(
-            "{:<10} V{:X}, V{:X}",
-            "SKIP.NE".yellow(),
-            instruct.x,
-            instruct.y,
-        ),
-        0xA => println!("{:<10} I, #${:03X}", "MVI".yellow(), instruct.nnn,),
-        0xB => println!("{:<10} #${:03X}(V0)", "JUMP".yellow(), instruct.nnn,),
-        0xC => println!(
-            "{:<10} V{:X}, #${:02X}",
-            "RNDMSK".yellow(),
-            instruct.x,
-            instruct.nn
-        ),
-        0xD => println!(
+        0x9 => format!("{:<10} V{:X}, V{:X}", "SKIP.NE", instruct.x, instruct.y,),
+        0xA => format!("{:<10} I, #${:03X}", "MVI", instruct.nnn,),
+        0xB => format!("{:<10} #${:03X}(V0)", "JUMP", instruct.nnn,),
+        0xC => format!("{:<10} V{:X}, #${:02X}", "RNDMSK", instruct.x, instruct.nn),
+        0xD => format!(
             "{:<10} V{:X}, V{:X}, #${:X}",
-            "DRAW".yellow(),
-            instruct.x,
-            instruct.y,
-            instruct.l_nibble
+            "DRAW", instruct.x, instruct.y, instruct.l_nibble
         ),
         0xE => match instruct.nn {
-            0x9E => println!("{:<10} V{:X}", "SKIPKEY.Y".yellow(), instruct.x),
-            0xA1 => println!("{:<10} V{:X}", "SKIPKEY.N".yellow(), instruct.x),
-            _ => println!("{}", "UNKNOWN E".red()),
+            0x9E => format!("{:<10} V{:X}", "SKIPKEY.Y", instruct.x),
+            0xA1 => format!("{:<10} V{:X}", "SKIPKEY.N", instruct.x),
+            _ => "UNKNOWN E".to_string(),
         },
         0xF => match instruct.nn {
-            0x07 => println!("{:<10} V{:X}, DELAY", "MOV".yellow(), instruct.x),
-            0x0A => println!("{:<10} V{:X}", "KEY".yellow(), instruct.x),
-            0x15 => println!("{:<10} DELAY, V{:X}", "MOV".yellow(), instruct.x),
-            0x18 => println!("{:<10} SOUND, V{:X}", "MOV".yellow(), instruct.x),
-            0x1E => println!("{:<10} I, V{:X}", "ADI".yellow(), instruct.x),
-            0x29 => println!("{:<10} I, V{:X}", "SPRITECHAR".yellow(), instruct.x),
-            0x33 => println!("{:<10} (I), V{:X}", "MOVBCD".yellow(), instruct.x),
-            0x55 => println!("{:<10} (I), V0-V{:X}", "MOVM".yellow(), instruct.x),
-            0x65 => println!("{:<10} V0-V{:X}, (I)", "MOVM".yellow(), instruct.x),
-            _ => println!("{}", "UNKNOWN F".red()),
+            0x07 => format!("{:<10} V{:X}, DELAY", "MOV", instruct.x),
+            0x0A => format!("{:<10} V{:X}", "KEY", instruct.x),
+            0x15 => format!("{:<10} DELAY, V{:X}", "MOV", instruct.x),
+            0x18 => format!("{:<10} SOUND, V{:X}", "MOV", instruct.x),
+            0x1E => format!("{:<10} I, V{:X}", "ADI", instruct.x),
+            0x29 => format!("{:<10} I, V{:X}", "SPRITECHAR", instruct.x),
+            0x33 => format!("{:<10} (I), V{:X}", "MOVBCD", instruct.x),
+            0x55 => format!("{:<10} (I), V0-V{:X}", "MOVM", instruct.x),
+            0x65 => format!("{:<10} V0-V{:X}, (I)", "MOVM", instruct.x),
+            _ => "UNKNOWN F".to_string(),
         },
-        _ => println!("{}", "UNKNOWN I".red()),
+        _ => "UNKNOWN I".to_string(),
+    }
+}
+
+fn chip8x_mnemonic(instruct: &Instruction) -> Option<String> {
+    Some(match (instruct.f_nibble, instruct.nn) {
+        (0x0, 0xA0) if instruct.x == 0x2 => format!("{:<10}", "BGCOL"),
+        (0x5, _) if instruct.l_nibble == 0x1 => {
+            format!("{:<10} V{:X}, V{:X}", "ADDCOL", instruct.x, instruct.y)
+        }
+        (0xB, _) => format!(
+            "{:<10} V{:X}, V{:X}, #${:X}",
+            "COLOUR", instruct.x, instruct.y, instruct.l_nibble
+        ),
+        (0xE, 0xF2) => format!("{:<10} V{:X}", "SKIPKEY2.Y", instruct.x),
+        (0xE, 0xF5) => format!("{:<10} V{:X}", "SKIPKEY2.N", instruct.x),
+        (0xF, 0xF8) => format!("{:<10} PORT, V{:X}", "OUT", instruct.x),
+        (0xF, 0xFB) => format!("{:<10} V{:X}, PORT", "IN", instruct.x),
+        _ => return None,
+    })
+}
+
+fn megachip_mnemonic(instruct: &Instruction) -> Option<String> {
+    let nn = instruct.nn;
+    Some(match Op::decode_for(instruct.opcode, Variant::Megachip) {
+        Op::MegaOff => format!("{:<10}", "MEGAOFF"),
+        Op::MegaOn => format!("{:<10}", "MEGAON"),
+        Op::LoadLongI(_) => format!("{:<10} I, #${:02X}....", "LDHI", nn),
+        Op::Palette(_) => format!("{:<10} #${:02X}", "LDPAL", nn),
+        Op::SpriteWidth(_) => format!("{:<10} #${:02X}", "SPRW", nn),
+        Op::SpriteHeight(_) => format!("{:<10} #${:02X}", "SPRH", nn),
+        Op::ScreenAlpha(_) => format!("{:<10} #${:02X}", "ALPHA", nn),
+        Op::PlaySample(_) => format!("{:<10} #${:X}", "DIGISND", instruct.l_nibble),
+        Op::StopSample => format!("{:<10}", "STOPSND"),
+        Op::Blend(_) => format!("{:<10} #${:X}", "BMODE", instruct.l_nibble),
+        Op::CollisionColour(_) => format!("{:<10} #${:02X}", "CCOL", nn),
+        _ => return None,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{coloured, mnemonic_for};
+    use crate::{instructions::Instruction, variant::Variant};
+
+    #[test]
+    fn test_coloured() {
+        colored::control::set_override(true);
+        let cls = mnemonic_for(&Instruction::new(&[0x00, 0xE0]), Variant::Chip8);
+        let jump = mnemonic_for(&Instruction::new(&[0x12, 0x34]), Variant::Chip8);
+
+        assert_eq!(cls, "CLS");
+        assert_eq!(coloured(&cls), "\x1b[33mCLS\x1b[0m");
+        assert_eq!(coloured(&jump), "\x1b[33mJUMP      \x1b[0m $234");
+        assert_eq!(coloured("UNKNOWN 0"), "\x1b[31mUNKNOWN 0\x1b[0m");
+    }
+
+    #[test]
+    fn test_variant_mnemonics() {
+        let text = |bytes: [u8; 2], variant| {
+            let text = mnemonic_for(&Instruction::new(&bytes), variant);
+            text.split_whitespace().collect::<Vec<_>>().join(" ")
+        };
+
+        assert_eq!(text([0xB1, 0x23], Variant::Chip8x), "COLOUR V1, V2, #$3");
+        assert_eq!(text([0xB1, 0x23], Variant::Chip8), "JUMP #$123(V0)");
+        assert_eq!(text([0x02, 0xA0], Variant::Chip8x), "BGCOL");
+        assert_eq!(text([0xF4, 0xFB], Variant::Chip8x), "IN V4, PORT");
+        assert_eq!(text([0x12, 0x60], Variant::Hires), "HIRES");
+        assert_eq!(text([0x01, 0x12], Variant::Megachip), "LDHI I, #$12....");
+        assert_eq!(text([0x08, 0x03], Variant::Megachip), "BMODE #$3");
     }
 }
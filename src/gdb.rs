@@ -0,0 +1,507 @@
+use std::{
+    collections::HashSet,
+    io::{self, ErrorKind, Read, Write},
+    net::{TcpListener, TcpStream},
+};
+
+use crate::{
+    chip::{Chip, Fault},
+    platform::Headless,
+};
+
+// How many instructions run between timer ticks, as in the emulator's default
+// speed.
+const CYCLES_PER_FRAME: usize = 11;
+
+// Instructions run between checks for an interrupt from the client.
+const BATCH: usize = 1000;
+
+const INTERRUPT: u8 = 0x03;
+const SIGTRAP: &str = "S05";
+const SIGSEGV: &str = "S0B";
+
+/// Register numbers as seen by gdb: V0-VF, then I, PC, SP, DT and ST.
+const REGISTERS: [(&str, usize); 21] = [
+    ("v0", 8),
+    ("v1", 8),
+    ("v2", 8),
+    ("v3", 8),
+    ("v4", 8),
+    ("v5", 8),
+    ("v6", 8),
+    ("v7", 8),
+    ("v8", 8),
+    ("v9", 8),
+    ("va", 8),
+    ("vb", 8),
+    ("vc", 8),
+    ("vd", 8),
+    ("ve", 8),
+    ("vf", 8),
+    ("i", 16),
+    ("pc", 16),
+    ("sp", 8),
+    ("dt", 8),
+    ("st", 8),
+];
+
+pub struct Stub<'a> {
+    chip: &'a mut Chip,
+    buffer: Vec<u32>,
+    breakpoints: HashSet<u16>,
+    cycles: usize,
+    fault: Option<Fault>,
+}
+
+impl<'a> Stub<'a> {
+    pub fn new(chip: &'a mut Chip) -> Stub<'a> {
+        chip.code_writes = Some(Vec::new());
+        let (width, height) = chip.variant.size();
+        Stub {
+            chip,
+            buffer: vec![0u32; width * height],
+            breakpoints: HashSet::new(),
+            cycles: 0,
+            fault: None,
+        }
+    }
+
+    /// Answers one packet. Continuing is left to the caller, which has to watch
+    /// the connection for interrupts while the chip runs, so `c` returns `None`.
+    pub fn handle(&mut self, packet: &str) -> Option<String> {
+        let (command, args) = packet.split_at(packet.len().min(1));
+        let reply = match command {
+            "?" => SIGTRAP.to_string(),
+            "g" => (0..REGISTERS.len())
+                .map(|n| self.read_register(n).unwrap_or_default())
+                .collect(),
+            "G" => {
+                let mut rest = args;
+                for (n, (_, bits)) in REGISTERS.iter().enumerate() {
+                    let digits = bits / 4;
+                    if rest.len() < digits {
+                        break;
+                    }
+                    let (value, tail) = rest.split_at(digits);
+                    self.write_register(n, value);
+                    rest = tail;
+                }
+                "OK".to_string()
+            }
+            "p" => usize::from_str_radix(args, 16)
+                .ok()
+                .and_then(|n| self.read_register(n))
+                .unwrap_or_else(|| "E01".to_string()),
+            "P" => match args.split_once('=') {
+                Some((n, value)) => match usize::from_str_radix(n, 16) {
+                    Ok(n) if self.write_register(n, value) => "OK".to_string(),
+                    _ => "E01".to_string(),
+                },
+                None => "E01".to_string(),
+            },
+            "m" => self.read_memory(args).unwrap_or_else(|| "E01".to_string()),
+            "M" => match self.write_memory(args) {
+                Some(()) => "OK".to_string(),
+                None => "E01".to_string(),
+            },
+            "Z" | "z" => match parse_breakpoint(args) {
+                Some(address) => {
+                    if command == "Z" {
+                        self.breakpoints.insert(address);
+                    } else {
+                        self.breakpoints.remove(&address);
+                    }
+                    "OK".to_string()
+                }
+                None => String::new(),
+            },
+            "s" => match self.step() {
+                Ok(()) => SIGTRAP.to_string(),
+                Err(fault) => self.stop(fault),
+            },
+            "c" => return None,
+            "H" | "D" => "OK".to_string(),
+            "q" => self.query(args),
+            _ => String::new(),
+        };
+        Some(reply)
+    }
+
+    /// Messages for the gdb console about what ran since the last call, such as
+    /// code overwriting itself.
+    pub fn console(&mut self) -> Vec<String> {
+        let mut messages: Vec<String> = self
+            .chip
+            .code_writes
+            .as_mut()
+            .map(std::mem::take)
+            .unwrap_or_default()
+            .iter()
+            .map(|w| {
+                format!(
+                    "self-modifying code: {:#05x} wrote to {:#05x}\n",
+                    w.pc, w.address
+                )
+            })
+            .collect();
+        messages.extend(self.fault.take().map(|fault| format!("{fault}\n")));
+        messages
+    }
+
+    /// Runs until a breakpoint is reached or `interrupted` returns true.
+    pub fn resume(&mut self, mut interrupted: impl FnMut() -> bool) -> String {
+        loop {
+            for _ in 0..BATCH {
+                if let Err(fault) = self.step() {
+                    return self.stop(fault);
+                }
+                if self.breakpoints.contains(&self.chip.pc) {
+                    return SIGTRAP.to_string();
+                }
+            }
+            if interrupted() {
+                return "S02".to_string();
+            }
+        }
+    }
+
+    fn step(&mut self) -> Result<(), Fault> {
+        self.chip.step(&mut self.buffer, &Headless::default())?;
+        self.cycles += 1;
+        if self.cycles.is_multiple_of(CYCLES_PER_FRAME) {
+            self.chip.tick_timers();
+        }
+        Ok(())
+    }
+
+    // The program cannot go on, so gdb is told it crashed.
+    fn stop(&mut self, fault: Fault) -> String {
+        self.fault = Some(fault);
+        SIGSEGV.to_string()
+    }
+
+    fn query(&self, args: &str) -> String {
+        if args.starts_with("Supported") {
+            "PacketSize=1000;qXfer:features:read+".to_string()
+        } else if args == "Attached" {
+            "1".to_string()
+        } else if let Some(range) = args.strip_prefix("Xfer:features:read:target.xml:") {
+            let Some((offset, length)) = range.split_once(',') else {
+                return "E01".to_string();
+            };
+            let (Ok(offset), Ok(length)) = (
+                usize::from_str_radix(offset, 16),
+                usize::from_str_radix(length, 16),
+            ) else {
+                return "E01".to_string();
+            };
+            let xml = target_xml();
+            let chunk = xml.get(offset..xml.len().min(offset + length));
+            match chunk {
+                Some(chunk) if offset + length < xml.len() => format!("m{chunk}"),
+                Some(chunk) => format!("l{chunk}"),
+                None => "l".to_string(),
+            }
+        } else {
+            String::new()
+        }
+    }
+
+    fn read_register(&self, n: usize) -> Option<String> {
+        let chip = &self.chip;
+        let value = match n {
+            0x0..=0xF => return Some(format!("{:02x}", chip.v[n])),
+            // gdb sees the low 16 bits of MegaChip's 24-bit I.
+            16 => chip.i as u16,
+            17 => chip.pc,
+            18 => return Some(format!("{:02x}", chip.sp)),
+            19 => return Some(format!("{:02x}", chip.dt)),
+            20 => return Some(format!("{:02x}", chip.st)),
+            _ => return None,
+        };
+        Some(encode(&value.to_le_bytes()))
+    }
+
+    fn write_register(&mut self, n: usize, value: &str) -> bool {
+        let Some(bytes) = decode(value) else {
+            return false;
+        };
+        let byte = bytes.first().copied().unwrap_or(0);
+        let word = u16::from_le_bytes([byte, bytes.get(1).copied().unwrap_or(0)]);
+        let chip = &mut self.chip;
+        match n {
+            0x0..=0xF => chip.v[n] = byte,
+            16 => chip.i = word as u32,
+            17 => chip.pc = word,
+            18 if (byte as usize) <= chip.stack.len() => chip.sp = byte as usize,
+            19 => chip.dt = byte,
+            20 => chip.st = byte,
+            _ => return false,
+        }
+        true
+    }
+
+    fn read_memory(&self, args: &str) -> Option<String> {
+        let (address, length) = parse_range(args)?;
+        Some(encode(
+            self.chip.mem.get(address..address.checked_add(length)?)?,
+        ))
+    }
+
+    fn write_memory(&mut self, args: &str) -> Option<()> {
+        let (range, data) = args.split_once(':')?;
+        let (address, length) = parse_range(range)?;
+        let bytes = decode(data)?;
+        let end = address.checked_add(length)?;
+        if bytes.len() != length || end > self.chip.mem.len() {
+            return None;
+        }
+        self.chip.write(address, &bytes);
+        Some(())
+    }
+}
+
+/// Waits for one gdb client on `address` and serves it until it detaches.
+pub fn serve(chip: &mut Chip, address: &str) -> io::Result<()> {
+    let listener = TcpListener::bind(address)?;
+    println!("Waiting for gdb on {}", listener.local_addr()?);
+    let (mut stream, peer) = listener.accept()?;
+    println!("gdb connected from {peer}");
+    stream.set_nodelay(true)?;
+
+    let mut stub = Stub::new(chip);
+    while let Some(packet) = read_packet(&mut stream)? {
+        let reply = match stub.handle(&packet) {
+            Some(reply) => reply,
+            None => {
+                let mut poll = stream.try_clone()?;
+                stub.resume(|| interrupted(&mut poll))
+            }
+        };
+        for message in stub.console() {
+            write_packet(&mut stream, &format!("O{}", encode(message.as_bytes())))?;
+        }
+        write_packet(&mut stream, &reply)?;
+        if packet == "D" || packet == "k" {
+            break;
+        }
+    }
+    Ok(())
+}
+
+fn read_packet(stream: &mut TcpStream) -> io::Result<Option<String>> {
+    let mut byte = [0u8];
+    loop {
+        if stream.read(&mut byte)? == 0 {
+            return Ok(None);
+        }
+        if byte[0] == b'$' {
+            break;
+        }
+    }
+
+    let mut data = Vec::new();
+    loop {
+        if stream.read(&mut byte)? == 0 {
+            return Ok(None);
+        }
+        if byte[0] == b'#' {
+            break;
+        }
+        data.push(byte[0]);
+    }
+    let mut checksum = [0u8; 2];
+    stream.read_exact(&mut checksum)?;
+
+    let valid = std::str::from_utf8(&checksum)
+        .ok()
+        .and_then(|c| u8::from_str_radix(c, 16).ok())
+        == Some(sum(&data));
+    stream.write_all(if valid { b"+" } else { b"-" })?;
+    if !valid {
+        return read_packet(stream);
+    }
+    Ok(Some(String::from_utf8_lossy(&data).into_owned()))
+}
+
+fn write_packet(stream: &mut TcpStream, data: &str) -> io::Result<()> {
+    stream.write_all(frame(data).as_bytes())
+}
+
+fn interrupted(stream: &mut TcpStream) -> bool {
+    let mut byte = [0u8];
+    if stream.set_nonblocking(true).is_err() {
+        return false;
+    }
+    let read = stream.read(&mut byte);
+    let _ = stream.set_nonblocking(false);
+    match read {
+        Ok(1) => byte[0] == INTERRUPT,
+        Err(e) if e.kind() == ErrorKind::WouldBlock => false,
+        // A closed connection stops the chip too.
+        _ => true,
+    }
+}
+
+fn frame(data: &str) -> String {
+    format!("${data}#{:02x}", sum(data.as_bytes()))
+}
+
+fn sum(data: &[u8]) -> u8 {
+    data.iter().fold(0u8, |sum, &b| sum.wrapping_add(b))
+}
+
+fn encode(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{b:02x}")).collect()
+}
+
+fn decode(hex: &str) -> Option<Vec<u8>> {
+    if !hex.len().is_multiple_of(2) {
+        return None;
+    }
+    (0..hex.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(hex.get(i..i + 2)?, 16).ok())
+        .collect()
+}
+
+fn parse_range(args: &str) -> Option<(usize, usize)> {
+    let (address, length) = args.split_once(',')?;
+    Some((
+        usize::from_str_radix(address, 16).ok()?,
+        usize::from_str_radix(length, 16).ok()?,
+    ))
+}
+
+// Only software breakpoints (type 0) are supported.
+fn parse_breakpoint(args: &str) -> Option<u16> {
+    let mut fields = args.split(',');
+    if fields.next()? != "0" {
+        return None;
+    }
+    u16::from_str_radix(fields.next()?, 16).ok()
+}
+
+fn target_xml() -> String {
+    let registers: String = REGISTERS
+        .iter()
+        .map(|(name, bits)| {
+            let kind = match *name {
+                "pc" => "code_ptr",
+                "i" => "data_ptr",
+                _ => "int",
+            };
+            format!("<reg name=\"{name}\" bitsize=\"{bits}\" type=\"{kind}\"/>")
+        })
+        .collect();
+    format!(
+        "<?xml version=\"1.0\"?><!DOCTYPE target SYSTEM \"gdb-target.dtd\">\
+         <target version=\"1.0\"><feature name=\"org.rusty-chip8.core\">{registers}</feature></target>"
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{encode, frame, Stub};
+    use crate::{chip::Chip, variant::Variant};
+
+    fn chip() -> Chip {
+        let mut chip = Chip::new();
+        chip.trace = false;
+        chip.load_bytes(&[0x60, 0x0C, 0x71, 0x01, 0x12, 0x02])
+            .unwrap();
+        chip
+    }
+
+    #[test]
+    fn test_frame() {
+        assert_eq!(frame("OK"), "$OK#9a");
+    }
+
+    #[test]
+    fn test_registers() {
+        let mut chip = chip();
+        let mut stub = Stub::new(&mut chip);
+
+        assert_eq!(stub.handle("p11").unwrap(), "0002");
+        assert_eq!(stub.handle("P3=2a").unwrap(), "OK");
+        assert_eq!(
+            stub.handle("g").unwrap().len(),
+            2 * (16 + 2 + 2 + 1 + 1 + 1)
+        );
+        assert!(stub.handle("g").unwrap().starts_with("0000002a"));
+    }
+
+    #[test]
+    fn test_memory() {
+        let mut chip = chip();
+        let mut stub = Stub::new(&mut chip);
+
+        assert_eq!(stub.handle("m200,2").unwrap(), "600c");
+        assert_eq!(stub.handle("M300,2:beef").unwrap(), "OK");
+        assert_eq!(stub.handle("m300,2").unwrap(), "beef");
+        assert_eq!(stub.handle("mfff,2").unwrap(), "E01");
+        assert_eq!(stub.handle("mffffffffffffffff,2").unwrap(), "E01");
+        assert_eq!(stub.handle("Mffffffffffffffff,2:beef").unwrap(), "E01");
+    }
+
+    #[test]
+    fn test_megachip_memory() {
+        let mut chip = chip();
+        chip.mem.resize(Variant::Megachip.memory_size(), 0);
+        let mut stub = Stub::new(&mut chip);
+        let low = stub.handle("m0,2").unwrap();
+
+        assert_eq!(stub.handle("M10000,2:beef").unwrap(), "OK");
+        assert_eq!(stub.handle("m10000,2").unwrap(), "beef");
+        assert_eq!(stub.handle("m0,2").unwrap(), low);
+    }
+
+    #[test]
+    fn test_step_and_breakpoint() {
+        let mut chip = chip();
+        let mut stub = Stub::new(&mut chip);
+
+        assert_eq!(stub.handle("s").unwrap(), "S05");
+        assert_eq!(stub.handle("Z0,204,2").unwrap(), "OK");
+        assert_eq!(stub.handle("c"), None);
+        assert_eq!(stub.resume(|| false), "S05");
+        drop(stub);
+
+        assert_eq!(chip.pc, 0x204);
+        assert_eq!(chip.v[0], 0x0C);
+        assert_eq!(chip.v[1], 0x01);
+    }
+
+    #[test]
+    fn test_console_reports_code_writes() {
+        let mut chip = Chip::new();
+        chip.trace = false;
+        chip.load_bytes(&[0xA2, 0x00, 0xF0, 0x55]).unwrap();
+        let mut stub = Stub::new(&mut chip);
+
+        stub.handle("s");
+        stub.handle("s");
+
+        assert_eq!(
+            stub.console(),
+            vec!["self-modifying code: 0x202 wrote to 0x200\n"]
+        );
+        assert!(stub.console().is_empty());
+        assert_eq!(encode(b"ok"), "6f6b");
+    }
+
+    #[test]
+    fn test_fault() {
+        let mut chip = Chip::new();
+        chip.trace = false;
+        chip.load_bytes(&[0x00, 0xEE]).unwrap();
+        let mut stub = Stub::new(&mut chip);
+
+        assert_eq!(stub.handle("s").unwrap(), "S0B");
+        assert_eq!(
+            stub.console(),
+            vec!["return with an empty stack at 0x200\n"]
+        );
+    }
+}
@@ -1,3 +1,27 @@
+pub mod audio;
+pub mod cartridge;
 pub mod chip;
+pub mod chip8x;
+pub mod config;
+pub mod conformance;
+pub mod cosmac;
+pub mod coverage;
+pub mod dap;
+pub mod database;
+pub mod detect;
 pub mod dump;
+pub mod gdb;
 pub mod instructions;
+pub mod megachip;
+pub mod octo;
+pub mod op;
+pub mod panel;
+pub mod platform;
+pub mod profile;
+pub mod quirks;
+pub mod recompile;
+pub mod rom;
+pub mod terminal;
+pub mod variant;
+pub mod vip;
+pub mod window;
@@ -1,22 +1,239 @@
-use std::process;
+use std::{
+    fs::File,
+    path::{Path, PathBuf},
+    process,
+};
 
-use clap::{Parser, Subcommand};
-use minifb::{Key, KeyRepeat, Scale, ScaleMode, Window, WindowOptions};
-use rusty_chip8::{chip::Chip, dump, instructions::Instruction};
+use clap::{Parser, Subcommand, ValueEnum};
+use rusty_chip8::{
+    audio::Speaker,
+    cartridge::Cartridge,
+    chip::{Chip, Fault},
+    config::{self, RomConfig},
+    conformance::{self, Golden, Status},
+    cosmac::Cosmac,
+    coverage::Coverage,
+    dap,
+    database::{Database, Found},
+    detect, dump, gdb,
+    platform::{Frontend, Hotkey, Platform},
+    profile::Profiler,
+    quirks::Profile,
+    recompile::Recompiler,
+    rom,
+    terminal::{Glyphs, Terminal},
+    variant::Variant,
+    vip::VipTiming,
+    window::Windowed,
+};
 
-const WIDTH: usize = 64;
-const HEIGHT: usize = 32;
+const FPS: u32 = 60;
+const DEFAULT_CYCLES_PER_FRAME: u32 = 11;
+const MAX_IPS: u32 = 1_000_000;
+const DEFAULT_SCALE: usize = 16;
+// Hot spots listed by --profile.
+const PROFILE_TOP: usize = 20;
+// Frames emulated per displayed frame while fast-forwarding.
+const FAST_FORWARD: usize = 8;
 
+#[derive(Clone, Copy, ValueEnum)]
+enum Display {
+    /// minifb window
+    Window,
+    /// Unicode graphics in the terminal, keys read from the terminal
+    Terminal,
+}
+
+#[derive(Clone, Copy, PartialEq, ValueEnum)]
+enum Engine {
+    /// Decode and run one instruction at a time
+    Interpreter,
+    /// Translate blocks of code into closures, for high instruction rates
+    Recompiler,
+    /// Interpreter paced by COSMAC VIP machine cycles; ignores --ips
+    Vip,
+    /// The VIP's own interpreter on an emulated CDP1802; needs --interpreter and --monitor
+    Cosmac,
+}
+
+/// The engine picked on the command line, with whatever state it keeps.
+enum Runner {
+    Interpreter,
+    Recompiler(Recompiler),
+    Vip(VipTiming),
+    Cosmac(Cosmac),
+}
+
+impl Runner {
+    fn frame(
+        &mut self,
+        chip: &mut Chip,
+        cycles: usize,
+        buffer: &mut [u32],
+        platform: &dyn Platform,
+    ) -> Result<(), Fault> {
+        match self {
+            Runner::Interpreter => chip.frame(cycles, buffer, platform),
+            Runner::Recompiler(recompiler) => recompiler.frame(chip, cycles, buffer, platform),
+            Runner::Vip(timing) => timing.frame(chip, buffer, platform),
+            Runner::Cosmac(cosmac) => cosmac.frame(chip, buffer, platform),
+        }
+    }
+}
+
+// Parsed once, so the size of `Emulate` does not matter.
+#[allow(clippy::large_enum_variant)]
 #[derive(Subcommand)]
 enum Command {
     Dump {
         #[arg(short, long)]
         filepath: String,
+
+        #[arg(long, value_enum, default_value_t = Variant::Chip8)]
+        variant: Variant,
     },
 
     Emulate {
+        /// The rom, a member of a zip like games.zip/pong.ch8, or - for stdin
         #[arg(short, long)]
         filepath: String,
+
+        #[arg(long, value_enum, default_value_t = Display::Window)]
+        display: Display,
+
+        /// Instructions per second, at most 1000000
+        #[arg(long, conflicts_with = "cycles_per_frame")]
+        ips: Option<u32>,
+
+        /// Instructions per 60 Hz frame [default: 11]
+        #[arg(long)]
+        cycles_per_frame: Option<u32>,
+
+        /// [default: chip8, or what the rom's config or the rom database says]
+        #[arg(long, value_enum)]
+        variant: Option<Variant>,
+
+        /// [default: chip8 for roms without a config or a database entry]
+        #[arg(long, value_enum)]
+        quirks: Option<Profile>,
+
+        /// Background and foreground colours, e.g. "#000000,#FFFFFF"
+        #[arg(long, value_parser = parse_palette)]
+        palette: Option<[u32; 2]>,
+
+        /// Window pixels per CHIP-8 pixel: 1, 2, 4, 8, 16 or 32 [default: 16]
+        #[arg(long)]
+        scale: Option<usize>,
+
+        /// Settings for the rom [default: <filepath>.toml, or that name in
+        /// ~/.config/rusty-chip8]
+        #[arg(long, conflicts_with = "no_config")]
+        config: Option<PathBuf>,
+
+        /// Ignore the rom's config file
+        #[arg(long)]
+        no_config: bool,
+
+        /// Pick the variant and quirks from the rom's contents, printing why,
+        /// for roms the rom database does not know
+        #[arg(long, conflicts_with = "variant")]
+        auto: bool,
+
+        /// The CHIP-8 community database's programs.json, to look roms up in
+        /// instead of the few bundled entries
+        #[arg(long)]
+        database: Option<PathBuf>,
+
+        /// Address the rom is loaded and started at, in decimal or hex with 0x,
+        /// e.g. 0x600 for ETI-660 roms [default: 0x200, 0x300 for CHIP-8X]
+        #[arg(long, value_parser = parse_address)]
+        load_address: Option<u16>,
+
+        #[arg(long, value_enum, default_value_t = Glyphs::HalfBlock)]
+        glyphs: Glyphs,
+
+        /// How instructions are run; the recompiler turns off the trace
+        #[arg(long, value_enum, default_value_t = Engine::Interpreter)]
+        engine: Engine,
+
+        /// CHIP-8 interpreter image for the cosmac engine, as loaded at 0x000
+        #[arg(long, required_if_eq("engine", "cosmac"))]
+        interpreter: Option<PathBuf>,
+
+        /// COSMAC VIP monitor rom for the cosmac engine
+        #[arg(long, required_if_eq("engine", "cosmac"))]
+        monitor: Option<PathBuf>,
+
+        /// Print a profile of the run when the emulator closes
+        #[arg(long)]
+        profile: bool,
+
+        /// Write the run's call stacks in flamegraph folded format to this file
+        #[arg(long)]
+        folded: Option<PathBuf>,
+
+        /// Write a disassembly of the rom marking what ran, was read or was written
+        #[arg(long)]
+        coverage: Option<PathBuf>,
+
+        /// Write a 64x64 PPM image of how each byte of memory was used
+        #[arg(long)]
+        coverage_image: Option<PathBuf>,
+
+        /// Show registers, stack, memory and code in a second window (window display only)
+        #[arg(long)]
+        panel: bool,
+
+        /// Write the sound to this file or pipe as raw unsigned 8-bit mono at
+        /// 44100 Hz, e.g. for `aplay -t raw -f U8 -r 44100`
+        #[arg(long)]
+        audio: Option<PathBuf>,
+    },
+
+    /// Guess the platform and quirks a rom was written for from its code
+    Detect {
+        #[arg(short, long)]
+        filepath: String,
+    },
+
+    /// Serve the rom to a gdb client over the remote serial protocol
+    Gdbserver {
+        #[arg(short, long)]
+        filepath: String,
+
+        #[arg(long, default_value = "127.0.0.1:1234")]
+        listen: String,
+    },
+
+    /// Run a debug adapter protocol server on stdin/stdout for editors, with
+    /// line breakpoints set in the disassembly it serves or in the Octo
+    /// source of a launched .8o program. Launch takes `variant`, `quirks` and
+    /// `auto` as `emulate` does
+    Dap,
+
+    /// Run the Timendus test suite and the bundled flag checks headlessly and
+    /// report results per quirk profile
+    Conformance {
+        /// Directory holding the suite's .ch8 files
+        #[arg(short, long)]
+        dir: PathBuf,
+
+        /// Only run this profile instead of all of them
+        #[arg(long, value_enum)]
+        quirks: Option<Profile>,
+
+        /// Known-good display hashes [default: <dir>/golden.txt]
+        #[arg(long)]
+        golden: Option<PathBuf>,
+
+        /// Store this run's hashes as the known-good ones, printing each
+        /// screen so it can be checked first
+        #[arg(long)]
+        record: bool,
+
+        /// Print the final screen of every result that is not a pass
+        #[arg(long)]
+        screens: bool,
     },
 }
 
@@ -32,50 +249,437 @@ fn main() {
     let cli = Cli::parse();
 
     match &cli.command {
-        Command::Dump { filepath } => {
-            if let Err(e) = dump::disasm(filepath.clone()) {
+        Command::Dump { filepath, variant } => {
+            if let Err(e) = dump::disasm(filepath.clone(), *variant) {
                 eprintln!("Error disassembling: {e}")
             }
         }
-        Command::Emulate { filepath } => {
-            let mut buffer = vec![0u32; WIDTH * HEIGHT];
-
-            let mut window = Window::new(
-                "rusty-chip8",
-                WIDTH,
-                HEIGHT,
-                WindowOptions {
-                    resize: true,
-                    scale: Scale::X16,
-                    scale_mode: ScaleMode::AspectRatioStretch,
-                    ..WindowOptions::default()
-                },
-            )
-            .unwrap();
-            window.set_target_fps(250);
+        Command::Emulate {
+            filepath,
+            display,
+            ips,
+            cycles_per_frame,
+            variant,
+            quirks,
+            palette,
+            scale,
+            config,
+            no_config,
+            auto,
+            database,
+            load_address,
+            glyphs,
+            engine,
+            interpreter,
+            monitor,
+            profile,
+            folded,
+            coverage,
+            coverage_image,
+            panel,
+            audio,
+        } => {
+            let config_path = match config {
+                Some(path) => Some(path.clone()),
+                None if *no_config || filepath == "-" => None,
+                None => RomConfig::find(Path::new(filepath)),
+            };
+            let rom_config = match config_path {
+                Some(path) => RomConfig::load(&path).unwrap_or_else(|e| {
+                    eprintln!("Error reading {}: {e}", path.display());
+                    process::exit(1);
+                }),
+                None => RomConfig::default(),
+            };
+            // Read once, as stdin can only be read once.
+            let contents = rom::read(Path::new(filepath)).unwrap_or_else(|e| {
+                eprintln!("Error loading the rom: {e}");
+                process::exit(1);
+            });
+            // A cartridge is decoded once, for both its program and its own
+            // options, which come after the config file.
+            let (rom, rom_config) = if Cartridge::is_cartridge(&contents) {
+                let (rom, options) = Cartridge::decode(&contents)
+                    .and_then(|cartridge| Ok((cartridge.rom()?, cartridge.config())))
+                    .unwrap_or_else(|e| {
+                        eprintln!("Error loading the rom: bad Octo cartridge: {e}");
+                        process::exit(1);
+                    });
+                (rom, rom_config.or(options))
+            } else {
+                (contents, rom_config)
+            };
+            let variant = variant.or(rom_config.variant);
+            let database = match database {
+                Some(path) => Database::load(path).unwrap_or_else(|e| {
+                    eprintln!("Error reading the rom database: {e}");
+                    process::exit(1);
+                }),
+                None => Database::bundled(),
+            };
+            let mut chip = Chip::new();
+            chip.variant = variant.unwrap_or_default();
+            chip.start = load_address.unwrap_or(chip.variant.start());
+            if let Err(e) = chip.load_bytes(&rom) {
+                eprintln!("Error loading the rom: {e}");
+                process::exit(1);
+            }
+            let found = database
+                .settle(&mut chip, *auto, variant.is_some(), *load_address)
+                .unwrap_or_else(|e| {
+                    eprintln!("Error loading the rom: {e}");
+                    process::exit(1);
+                });
+            // stdout may be the terminal display, so these go to stderr.
+            let info = match found {
+                Some(Found::Database(info)) => {
+                    eprintln!(
+                        "{} ({}), from the rom database",
+                        info.caption(),
+                        info.platform
+                    );
+                    Some(info)
+                }
+                Some(Found::Analysis(report)) => {
+                    eprint!("{report}");
+                    None
+                }
+                None => None,
+            };
+            if let Some(quirks) = quirks.map(Profile::quirks).or(rom_config.quirks) {
+                chip.quirks = quirks;
+            }
+            let tickrate = info.as_ref().and_then(|info| info.tickrate);
+            let ips = ips
+                .or(cycles_per_frame.map(|c| c.saturating_mul(FPS)))
+                .or(rom_config.ips)
+                .or(tickrate.map(|t| t.saturating_mul(FPS)))
+                .unwrap_or(DEFAULT_CYCLES_PER_FRAME * FPS)
+                .min(MAX_IPS);
+            let colours = palette
+                .or(rom_config.palette)
+                .or(info.as_ref().and_then(|info| info.colours));
+            let scale = scale.or(rom_config.scale).unwrap_or(DEFAULT_SCALE);
+            if *profile || folded.is_some() {
+                // PC is 16 bits, so code never runs past 64K.
+                let size = chip.mem.len().min(0x10000);
+                chip.profiler = Some(Profiler::new(chip.start, size));
+            }
+            if coverage.is_some() || coverage_image.is_some() {
+                chip.coverage = Some(Coverage::new(chip.mem.len()));
+            }
+            let runner = match engine {
+                Engine::Interpreter => Runner::Interpreter,
+                Engine::Recompiler => {
+                    chip.trace = false;
+                    Runner::Recompiler(Recompiler::new())
+                }
+                Engine::Vip => Runner::Vip(VipTiming::new()),
+                Engine::Cosmac => {
+                    let (Some(interpreter), Some(monitor)) = (interpreter, monitor) else {
+                        unreachable!("clap requires both for the cosmac engine");
+                    };
+                    match Cosmac::load(interpreter, monitor) {
+                        Ok(cosmac) => Runner::Cosmac(cosmac),
+                        Err(e) => {
+                            eprintln!("Error loading the VIP images: {e}");
+                            process::exit(1);
+                        }
+                    }
+                }
+            };
+
+            let speaker = audio.as_ref().map(|path| {
+                File::create(path).map(Speaker::new).unwrap_or_else(|e| {
+                    eprintln!("Error opening {}: {e}", path.display());
+                    process::exit(1);
+                })
+            });
+
+            let (width, height) = chip.variant.size();
+            let result = match display {
+                Display::Window => {
+                    let title = info
+                        .as_ref()
+                        .map_or("rusty-chip8".to_string(), |info| info.caption());
+                    Windowed::new(&title, width, height, scale).and_then(|mut window| {
+                        if *panel {
+                            window.show_panel()?;
+                        }
+                        if let Some(info) = &info {
+                            window.bind(&info.keys);
+                        }
+                        window.bind_named(&rom_config.keys)?;
+                        emulate(&mut chip, runner, &mut window, ips, colours, speaker)
+                    })
+                }
+                Display::Terminal => {
+                    // stdout is the screen, so the per-cycle trace has to go
+                    chip.trace = false;
+                    Terminal::new(*glyphs).and_then(|mut terminal| {
+                        emulate(&mut chip, runner, &mut terminal, ips, colours, speaker)
+                    })
+                }
+            };
+            if let Err(e) = result {
+                eprintln!("Error running the rom: {e}");
+                process::exit(1);
+            }
+
+            if let Some(map) = &chip.coverage {
+                let start = chip.start as usize;
+                let end = start + chip.rom().len();
+                let outputs = [
+                    (
+                        coverage,
+                        map.annotated(&chip.mem, start, end, chip.variant)
+                            .into_bytes(),
+                    ),
+                    (coverage_image, map.image()),
+                ];
+                for (path, contents) in outputs {
+                    if let Some(path) = path {
+                        if let Err(e) = std::fs::write(path, contents) {
+                            eprintln!("Error writing {}: {e}", path.display());
+                            process::exit(1);
+                        }
+                    }
+                }
+            }
+
+            if let Some(profiler) = &chip.profiler {
+                if *profile {
+                    println!("{}", profiler.report(&chip.mem, PROFILE_TOP, chip.variant));
+                }
+                if let Some(path) = folded {
+                    if let Err(e) = std::fs::write(path, profiler.folded()) {
+                        eprintln!("Error writing {}: {e}", path.display());
+                        process::exit(1);
+                    }
+                }
+            }
+        }
+        Command::Detect { filepath } => {
             let mut chip = Chip::new();
-            if let Err(e) = chip.load(filepath.clone()) {
+            if let Err(e) = chip.load(filepath) {
                 eprintln!("Error loading the rom: {e}");
                 process::exit(1);
             }
+            print!("{}", detect::analyze(chip.rom(), chip.start));
+        }
+        Command::Gdbserver { filepath, listen } => {
+            let mut chip = Chip::new();
+            chip.trace = false;
+            if let Err(e) = chip.load(filepath) {
+                eprintln!("Error loading the rom: {e}");
+                process::exit(1);
+            }
+            if let Err(e) = gdb::serve(&mut chip, listen) {
+                eprintln!("Error serving gdb: {e}");
+                process::exit(1);
+            }
+        }
+        Command::Dap => {
+            if let Err(e) = dap::serve(std::io::stdin(), std::io::stdout()) {
+                eprintln!("Error in debug adapter: {e}");
+                process::exit(1);
+            }
+        }
+        Command::Conformance {
+            dir,
+            quirks,
+            golden,
+            record,
+            screens,
+        } => {
+            let golden_path = golden.clone().unwrap_or_else(|| dir.join("golden.txt"));
+            let mut golden = match Golden::load(&golden_path) {
+                Ok(golden) => golden,
+                Err(e) => {
+                    eprintln!("Error reading {}: {e}", golden_path.display());
+                    process::exit(1);
+                }
+            };
+            let profiles = quirks.map_or(Profile::ALL.to_vec(), |p| vec![p]);
 
-            while window.is_open() && !window.is_key_down(Key::Escape) {
-                // if window.is_key_pressed(Key::J, KeyRepeat::No) {
-                println!("{}", chip);
-                chip.interpret(
-                    Instruction::new(&[chip.mem[chip.pc as usize], chip.mem[chip.pc as usize + 1]]),
-                    &mut buffer,
-                    &window,
-                );
-                // }
-                window.update_with_buffer(&buffer, WIDTH, HEIGHT).unwrap();
+            let outcomes = conformance::run(dir, &profiles, &golden);
+            println!("{}", conformance::report(&outcomes, &profiles));
+
+            if *screens || *record {
+                for outcome in &outcomes {
+                    if let Status::Fail { expected } = outcome.status {
+                        println!(
+                            "\n{} ({}): expected {expected:#018x}, got {:#018x}",
+                            outcome.rom,
+                            outcome.profile.name(),
+                            outcome.hash()
+                        );
+                    } else if let Status::Checks { failed } = outcome.status {
+                        println!(
+                            "\n{} ({}): {failed} checks failed",
+                            outcome.rom,
+                            outcome.profile.name()
+                        );
+                    } else if matches!(outcome.status, Status::Unrecorded) {
+                        println!(
+                            "\n{} ({}): {:#018x}",
+                            outcome.rom,
+                            outcome.profile.name(),
+                            outcome.hash()
+                        );
+                    } else {
+                        continue;
+                    }
+                    println!("{}", conformance::render(&outcome.screen));
+                }
+            }
+
+            if *record {
+                outcomes
+                    .iter()
+                    .filter(|o| !o.screen.is_empty())
+                    .for_each(|o| golden.insert(o.rom, o.profile, o.hash()));
+                if let Err(e) = golden.save(&golden_path) {
+                    eprintln!("Error writing {}: {e}", golden_path.display());
+                    process::exit(1);
+                }
+            } else if outcomes.iter().any(|o| {
+                matches!(
+                    o.status,
+                    Status::Fail { .. } | Status::Checks { .. } | Status::Faulted(_)
+                )
+            }) {
+                process::exit(1);
             }
         }
     }
 }
 
+fn emulate(
+    chip: &mut Chip,
+    mut runner: Runner,
+    frontend: &mut impl Frontend,
+    ips: u32,
+    colours: Option<[u32; 2]>,
+    mut speaker: Option<Speaker>,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let (width, height) = chip.variant.size();
+    let mut buffer = vec![0u32; width * height];
+    let mut speed = Speed::new(ips);
+    let mut paused = false;
+    // The VIP engines set their own pace, so the speed hotkeys do nothing.
+    let paced = matches!(runner, Runner::Vip(_) | Runner::Cosmac(_));
+    let label = |speed: &Speed| {
+        if paced {
+            "VIP timing".to_string()
+        } else {
+            speed.to_string()
+        }
+    };
+    frontend.status(&label(&speed));
+
+    while frontend.is_open() {
+        let mut frames = 1;
+        for hotkey in frontend.hotkeys() {
+            match hotkey {
+                Hotkey::SpeedUp => speed.ips = speed.ips.saturating_mul(2).min(MAX_IPS),
+                Hotkey::SlowDown => speed.ips = (speed.ips / 2).max(1),
+                Hotkey::Pause => paused = !paused,
+                Hotkey::FastForward => frames = FAST_FORWARD,
+            }
+            if hotkey != Hotkey::FastForward {
+                let status = if paused {
+                    format!("{} (paused)", label(&speed))
+                } else {
+                    label(&speed)
+                };
+                frontend.status(&status);
+            }
+        }
+
+        if !paused {
+            for _ in 0..frames {
+                let cycles = speed.cycles();
+                runner.frame(chip, cycles, &mut buffer, frontend)?;
+            }
+            // One frame of sound per frame shown, so fast-forwarding does not
+            // get ahead of the player.
+            if let Some(speaker) = &mut speaker {
+                speaker.play(chip)?;
+            }
+        }
+        frontend.inspect(chip)?;
+        if chip.variant == Variant::Chip8x && frontend.shows_colour() {
+            frontend.present(&chip.colours.paint(&buffer), width, height)?;
+        } else if let (Some([background, foreground]), true) =
+            (colours, frontend.shows_colour() && !chip.mega.enabled)
+        {
+            let shown: Vec<u32> = buffer
+                .iter()
+                .map(|&pixel| if pixel == 0 { background } else { foreground })
+                .collect();
+            frontend.present(&shown, width, height)?;
+        } else {
+            frontend.present(&buffer, width, height)?;
+        }
+    }
+    Ok(())
+}
+
+/// Spreads `ips` instructions over the 60 frames of each second.
+struct Speed {
+    ips: u32,
+    remainder: u32,
+}
+
+impl Speed {
+    fn new(ips: u32) -> Speed {
+        Speed { ips, remainder: 0 }
+    }
+
+    fn cycles(&mut self) -> usize {
+        let total = self.ips + self.remainder;
+        self.remainder = total % FPS;
+        (total / FPS) as usize
+    }
+}
+
+impl std::fmt::Display for Speed {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{} ips", self.ips)
+    }
+}
+
+fn parse_address(s: &str) -> Result<u16, String> {
+    match s.strip_prefix("0x").or(s.strip_prefix("0X")) {
+        Some(hex) => u16::from_str_radix(hex, 16),
+        None => s.parse(),
+    }
+    .map_err(|e| e.to_string())
+}
+
+fn parse_palette(s: &str) -> Result<[u32; 2], String> {
+    let colours: Vec<u32> = s
+        .split(',')
+        .map(|colour| config::parse_colour(colour.trim()))
+        .collect::<Option<_>>()
+        .ok_or("expected colours like #RRGGBB")?;
+    colours
+        .try_into()
+        .map_err(|_| "expected a background and a foreground colour".to_string())
+}
+
 #[test]
 fn verify_cli() {
     use clap::CommandFactory;
     Cli::command().debug_assert();
 }
+
+#[test]
+fn test_parse_address() {
+    assert_eq!(parse_address("0x600"), Ok(0x600));
+    assert_eq!(parse_address("0X600"), Ok(0x600));
+    assert_eq!(parse_address("1536"), Ok(0x600));
+    assert!(parse_address("600h").is_err());
+    assert!(parse_address("0x10000").is_err());
+}
@@ -0,0 +1,326 @@
+use crate::audio;
+
+const WIDTH: usize = 256;
+const HEIGHT: usize = 192;
+
+/// How 080N mixes sprite pixels with what is already on screen.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Blend {
+    #[default]
+    Normal,
+    /// The sprite at 25% or 50% opacity.
+    Quarter,
+    Half,
+    Add,
+    Multiply,
+}
+
+/// A 060N sample: `len` unsigned 8-bit samples at `rate` Hz, from `start`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Sample {
+    pub rate: u16,
+    pub start: u32,
+    pub len: u32,
+    pub looping: bool,
+    /// Output samples played so far, at `audio::RATE`.
+    pub played: u64,
+}
+
+impl Sample {
+    /// 060N: `header` is the six bytes at I, a 16-bit rate, a 24-bit length
+    /// and a reserved byte, followed by the samples at `start`.
+    pub fn new(header: &[u8], start: u32, looping: bool) -> Sample {
+        Sample {
+            rate: u16::from_be_bytes([header[0], header[1]]),
+            start,
+            len: u32::from_be_bytes([0, header[2], header[3], header[4]]),
+            looping,
+            played: 0,
+        }
+    }
+
+    /// Fills `out` with the next stretch of the sample from `mem`, resampled
+    /// to `audio::RATE`. False once a sample that does not loop has ended,
+    /// leaving the rest of `out` as it was.
+    pub fn play(&mut self, mem: &[u8], out: &mut [u8]) -> bool {
+        for level in out {
+            let mut index = self.played * self.rate as u64 / audio::RATE as u64;
+            if index >= self.len as u64 {
+                if !self.looping || self.len == 0 {
+                    return false;
+                }
+                index %= self.len as u64;
+            }
+            *level = mem[self.start as usize + index as usize];
+            self.played += 1;
+        }
+        true
+    }
+}
+
+/// MegaChip's display and sound state. Sprites are drawn to a back buffer of
+/// palette indices and colours, which 00E0 shows and then clears, so the
+/// buffer passed to `Chip` only changes once per 00E0.
+#[derive(Clone, Debug, Default)]
+pub struct Megachip {
+    /// Set by 0011 and cleared by 0010; the other instructions work either
+    /// way, but DXYN and 00E0 only behave differently while it is set.
+    pub enabled: bool,
+    /// The sample 060N started and 0700 stopped.
+    pub sample: Option<Sample>,
+    palette: Vec<u32>,
+    sprite_width: usize,
+    sprite_height: usize,
+    alpha: u8,
+    blend: Blend,
+    collision: u8,
+    indices: Vec<u8>,
+    screen: Vec<u32>,
+}
+
+impl Megachip {
+    /// 0011.
+    pub fn enable(&mut self) {
+        self.enabled = true;
+        self.allocate();
+    }
+
+    // The back buffer is only allocated the first time a MegaChip instruction
+    // needs it, enabled or not.
+    fn allocate(&mut self) {
+        if self.screen.is_empty() {
+            self.palette = vec![0; 256];
+            self.sprite_width = 256;
+            self.sprite_height = 256;
+            self.alpha = 0xFF;
+            self.indices = vec![0; WIDTH * HEIGHT];
+            self.screen = vec![0; WIDTH * HEIGHT];
+        }
+    }
+
+    /// 02NN: `colours` are NN ARGB entries, loaded into palette indices
+    /// 1-NN. Index 0 is always transparent.
+    pub fn load_palette(&mut self, colours: &[u8]) {
+        self.allocate();
+        for (index, argb) in colours.chunks_exact(4).enumerate() {
+            self.palette[index + 1] = u32::from_be_bytes([argb[0], argb[1], argb[2], argb[3]]);
+        }
+    }
+
+    /// 03NN and 04NN, where 0 means 256.
+    pub fn set_sprite_size(&mut self, width: Option<u8>, height: Option<u8>) {
+        self.allocate();
+        let size = |nn: u8| if nn == 0 { 256 } else { nn as usize };
+        if let Some(width) = width {
+            self.sprite_width = size(width);
+        }
+        if let Some(height) = height {
+            self.sprite_height = size(height);
+        }
+    }
+
+    pub fn sprite_len(&self) -> usize {
+        self.sprite_width * self.sprite_height
+    }
+
+    /// 05NN: how bright the whole screen is when shown.
+    pub fn set_alpha(&mut self, alpha: u8) {
+        self.allocate();
+        self.alpha = alpha;
+    }
+
+    /// 080N. Unknown modes draw normally.
+    pub fn set_blend(&mut self, n: u8) {
+        self.blend = match n {
+            1 => Blend::Quarter,
+            2 => Blend::Half,
+            3 => Blend::Add,
+            4 => Blend::Multiply,
+            _ => Blend::Normal,
+        };
+    }
+
+    /// 09NN.
+    pub fn set_collision(&mut self, index: u8) {
+        self.collision = index;
+    }
+
+    /// DXYN with MegaChip enabled: `sprite` is one palette index per pixel,
+    /// row by row. Pixels past the right and bottom edges are clipped. Returns
+    /// whether a pixel landed on one drawn in the collision colour.
+    pub fn draw(&mut self, x: u8, y: u8, sprite: &[u8]) -> bool {
+        let mut collision = false;
+        for (row, indices) in sprite.chunks(self.sprite_width).enumerate() {
+            let py = y as usize + row;
+            if py >= HEIGHT {
+                break;
+            }
+            for (column, &index) in indices.iter().enumerate() {
+                let px = x as usize + column;
+                if index == 0 || px >= WIDTH {
+                    continue;
+                }
+                let pixel = py * WIDTH + px;
+                let under = self.indices[pixel];
+                collision |= under != 0 && under == self.collision;
+                self.indices[pixel] = index;
+                self.screen[pixel] =
+                    blend(self.palette[index as usize], self.screen[pixel], self.blend);
+            }
+        }
+        collision
+    }
+
+    /// 00E0 with MegaChip enabled: shows the back buffer and clears it.
+    pub fn present(&mut self, buffer: &mut [u32]) {
+        for (shown, &colour) in buffer.iter_mut().zip(&self.screen) {
+            *shown = scale(colour, self.alpha as u32);
+        }
+        self.screen.fill(0);
+        self.indices.fill(0);
+    }
+}
+
+fn channels(colour: u32) -> [u32; 3] {
+    [colour >> 16 & 0xFF, colour >> 8 & 0xFF, colour & 0xFF]
+}
+
+fn join([r, g, b]: [u32; 3]) -> u32 {
+    r.min(0xFF) << 16 | g.min(0xFF) << 8 | b.min(0xFF)
+}
+
+// Each channel times `amount` / 255.
+fn scale(colour: u32, amount: u32) -> u32 {
+    join(channels(colour).map(|c| c * amount / 0xFF))
+}
+
+// `source` is ARGB: the blended colour is then laid over `destination` by
+// the source's alpha.
+fn blend(source: u32, destination: u32, mode: Blend) -> u32 {
+    let (s, d) = (channels(source), channels(destination));
+    let mix = |f: fn(u32, u32) -> u32| [f(s[0], d[0]), f(s[1], d[1]), f(s[2], d[2])];
+    let blended = match mode {
+        Blend::Normal => s,
+        Blend::Quarter => mix(|s, d| (s + 3 * d) / 4),
+        Blend::Half => mix(|s, d| (s + d) / 2),
+        Blend::Add => mix(|s, d| (s + d).min(0xFF)),
+        Blend::Multiply => mix(|s, d| s * d / 0xFF),
+    };
+    let alpha = source >> 24;
+    let over = |b: u32, d: u32| (b * alpha + d * (0xFF - alpha)) / 0xFF;
+    join([
+        over(blended[0], d[0]),
+        over(blended[1], d[1]),
+        over(blended[2], d[2]),
+    ])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Megachip, Sample, WIDTH};
+
+    fn megachip() -> Megachip {
+        let mut megachip = Megachip::default();
+        megachip.load_palette(&[
+            0xFF, 0xFF, 0x00, 0x00, // 1: red
+            0xFF, 0x00, 0x00, 0xFF, // 2: blue
+        ]);
+        megachip.set_sprite_size(Some(2), Some(2));
+        megachip
+    }
+
+    #[test]
+    fn test_draw_and_present() {
+        let mut megachip = megachip();
+        let mut buffer = vec![0u32; 256 * 192];
+
+        assert!(!megachip.draw(10, 20, &[1, 0, 2, 1]));
+        assert!(buffer.iter().all(|&p| p == 0));
+        megachip.present(&mut buffer);
+
+        let at = |x: usize, y: usize| buffer[y * WIDTH + x];
+        assert_eq!(at(10, 20), 0xFF0000);
+        assert_eq!(at(11, 20), 0);
+        assert_eq!(at(10, 21), 0x0000FF);
+        assert_eq!(at(11, 21), 0xFF0000);
+    }
+
+    #[test]
+    fn test_collision_and_blend() {
+        let mut megachip = megachip();
+        let mut buffer = vec![0u32; 256 * 192];
+        megachip.set_collision(2);
+
+        megachip.draw(0, 0, &[2, 2, 0, 0]);
+        megachip.set_blend(3);
+        assert!(megachip.draw(1, 0, &[1, 0, 0, 0]));
+        assert!(!megachip.draw(1, 0, &[1, 0, 0, 0]));
+        megachip.set_alpha(0x80);
+        megachip.present(&mut buffer);
+
+        assert_eq!(buffer[0], 0x000080);
+        assert_eq!(buffer[1], 0x800080);
+    }
+
+    #[test]
+    fn test_palette_alpha() {
+        let mut megachip = Megachip::default();
+        let mut buffer = vec![0u32; 256 * 192];
+        megachip.load_palette(&[
+            0xFF, 0x00, 0x00, 0xFF, // 1: blue
+            0x80, 0xFF, 0x00, 0x00, // 2: half red
+        ]);
+        megachip.set_sprite_size(Some(1), Some(1));
+        assert!(!megachip.enabled);
+
+        megachip.draw(0, 0, &[1]);
+        megachip.draw(0, 0, &[2]);
+        megachip.draw(1, 0, &[2]);
+        megachip.present(&mut buffer);
+
+        assert_eq!(buffer[0], 0x80007F);
+        assert_eq!(buffer[1], 0x800000);
+    }
+
+    #[test]
+    fn test_clipping() {
+        let mut megachip = megachip();
+        let mut buffer = vec![0u32; 256 * 192];
+
+        megachip.draw(255, 191, &[1, 1, 1, 1]);
+        megachip.present(&mut buffer);
+
+        assert_eq!(buffer.iter().filter(|&&p| p != 0).count(), 1);
+    }
+
+    #[test]
+    fn test_sample() {
+        let sample = Sample::new(&[0x1F, 0x40, 0x00, 0x01, 0x00, 0x00], 0x1006, true);
+
+        assert_eq!(
+            sample,
+            Sample {
+                rate: 8000,
+                start: 0x1006,
+                len: 0x100,
+                looping: true,
+                played: 0,
+            }
+        );
+    }
+
+    #[test]
+    fn test_sample_playback() {
+        let mem: Vec<u8> = (0..8).collect();
+        // Two samples at a quarter of the output rate, each heard 4 times.
+        let header = [0x2B, 0x11, 0x00, 0x00, 0x02, 0x00];
+        let mut once = Sample::new(&header, 4, false);
+        let mut looping = Sample::new(&header, 4, true);
+        let (mut a, mut b) = ([0x80; 10], [0x80; 10]);
+
+        assert!(!once.play(&mem, &mut a));
+        assert!(looping.play(&mem, &mut b));
+        assert_eq!(a, [4, 4, 4, 4, 5, 5, 5, 5, 0x80, 0x80]);
+        assert_eq!(b, [4, 4, 4, 4, 5, 5, 5, 5, 4, 4]);
+    }
+}
@@ -0,0 +1,834 @@
+use std::{collections::HashMap, error::Error, fmt::Display};
+
+const START: usize = 0x200;
+const MEMORY: usize = 0x10000;
+
+/// A problem in Octo source, at a 1-based line.
+#[derive(Debug, PartialEq, Eq)]
+pub struct AsmError {
+    pub line: usize,
+    pub message: String,
+}
+
+impl Display for AsmError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "line {}: {}", self.line, self.message)
+    }
+}
+
+impl Error for AsmError {}
+
+/// An assembled program, with the source line of each instruction.
+#[derive(Debug)]
+pub struct Program {
+    /// The rom, to load at 0x200.
+    pub rom: Vec<u8>,
+    /// The address and source line of every instruction, in source order.
+    pub lines: Vec<(u16, usize)>,
+}
+
+impl Program {
+    /// The first address assembled from a source line.
+    pub fn address(&self, line: usize) -> Option<u16> {
+        self.lines
+            .iter()
+            .filter(|&&(_, l)| l == line)
+            .map(|&(address, _)| address)
+            .min()
+    }
+
+    /// The source line an instruction was assembled from.
+    pub fn line(&self, address: u16) -> Option<usize> {
+        self.lines
+            .iter()
+            .find(|&&(a, _)| a == address)
+            .map(|&(_, line)| line)
+    }
+}
+
+/// Assembles Octo source into a rom to load at 0x200. Covers the language
+/// itself but not `:calc`, `:assert` or `:stringmode`.
+pub fn assemble(source: &str) -> Result<Program, AsmError> {
+    let mut assembler = Assembler::new(source);
+    assembler.run()?;
+    assembler.finish()
+}
+
+#[derive(Clone)]
+struct Token {
+    text: String,
+    line: usize,
+}
+
+#[derive(Clone, Copy)]
+enum Fixup {
+    // The low 12 bits of the instruction.
+    Nnn,
+    // A whole 16-bit word, after F000.
+    Long,
+    // The low byte of the instruction, with the address' high or low byte.
+    High(u8),
+    Low,
+}
+
+struct Macro {
+    params: Vec<String>,
+    body: Vec<Token>,
+}
+
+// The jumps a pending `begin`, `else` or `loop` has to patch.
+enum Block {
+    If { jump: usize },
+    Else { jump: usize },
+    Loop { start: usize, breaks: Vec<usize> },
+}
+
+// What an `if` or `while` tests: instructions that set VF first, then the
+// skips that skip the next instruction when the condition is false or true.
+struct Condition {
+    prelude: Vec<u16>,
+    unless: u16,
+    when: u16,
+}
+
+struct Assembler {
+    // Taken from the end, so macros can push their expansion.
+    tokens: Vec<Token>,
+    line: usize,
+    memory: Vec<u8>,
+    written: Vec<bool>,
+    here: usize,
+    end: usize,
+    labels: HashMap<String, usize>,
+    constants: HashMap<String, i64>,
+    aliases: HashMap<String, u8>,
+    macros: HashMap<String, Macro>,
+    fixups: Vec<(usize, String, Fixup, usize)>,
+    blocks: Vec<Block>,
+    next: Option<String>,
+    lines: Vec<(u16, usize)>,
+}
+
+impl Assembler {
+    fn new(source: &str) -> Assembler {
+        let mut tokens: Vec<Token> = source
+            .lines()
+            .enumerate()
+            .flat_map(|(index, line)| {
+                let code = line.split('#').next().unwrap_or_default();
+                code.split_whitespace().map(move |text| Token {
+                    text: text.to_string(),
+                    line: index + 1,
+                })
+            })
+            .collect();
+        tokens.reverse();
+        Assembler {
+            tokens,
+            line: 0,
+            memory: vec![0; MEMORY],
+            written: vec![false; MEMORY],
+            here: START,
+            end: START,
+            labels: HashMap::new(),
+            constants: HashMap::new(),
+            aliases: HashMap::new(),
+            macros: HashMap::new(),
+            fixups: Vec::new(),
+            blocks: Vec::new(),
+            next: None,
+            lines: Vec::new(),
+        }
+    }
+
+    fn error<T>(&self, message: impl Into<String>) -> Result<T, AsmError> {
+        Err(AsmError {
+            line: self.line,
+            message: message.into(),
+        })
+    }
+
+    fn peek(&self) -> Option<&str> {
+        self.tokens.last().map(|token| token.text.as_str())
+    }
+
+    fn take(&mut self) -> Result<String, AsmError> {
+        match self.tokens.pop() {
+            Some(token) => {
+                self.line = token.line;
+                Ok(token.text)
+            }
+            None => self.error("unexpected end of the program"),
+        }
+    }
+
+    fn expect(&mut self, text: &str) -> Result<(), AsmError> {
+        let token = self.take()?;
+        if token != text {
+            return self.error(format!("expected `{text}`, found `{token}`"));
+        }
+        Ok(())
+    }
+
+    fn run(&mut self) -> Result<(), AsmError> {
+        while !self.tokens.is_empty() {
+            let token = self.take()?;
+            self.statement(&token)?;
+        }
+        if !self.blocks.is_empty() {
+            return self.error("a `begin` or `loop` is never closed");
+        }
+        Ok(())
+    }
+
+    fn finish(mut self) -> Result<Program, AsmError> {
+        for (address, name, fixup, line) in std::mem::take(&mut self.fixups) {
+            self.line = line;
+            let Some(&target) = self.labels.get(&name) else {
+                return self.error(format!("undefined name `{name}`"));
+            };
+            match fixup {
+                Fixup::Nnn => {
+                    if target > 0xFFF {
+                        return self.error(format!("`{name}` is past 0xFFF"));
+                    }
+                    self.memory[address] |= (target >> 8) as u8;
+                    self.memory[address + 1] = target as u8;
+                }
+                Fixup::Long => {
+                    self.memory[address] = (target >> 8) as u8;
+                    self.memory[address + 1] = target as u8;
+                }
+                Fixup::High(nibble) => self.memory[address + 1] = nibble << 4 | (target >> 8) as u8,
+                Fixup::Low => self.memory[address + 1] = target as u8,
+            }
+        }
+        Ok(Program {
+            rom: self.memory[START..self.end].to_vec(),
+            lines: self.lines,
+        })
+    }
+
+    fn byte(&mut self, value: u8) -> Result<(), AsmError> {
+        if self.here >= MEMORY {
+            return self.error("the program does not fit in memory");
+        }
+        if let Some(name) = self.next.take() {
+            self.labels.insert(name, self.here + 1);
+        }
+        if self.written[self.here] {
+            return self.error(format!("{:#05X} is written twice", self.here));
+        }
+        self.written[self.here] = true;
+        self.memory[self.here] = value;
+        self.here += 1;
+        self.end = self.end.max(self.here);
+        Ok(())
+    }
+
+    fn word(&mut self, word: u16) -> Result<(), AsmError> {
+        let [high, low] = word.to_be_bytes();
+        let address = self.here as u16;
+        self.byte(high)?;
+        self.byte(low)?;
+        self.lines.push((address, self.line));
+        Ok(())
+    }
+
+    // An instruction whose low bits are the address of `name`, now or once
+    // it is defined.
+    fn word_to(&mut self, word: u16, name: &str, fixup: Fixup) -> Result<(), AsmError> {
+        let address = self.here;
+        self.word(word)?;
+        self.fixups
+            .push((address, name.to_string(), fixup, self.line));
+        Ok(())
+    }
+
+    fn statement(&mut self, token: &str) -> Result<(), AsmError> {
+        if let Some(definition) = self.macros.get(token) {
+            let params = definition.params.clone();
+            let body = definition.body.clone();
+            let args = (0..params.len())
+                .map(|_| self.take())
+                .collect::<Result<Vec<_>, _>>()?;
+            // Pushed in reverse, so the expansion is read first.
+            for mut token in body.into_iter().rev() {
+                if let Some(index) = params.iter().position(|param| *param == token.text) {
+                    token.text = args[index].clone();
+                }
+                self.tokens.push(token);
+            }
+            return Ok(());
+        }
+        match token {
+            ":" => {
+                let name = self.take()?;
+                self.define(name, self.here)
+            }
+            ":const" => {
+                let name = self.take()?;
+                let value = self.take()?;
+                let value = self.number(&value)?;
+                self.constants.insert(name, value);
+                Ok(())
+            }
+            ":alias" => {
+                let name = self.take()?;
+                let register = self.take()?;
+                let register = self.register(&register)?;
+                self.aliases.insert(name, register);
+                Ok(())
+            }
+            ":org" => {
+                let address = self.take()?;
+                self.here = self.number(&address)? as usize;
+                if !(START..MEMORY).contains(&self.here) {
+                    return self.error(format!("cannot assemble at {:#X}", self.here));
+                }
+                Ok(())
+            }
+            ":byte" => {
+                let value = self.take()?;
+                let value = self.immediate(&value)?;
+                self.byte(value)
+            }
+            ":next" => {
+                self.next = Some(self.take()?);
+                Ok(())
+            }
+            ":unpack" => {
+                let nibble = self.take()?;
+                let nibble = self.number(&nibble)? as u8 & 0xF;
+                let name = self.take()?;
+                self.word_to(0x6000 | (nibble as u16) << 4, &name, Fixup::High(nibble))?;
+                self.word_to(0x6100, &name, Fixup::Low)
+            }
+            ":call" => {
+                let name = self.take()?;
+                self.address(0x2000, &name)
+            }
+            ":macro" => self.define_macro(),
+            ":breakpoint" | ":proto" => self.take().map(drop),
+            ":monitor" => {
+                self.take()?;
+                self.take().map(drop)
+            }
+            ":calc" | ":assert" | ":stringmode" | ":include" | ":segment" => {
+                self.error(format!("`{token}` is not supported"))
+            }
+            "clear" => self.word(0x00E0),
+            "return" | ";" => self.word(0x00EE),
+            "exit" => self.word(0x00FD),
+            "lores" => self.word(0x00FE),
+            "hires" => self.word(0x00FF),
+            "scroll-left" => self.word(0x00FC),
+            "scroll-right" => self.word(0x00FB),
+            "scroll-down" | "scroll-up" => {
+                let n = self.take()?;
+                let n = self.number(&n)? as u16 & 0xF;
+                self.word(
+                    if token == "scroll-down" {
+                        0x00C0
+                    } else {
+                        0x00D0
+                    } | n,
+                )
+            }
+            "audio" => self.word(0xF002),
+            "plane" => {
+                let n = self.take()?;
+                let n = self.number(&n)? as u16 & 0xF;
+                self.word(0xF001 | n << 8)
+            }
+            "pitch" => {
+                self.expect(":=")?;
+                let x = self.take_register()?;
+                self.word(0xF03A | x << 8)
+            }
+            "jump" => {
+                let target = self.take()?;
+                self.address(0x1000, &target)
+            }
+            "jump0" => {
+                let target = self.take()?;
+                self.address(0xB000, &target)
+            }
+            "native" => {
+                let target = self.take()?;
+                self.address(0x0000, &target)
+            }
+            "sprite" => {
+                let x = self.take_register()?;
+                let y = self.take_register()?;
+                let n = self.take()?;
+                let n = self.number(&n)? as u16 & 0xF;
+                self.word(0xD000 | x << 8 | y << 4 | n)
+            }
+            "save" | "load" => {
+                let x = self.take_register()?;
+                if self.peek() == Some("-") {
+                    self.take()?;
+                    let y = self.take_register()?;
+                    let op = if token == "save" { 0x5002 } else { 0x5003 };
+                    return self.word(op | x << 8 | y << 4);
+                }
+                let op = if token == "save" { 0xF055 } else { 0xF065 };
+                self.word(op | x << 8)
+            }
+            "bcd" => {
+                let x = self.take_register()?;
+                self.word(0xF033 | x << 8)
+            }
+            "delay" | "buzzer" => {
+                self.expect(":=")?;
+                let x = self.take_register()?;
+                self.word(if token == "delay" { 0xF015 } else { 0xF018 } | x << 8)
+            }
+            "i" => self.assign_i(),
+            "if" => self.conditional(),
+            "else" => match self.blocks.pop() {
+                Some(Block::If { jump }) => {
+                    let skip = self.here;
+                    self.word(0x1000)?;
+                    self.patch(jump, self.here);
+                    self.blocks.push(Block::Else { jump: skip });
+                    Ok(())
+                }
+                _ => self.error("`else` without `begin`"),
+            },
+            "end" => match self.blocks.pop() {
+                Some(Block::If { jump } | Block::Else { jump }) => {
+                    self.patch(jump, self.here);
+                    Ok(())
+                }
+                _ => self.error("`end` without `begin`"),
+            },
+            "loop" => {
+                self.blocks.push(Block::Loop {
+                    start: self.here,
+                    breaks: Vec::new(),
+                });
+                Ok(())
+            }
+            "while" => {
+                let condition = self.condition()?;
+                self.test(&condition, condition.when)?;
+                let jump = self.here;
+                self.word(0x1000)?;
+                match self.blocks.iter_mut().rev().find_map(|block| match block {
+                    Block::Loop { breaks, .. } => Some(breaks),
+                    _ => None,
+                }) {
+                    Some(breaks) => {
+                        breaks.push(jump);
+                        Ok(())
+                    }
+                    None => self.error("`while` outside a loop"),
+                }
+            }
+            "again" => match self.blocks.pop() {
+                Some(Block::Loop { start, breaks }) => {
+                    self.word(0x1000 | start as u16)?;
+                    for jump in breaks {
+                        self.patch(jump, self.here);
+                    }
+                    Ok(())
+                }
+                _ => self.error("`again` without `loop`"),
+            },
+            _ if self.register(token).is_ok() => self.assign_register(token),
+            _ if self.number(token).is_ok() => {
+                let value = self.immediate(token)?;
+                self.byte(value)
+            }
+            _ if is_name(token) => self.address(0x2000, token),
+            _ => self.error(format!("unexpected `{token}`")),
+        }
+    }
+
+    fn define(&mut self, name: String, address: usize) -> Result<(), AsmError> {
+        if !is_name(&name) || self.labels.contains_key(&name) {
+            return self.error(format!("cannot define `{name}` here"));
+        }
+        self.labels.insert(name, address);
+        Ok(())
+    }
+
+    fn define_macro(&mut self) -> Result<(), AsmError> {
+        let name = self.take()?;
+        let mut params = Vec::new();
+        loop {
+            let token = self.take()?;
+            if token == "{" {
+                break;
+            }
+            params.push(token);
+        }
+        let mut body = Vec::new();
+        let mut depth = 1;
+        loop {
+            let token = self.tokens.pop();
+            let Some(token) = token else {
+                return self.error(format!("macro `{name}` is never closed"));
+            };
+            depth += match token.text.as_str() {
+                "{" => 1,
+                "}" => -1,
+                _ => 0,
+            };
+            if depth == 0 {
+                break;
+            }
+            body.push(token);
+        }
+        self.macros.insert(name, Macro { params, body });
+        Ok(())
+    }
+
+    // `op` with the 12-bit address `target`, a number or a label.
+    fn address(&mut self, op: u16, target: &str) -> Result<(), AsmError> {
+        if let Ok(value) = self.number(target) {
+            return self.word(op | value as u16 & 0xFFF);
+        }
+        if !is_name(target) {
+            return self.error(format!("expected an address, found `{target}`"));
+        }
+        self.word_to(op, target, Fixup::Nnn)
+    }
+
+    fn patch(&mut self, jump: usize, target: usize) {
+        self.memory[jump] = 0x10 | (target >> 8) as u8 & 0xF;
+        self.memory[jump + 1] = target as u8;
+    }
+
+    fn number(&self, token: &str) -> Result<i64, AsmError> {
+        if let Some(&value) = self.constants.get(token) {
+            return Ok(value);
+        }
+        let (negative, digits) = match token.strip_prefix('-') {
+            Some(digits) => (true, digits),
+            None => (false, token),
+        };
+        let value = if let Some(hex) = digits.strip_prefix("0x") {
+            i64::from_str_radix(hex, 16)
+        } else if let Some(binary) = digits.strip_prefix("0b") {
+            i64::from_str_radix(binary, 2)
+        } else {
+            digits.parse()
+        };
+        match value {
+            Ok(value) if negative => Ok(-value),
+            Ok(value) => Ok(value),
+            Err(_) => self.error(format!("expected a number, found `{token}`")),
+        }
+    }
+
+    // A byte, where -128 to -1 wrap around.
+    fn immediate(&self, token: &str) -> Result<u8, AsmError> {
+        match self.number(token)? {
+            value @ -128..=255 => Ok(value as u8),
+            value => self.error(format!("{value} does not fit in a byte")),
+        }
+    }
+
+    fn register(&self, token: &str) -> Result<u8, AsmError> {
+        if let Some(&register) = self.aliases.get(token) {
+            return Ok(register);
+        }
+        match token.strip_prefix(['v', 'V']) {
+            Some(digit) if digit.len() == 1 => u8::from_str_radix(digit, 16)
+                .or_else(|_| self.error(format!("`{token}` is not a register"))),
+            _ => self.error(format!("`{token}` is not a register")),
+        }
+    }
+
+    fn take_register(&mut self) -> Result<u16, AsmError> {
+        let token = self.take()?;
+        Ok(self.register(&token)? as u16)
+    }
+
+    fn assign_i(&mut self) -> Result<(), AsmError> {
+        let op = self.take()?;
+        if op == "+=" {
+            let x = self.take_register()?;
+            return self.word(0xF01E | x << 8);
+        }
+        if op != ":=" {
+            return self.error(format!("unexpected `{op}` after `i`"));
+        }
+        let source = self.take()?;
+        match source.as_str() {
+            "hex" | "bighex" => {
+                let x = self.take_register()?;
+                self.word(if source == "hex" { 0xF029 } else { 0xF030 } | x << 8)
+            }
+            "long" => {
+                let target = self.take()?;
+                self.word(0xF000)?;
+                match self.number(&target) {
+                    Ok(value) => self.word(value as u16),
+                    Err(_) => self.word_to(0, &target, Fixup::Long),
+                }
+            }
+            _ => self.address(0xA000, &source),
+        }
+    }
+
+    fn assign_register(&mut self, token: &str) -> Result<(), AsmError> {
+        let x = self.register(token)? as u16;
+        let op = self.take()?;
+        let source = self.take()?;
+        if let Ok(y) = self.register(&source) {
+            let y = y as u16;
+            let n = match op.as_str() {
+                ":=" => 0x0,
+                "|=" => 0x1,
+                "&=" => 0x2,
+                "^=" => 0x3,
+                "+=" => 0x4,
+                "-=" => 0x5,
+                ">>=" => 0x6,
+                "=-" => 0x7,
+                "<<=" => 0xE,
+                _ => return self.error(format!("unexpected `{op}`")),
+            };
+            return self.word(0x8000 | x << 8 | y << 4 | n);
+        }
+        match (op.as_str(), source.as_str()) {
+            (":=", "key") => self.word(0xF00A | x << 8),
+            (":=", "delay") => self.word(0xF007 | x << 8),
+            (":=", "random") => {
+                let mask = self.take()?;
+                let mask = self.immediate(&mask)? as u16;
+                self.word(0xC000 | x << 8 | mask)
+            }
+            (":=", _) => {
+                let nn = self.immediate(&source)? as u16;
+                self.word(0x6000 | x << 8 | nn)
+            }
+            ("+=", _) => {
+                let nn = self.immediate(&source)? as u16;
+                self.word(0x7000 | x << 8 | nn)
+            }
+            ("-=", _) => {
+                let nn = self.immediate(&source)?.wrapping_neg() as u16;
+                self.word(0x7000 | x << 8 | nn)
+            }
+            _ => self.error(format!("unexpected `{op} {source}`")),
+        }
+    }
+
+    fn conditional(&mut self) -> Result<(), AsmError> {
+        let condition = self.condition()?;
+        let form = self.take()?;
+        match form.as_str() {
+            "then" => self.test(&condition, condition.unless),
+            "begin" => {
+                self.test(&condition, condition.when)?;
+                self.blocks.push(Block::If { jump: self.here });
+                self.word(0x1000)
+            }
+            _ => self.error(format!("expected `then` or `begin`, found `{form}`")),
+        }
+    }
+
+    fn test(&mut self, condition: &Condition, skip: u16) -> Result<(), AsmError> {
+        for &word in &condition.prelude {
+            self.word(word)?;
+        }
+        self.word(skip)
+    }
+
+    fn condition(&mut self) -> Result<Condition, AsmError> {
+        let x = self.take_register()?;
+        let op = self.take()?;
+        let simple = |unless, when| Condition {
+            prelude: Vec::new(),
+            unless,
+            when,
+        };
+        match op.as_str() {
+            "key" => return Ok(simple(0xE0A1 | x << 8, 0xE09E | x << 8)),
+            "-key" => return Ok(simple(0xE09E | x << 8, 0xE0A1 | x << 8)),
+            _ => {}
+        }
+        let operand = self.take()?;
+        let register = self.register(&operand).ok().map(u16::from);
+        let nn = match register {
+            Some(_) => 0,
+            None => self.immediate(&operand)? as u16,
+        };
+        Ok(match (op.as_str(), register) {
+            ("==", Some(y)) => simple(0x9000 | x << 8 | y << 4, 0x5000 | x << 8 | y << 4),
+            ("!=", Some(y)) => simple(0x5000 | x << 8 | y << 4, 0x9000 | x << 8 | y << 4),
+            ("==", None) => simple(0x4000 | x << 8 | nn, 0x3000 | x << 8 | nn),
+            ("!=", None) => simple(0x3000 | x << 8 | nn, 0x4000 | x << 8 | nn),
+            // VF is set to whether one side is at least the other, and then
+            // tested like `vf == 0` or `vf == 1`.
+            ("<" | ">=" | ">" | "<=", _) => {
+                let swapped = matches!(op.as_str(), ">" | "<=");
+                let prelude = match (register, swapped) {
+                    // VF = x >= y
+                    (Some(y), false) => vec![0x8F00 | x << 4, 0x8F05 | y << 4],
+                    // VF = y >= x
+                    (Some(y), true) => vec![0x8F00 | y << 4, 0x8F05 | x << 4],
+                    // VF = x >= nn
+                    (None, false) => vec![0x6F00 | nn, 0x8F07 | x << 4],
+                    // VF = nn >= x
+                    (None, true) => vec![0x6F00 | nn, 0x8F05 | x << 4],
+                };
+                let holds = matches!(op.as_str(), ">=" | "<=") as u16;
+                Condition {
+                    prelude,
+                    unless: 0x4F00 | holds,
+                    when: 0x3F00 | holds,
+                }
+            }
+            _ => return self.error(format!("unexpected `{op}` in a condition")),
+        })
+    }
+}
+
+fn is_name(token: &str) -> bool {
+    token
+        .chars()
+        .next()
+        .is_some_and(|c| c.is_ascii_alphabetic() || c == '_')
+        && token
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-')
+}
+
+#[cfg(test)]
+mod tests {
+    use super::assemble;
+
+    fn words(rom: &[u8]) -> Vec<u16> {
+        rom.chunks(2)
+            .map(|pair| u16::from_be_bytes([pair[0], pair[1]]))
+            .collect()
+    }
+
+    #[test]
+    fn test_statements() {
+        let rom = assemble(
+            "
+            : main
+              clear
+              v0 := 5      # a comment
+              v1 += -1
+              v0 += v1
+              i := sprite
+              sprite v0 v1 3
+              if v0 == 5 then v2 := random 0x0F
+              jump main
+            : sprite
+              0x80 0b11000000 255 0
+            ",
+        )
+        .unwrap()
+        .rom;
+
+        assert_eq!(
+            words(&rom),
+            [
+                0x00E0, 0x6005, 0x71FF, 0x8014, 0xA212, 0xD013, 0x4005, 0xC20F, 0x1200, 0x80C0,
+                0xFF00
+            ]
+        );
+    }
+
+    #[test]
+    fn test_blocks() {
+        let rom = assemble(
+            "
+            :const limit 10
+            :alias counter v3
+            loop
+              counter += 1
+              while counter < limit
+              if counter key begin
+                draw
+              else
+                ;
+              end
+            again
+            : draw return
+            ",
+        )
+        .unwrap()
+        .rom;
+
+        assert_eq!(
+            words(&rom),
+            [
+                0x7301, // 0x200: counter += 1
+                0x6F0A, 0x8F37, 0x3F00, 0x1216, // while: VF = counter >= limit
+                0xE39E, 0x1212, // begin
+                0x2216, 0x1214, // draw, then over else
+                0x00EE, // 0x212
+                0x1200, // 0x214: again
+                0x00EE, // 0x216: draw
+            ]
+        );
+    }
+
+    #[test]
+    fn test_directives() {
+        let rom = assemble(
+            "
+            :macro twice op { op op }
+            twice clear
+            :unpack 0xA data
+            :next target v0 := 0
+            i := long data
+            :org 0x220
+            : data :byte -2 :byte 9 jump target
+            ",
+        )
+        .unwrap()
+        .rom;
+
+        assert_eq!(
+            words(&rom[..14]),
+            [0x00E0, 0x00E0, 0x60A2, 0x6120, 0x6000, 0xF000, 0x0220]
+        );
+        assert_eq!(rom[0x20..], [0xFE, 0x09, 0x12, 0x09]);
+    }
+
+    #[test]
+    fn test_lines() {
+        let program = assemble(
+            "
+            : main
+              clear
+              v0 := 5  v1 := 6
+              sprite v0 v1 0
+
+              jump main
+            ",
+        )
+        .unwrap();
+
+        assert_eq!(
+            program.lines,
+            [(0x200, 3), (0x202, 4), (0x204, 4), (0x206, 5), (0x208, 7)]
+        );
+        assert_eq!(program.address(4), Some(0x202));
+        assert_eq!(program.address(6), None);
+        assert_eq!(program.line(0x206), Some(5));
+        assert_eq!(program.line(0x207), None);
+    }
+
+    #[test]
+    fn test_errors() {
+        let error = |source| assemble(source).unwrap_err();
+
+        assert_eq!(error("clear\njump nowhere").line, 2);
+        assert!(error("v0 := 300").message.contains("byte"));
+        assert!(error("loop clear").message.contains("never closed"));
+        assert!(error(": a : a").message.contains("`a`"));
+        assert!(error(":calc x { 1 + 2 }").message.contains("not supported"));
+    }
+}
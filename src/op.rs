@@ -0,0 +1,293 @@
+use crate::variant::Variant;
+
+/// An instruction decoded once so it can be run again without looking at its
+/// nibbles. Register operands are indices into `V`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Op {
+    Cls,
+    Ret,
+    /// 0NNN, a call into machine code.
+    Sys(u16),
+    Jump(u16),
+    Call(u16),
+    SkipEqImm {
+        x: usize,
+        nn: u8,
+    },
+    SkipNeImm {
+        x: usize,
+        nn: u8,
+    },
+    SkipEq {
+        x: usize,
+        y: usize,
+    },
+    SkipNe {
+        x: usize,
+        y: usize,
+    },
+    LoadImm {
+        x: usize,
+        nn: u8,
+    },
+    AddImm {
+        x: usize,
+        nn: u8,
+    },
+    Move {
+        x: usize,
+        y: usize,
+    },
+    Or {
+        x: usize,
+        y: usize,
+    },
+    And {
+        x: usize,
+        y: usize,
+    },
+    Xor {
+        x: usize,
+        y: usize,
+    },
+    Add {
+        x: usize,
+        y: usize,
+    },
+    Sub {
+        x: usize,
+        y: usize,
+    },
+    ShiftRight {
+        x: usize,
+        y: usize,
+    },
+    SubReverse {
+        x: usize,
+        y: usize,
+    },
+    ShiftLeft {
+        x: usize,
+        y: usize,
+    },
+    LoadI(u16),
+    JumpOffset {
+        x: usize,
+        nnn: u16,
+    },
+    Random {
+        x: usize,
+        nn: u8,
+    },
+    Draw {
+        x: usize,
+        y: usize,
+        n: u8,
+    },
+    SkipKey(usize),
+    SkipNoKey(usize),
+    GetDelay(usize),
+    WaitKey(usize),
+    SetDelay(usize),
+    SetSound(usize),
+    AddI(usize),
+    Bcd(usize),
+    Store(usize),
+    Load(usize),
+    /// CHIP-8X 02A0.
+    CycleBackground,
+    /// CHIP-8X 5XY1: adds VY to VX, each nibble on its own and kept to three
+    /// bits.
+    AddColour {
+        x: usize,
+        y: usize,
+    },
+    /// CHIP-8X BXYN.
+    SetColour {
+        x: usize,
+        y: usize,
+        n: u8,
+    },
+    /// CHIP-8X EXF2/EXF5, on the second keypad.
+    SkipKey2(usize),
+    SkipNoKey2(usize),
+    /// CHIP-8X FXF8/FXFB, on the I/O port.
+    Output(usize),
+    Input(usize),
+    /// MegaChip 0010/0011.
+    MegaOff,
+    MegaOn,
+    /// MegaChip 01NN NNNN: I = NN and the next word, 24 bits in all.
+    LoadLongI(u8),
+    /// MegaChip 02NN: NN palette entries from I.
+    Palette(u8),
+    /// MegaChip 03NN/04NN.
+    SpriteWidth(u8),
+    SpriteHeight(u8),
+    /// MegaChip 05NN.
+    ScreenAlpha(u8),
+    /// MegaChip 060N, looping when N is 0.
+    PlaySample(u8),
+    /// MegaChip 0700.
+    StopSample,
+    /// MegaChip 080N.
+    Blend(u8),
+    /// MegaChip 09NN.
+    CollisionColour(u8),
+    Unknown(u16),
+}
+
+impl Op {
+    /// Decodes `opcode` at `pc` as `variant` defines it.
+    pub fn decode_at(opcode: u16, variant: Variant, pc: u16) -> Op {
+        match (variant, opcode, pc) {
+            // The hi-res interpreter's own code runs up to 0x2C0, where the
+            // program starts. Elsewhere 1260 is an ordinary jump.
+            (Variant::Hires, 0x1260, 0x200) => Op::Jump(0x2C0),
+            _ => Op::decode_for(opcode, variant),
+        }
+    }
+
+    /// Decodes `opcode` as `variant` defines it, wherever it is.
+    pub fn decode_for(opcode: u16, variant: Variant) -> Op {
+        let x = (opcode >> 8 & 0xF) as usize;
+        let y = (opcode >> 4 & 0xF) as usize;
+        let n = (opcode & 0xF) as u8;
+        match (variant, opcode) {
+            (Variant::Hires, 0x0230) => Op::Cls,
+            (Variant::Chip8x, 0x02A0) => Op::CycleBackground,
+            (Variant::Chip8x, _) if opcode & 0xF00F == 0x5001 => Op::AddColour { x, y },
+            (Variant::Chip8x, _) if opcode >> 12 == 0xB => Op::SetColour { x, y, n },
+            (Variant::Chip8x, _) if opcode & 0xF0FF == 0xE0F2 => Op::SkipKey2(x),
+            (Variant::Chip8x, _) if opcode & 0xF0FF == 0xE0F5 => Op::SkipNoKey2(x),
+            (Variant::Chip8x, _) if opcode & 0xF0FF == 0xF0F8 => Op::Output(x),
+            (Variant::Chip8x, _) if opcode & 0xF0FF == 0xF0FB => Op::Input(x),
+            (Variant::Megachip, _) => Op::decode_megachip(opcode),
+            _ => Op::decode(opcode),
+        }
+    }
+
+    fn decode_megachip(opcode: u16) -> Op {
+        let nn = (opcode & 0xFF) as u8;
+        match opcode >> 8 {
+            0x00 if nn == 0x10 => Op::MegaOff,
+            0x00 if nn == 0x11 => Op::MegaOn,
+            0x01 => Op::LoadLongI(nn),
+            0x02 => Op::Palette(nn),
+            0x03 => Op::SpriteWidth(nn),
+            0x04 => Op::SpriteHeight(nn),
+            0x05 => Op::ScreenAlpha(nn),
+            0x06 if nn < 0x10 => Op::PlaySample(nn),
+            0x07 if nn == 0 => Op::StopSample,
+            0x08 if nn < 0x10 => Op::Blend(nn),
+            0x09 => Op::CollisionColour(nn),
+            _ => Op::decode(opcode),
+        }
+    }
+
+    pub fn decode(opcode: u16) -> Op {
+        let x = (opcode >> 8 & 0xF) as usize;
+        let y = (opcode >> 4 & 0xF) as usize;
+        let n = (opcode & 0xF) as u8;
+        let nn = (opcode & 0xFF) as u8;
+        let nnn = opcode & 0xFFF;
+        match opcode >> 12 {
+            0x0 => match opcode {
+                0x00E0 => Op::Cls,
+                0x00EE => Op::Ret,
+                _ => Op::Sys(nnn),
+            },
+            0x1 => Op::Jump(nnn),
+            0x2 => Op::Call(nnn),
+            0x3 => Op::SkipEqImm { x, nn },
+            0x4 => Op::SkipNeImm { x, nn },
+            0x5 => Op::SkipEq { x, y },
+            0x6 => Op::LoadImm { x, nn },
+            0x7 => Op::AddImm { x, nn },
+            0x8 => match n {
+                0x0 => Op::Move { x, y },
+                0x1 => Op::Or { x, y },
+                0x2 => Op::And { x, y },
+                0x3 => Op::Xor { x, y },
+                0x4 => Op::Add { x, y },
+                0x5 => Op::Sub { x, y },
+                0x6 => Op::ShiftRight { x, y },
+                0x7 => Op::SubReverse { x, y },
+                0xE => Op::ShiftLeft { x, y },
+                _ => Op::Unknown(opcode),
+            },
+            0x9 => Op::SkipNe { x, y },
+            0xA => Op::LoadI(nnn),
+            0xB => Op::JumpOffset { x, nnn },
+            0xC => Op::Random { x, nn },
+            0xD => Op::Draw { x, y, n },
+            0xE => match nn {
+                0x9E => Op::SkipKey(x),
+                0xA1 => Op::SkipNoKey(x),
+                _ => Op::Unknown(opcode),
+            },
+            _ => match nn {
+                0x07 => Op::GetDelay(x),
+                0x0A => Op::WaitKey(x),
+                0x15 => Op::SetDelay(x),
+                0x18 => Op::SetSound(x),
+                0x1E => Op::AddI(x),
+                0x33 => Op::Bcd(x),
+                0x55 => Op::Store(x),
+                0x65 => Op::Load(x),
+                _ => Op::Unknown(opcode),
+            },
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Op;
+    use crate::variant::Variant;
+
+    #[test]
+    fn test_decode() {
+        assert_eq!(Op::decode(0x00E0), Op::Cls);
+        assert_eq!(Op::decode(0x0123), Op::Sys(0x123));
+        assert_eq!(Op::decode(0x8AB4), Op::Add { x: 0xA, y: 0xB });
+        assert_eq!(Op::decode(0xD125), Op::Draw { x: 1, y: 2, n: 5 });
+        assert_eq!(Op::decode(0xB3FF), Op::JumpOffset { x: 3, nnn: 0x3FF });
+        assert_eq!(Op::decode(0xF265), Op::Load(2));
+        assert_eq!(Op::decode(0x8AB9), Op::Unknown(0x8AB9));
+        assert_eq!(Op::decode(0xE1FF), Op::Unknown(0xE1FF));
+    }
+
+    #[test]
+    fn test_decode_variants() {
+        let decode = |opcode| Op::decode_for(opcode, Variant::Chip8x);
+
+        assert_eq!(decode(0x02A0), Op::CycleBackground);
+        assert_eq!(decode(0xB123), Op::SetColour { x: 1, y: 2, n: 3 });
+        assert_eq!(decode(0x5AB1), Op::AddColour { x: 0xA, y: 0xB });
+        assert_eq!(decode(0x5AB0), Op::SkipEq { x: 0xA, y: 0xB });
+        assert_eq!(decode(0xE3F2), Op::SkipKey2(3));
+        assert_eq!(decode(0xE3A1), Op::SkipNoKey(3));
+        assert_eq!(decode(0xF4FB), Op::Input(4));
+        assert_eq!(
+            Op::decode_at(0x1260, Variant::Hires, 0x200),
+            Op::Jump(0x2C0)
+        );
+        assert_eq!(
+            Op::decode_at(0x1260, Variant::Hires, 0x2C0),
+            Op::Jump(0x260)
+        );
+        assert_eq!(Op::decode_for(0x0230, Variant::Hires), Op::Cls);
+        assert_eq!(
+            Op::decode_for(0xB123, Variant::Chip8),
+            Op::JumpOffset { x: 1, nnn: 0x123 }
+        );
+        let decode = |opcode| Op::decode_for(opcode, Variant::Megachip);
+        assert_eq!(decode(0x0011), Op::MegaOn);
+        assert_eq!(decode(0x0112), Op::LoadLongI(0x12));
+        assert_eq!(decode(0x0600), Op::PlaySample(0));
+        assert_eq!(decode(0x0803), Op::Blend(3));
+        assert_eq!(decode(0x00E0), Op::Cls);
+        assert_eq!(decode(0x0A00), Op::Sys(0xA00));
+    }
+}
@@ -0,0 +1,216 @@
+use std::error::Error;
+
+use minifb::{Scale, Window, WindowOptions};
+
+use crate::{chip::Chip, dump, instructions::Instruction};
+
+const COLUMNS: usize = 36;
+const ROWS: usize = 32;
+// Each glyph is 3x5 pixels inside a 4x6 cell.
+const CELL_WIDTH: usize = 4;
+const CELL_HEIGHT: usize = 6;
+const WIDTH: usize = COLUMNS * CELL_WIDTH;
+const HEIGHT: usize = ROWS * CELL_HEIGHT;
+
+const FOREGROUND: u32 = 0x00FF_FFFF;
+const HIGHLIGHT: u32 = 0x00FF_D700;
+const BACKGROUND: u32 = 0x0020_2020;
+
+/// A second window showing the chip's registers, stack, timers, memory around
+/// I and PC, and the disassembly around PC.
+pub struct Panel {
+    window: Window,
+    buffer: Vec<u32>,
+}
+
+impl Panel {
+    pub fn new() -> Result<Panel, Box<dyn Error>> {
+        let window = Window::new(
+            "rusty-chip8 - state",
+            WIDTH,
+            HEIGHT,
+            WindowOptions {
+                scale: Scale::X4,
+                ..WindowOptions::default()
+            },
+        )?;
+
+        Ok(Panel {
+            window,
+            buffer: vec![BACKGROUND; WIDTH * HEIGHT],
+        })
+    }
+
+    pub fn is_open(&self) -> bool {
+        self.window.is_open()
+    }
+
+    pub fn update(&mut self, chip: &Chip) -> Result<(), Box<dyn Error>> {
+        self.buffer.fill(BACKGROUND);
+        for (row, line) in lines(chip).iter().enumerate().take(ROWS) {
+            let color = if line.starts_with('>') {
+                HIGHLIGHT
+            } else {
+                FOREGROUND
+            };
+            for (column, c) in line.chars().enumerate().take(COLUMNS) {
+                draw_glyph(&mut self.buffer, column, row, c, color);
+            }
+        }
+        self.window
+            .update_with_buffer(&self.buffer, WIDTH, HEIGHT)?;
+        Ok(())
+    }
+}
+
+/// The panel's text, one entry per row.
+pub fn lines(chip: &Chip) -> Vec<String> {
+    let mut lines = vec![
+        format!(
+            "PC {:03X} I {:03X} DT {:02X} ST {:02X}",
+            chip.pc, chip.i, chip.dt, chip.st
+        ),
+        String::new(),
+    ];
+
+    lines.extend(chip.v.chunks(4).enumerate().map(|(row, regs)| {
+        regs.iter()
+            .enumerate()
+            .map(|(n, v)| format!("V{:X} {v:02X}", row * 4 + n))
+            .collect::<Vec<_>>()
+            .join("  ")
+    }));
+
+    lines.push(String::new());
+    lines.push(format!("STACK SP {}", chip.sp));
+    lines.extend(chip.stack.chunks(4).map(|entries| {
+        entries
+            .iter()
+            .map(|s| format!("{s:03X}"))
+            .collect::<Vec<_>>()
+            .join(" ")
+    }));
+
+    lines.push(String::new());
+    lines.push("MEM I".to_string());
+    lines.extend(hex_view(chip, chip.i));
+    lines.push("MEM PC".to_string());
+    lines.extend(hex_view(chip, chip.pc as u32));
+
+    lines.push(String::new());
+    lines.push("CODE".to_string());
+    let first = chip.pc.saturating_sub(8);
+    lines.extend((first..=first + 16).step_by(2).filter_map(|pc| {
+        let bytes = chip.mem.get(pc as usize..pc as usize + 2)?;
+        let instruction = Instruction::new(bytes);
+        let marker = if pc == chip.pc { '>' } else { ' ' };
+        Some(format!(
+            "{marker}{pc:03X} {:04X} {}",
+            instruction.opcode,
+            dump::mnemonic_for(&instruction, chip.variant)
+        ))
+    }));
+    lines
+}
+
+// Eight bytes per row, starting on the row holding `address`.
+fn hex_view(chip: &Chip, address: u32) -> Vec<String> {
+    let row = (address as usize & !7).saturating_sub(8);
+    (row..row + 24)
+        .step_by(8)
+        .filter_map(|start| {
+            let bytes = chip.mem.get(start..start + 8)?;
+            let hex: Vec<String> = bytes.iter().map(|b| format!("{b:02X}")).collect();
+            Some(format!("{start:03X} {}", hex.join(" ")))
+        })
+        .collect()
+}
+
+fn draw_glyph(buffer: &mut [u32], column: usize, row: usize, c: char, color: u32) {
+    for (y, bits) in glyph(c).iter().enumerate() {
+        for x in 0..3 {
+            if bits & (0b100 >> x) != 0 {
+                let index = (row * CELL_HEIGHT + y) * WIDTH + column * CELL_WIDTH + x;
+                buffer[index] = color;
+            }
+        }
+    }
+}
+
+fn glyph(c: char) -> [u8; 5] {
+    match c.to_ascii_uppercase() {
+        ' ' => [0, 0, 0, 0, 0],
+        '0' => [7, 5, 5, 5, 7],
+        '1' => [2, 6, 2, 2, 7],
+        '2' => [7, 1, 7, 4, 7],
+        '3' => [7, 1, 7, 1, 7],
+        '4' => [5, 5, 7, 1, 1],
+        '5' => [7, 4, 7, 1, 7],
+        '6' => [7, 4, 7, 5, 7],
+        '7' => [7, 1, 1, 1, 1],
+        '8' => [7, 5, 7, 5, 7],
+        '9' => [7, 5, 7, 1, 7],
+        'A' => [2, 5, 7, 5, 5],
+        'B' => [6, 5, 6, 5, 6],
+        'C' => [3, 4, 4, 4, 3],
+        'D' => [6, 5, 5, 5, 6],
+        'E' => [7, 4, 6, 4, 7],
+        'F' => [7, 4, 6, 4, 4],
+        'G' => [3, 4, 5, 5, 3],
+        'H' => [5, 5, 7, 5, 5],
+        'I' => [7, 2, 2, 2, 7],
+        'J' => [1, 1, 1, 5, 2],
+        'K' => [5, 5, 6, 5, 5],
+        'L' => [4, 4, 4, 4, 7],
+        'M' => [5, 7, 7, 5, 5],
+        'N' => [6, 5, 5, 5, 5],
+        'O' => [2, 5, 5, 5, 2],
+        'P' => [6, 5, 6, 4, 4],
+        'Q' => [2, 5, 5, 6, 3],
+        'R' => [6, 5, 6, 5, 5],
+        'S' => [3, 4, 2, 1, 6],
+        'T' => [7, 2, 2, 2, 2],
+        'U' => [5, 5, 5, 5, 7],
+        'V' => [5, 5, 5, 5, 2],
+        'W' => [5, 5, 7, 7, 5],
+        'X' => [5, 5, 2, 5, 5],
+        'Y' => [5, 5, 2, 2, 2],
+        'Z' => [7, 1, 2, 4, 7],
+        ':' => [0, 2, 0, 2, 0],
+        ',' => [0, 0, 0, 2, 4],
+        '.' => [0, 0, 0, 0, 2],
+        '#' => [5, 7, 5, 7, 5],
+        '$' => [3, 6, 2, 3, 6],
+        '(' => [1, 2, 2, 2, 1],
+        ')' => [4, 2, 2, 2, 4],
+        '[' => [3, 2, 2, 2, 3],
+        ']' => [6, 2, 2, 2, 6],
+        '-' => [0, 0, 7, 0, 0],
+        '+' => [0, 2, 7, 2, 0],
+        '=' => [0, 7, 0, 7, 0],
+        '>' => [4, 2, 1, 2, 4],
+        '/' => [1, 1, 2, 4, 4],
+        '_' => [0, 0, 0, 0, 7],
+        _ => [6, 1, 2, 0, 2],
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::lines;
+    use crate::chip::Chip;
+
+    #[test]
+    fn test_lines() {
+        let mut chip = Chip::new();
+        chip.load_bytes(&[0x00, 0xE0, 0xA2, 0x2A]).unwrap();
+        chip.v[0xA] = 0x5C;
+
+        let lines = lines(&chip);
+
+        assert_eq!(lines[0], "PC 200 I 000 DT 00 ST 00");
+        assert!(lines[4].contains("VA 5C"));
+        assert!(lines.iter().any(|l| l.starts_with(">200 00E0 CLS")));
+        assert!(lines.iter().any(|l| l.starts_with(" 202 A22A MVI")));
+    }
+}
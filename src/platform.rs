@@ -0,0 +1,84 @@
+use std::error::Error;
+
+use crate::chip::Chip;
+
+/// Keypad input as seen by `Chip`. Keys are the CHIP-8 key values 0x0-0xF.
+pub trait Platform {
+    fn is_key_down(&self, key: u8) -> bool;
+
+    fn pressed_key(&self) -> Option<u8> {
+        (0x0..=0xF).find(|&key| self.is_key_down(key))
+    }
+
+    /// The CHIP-8X second keypad.
+    fn is_second_key_down(&self, _key: u8) -> bool {
+        false
+    }
+
+    /// A byte waiting on the CHIP-8X I/O port, which nothing is attached to
+    /// by default.
+    fn read_port(&self) -> Option<u8> {
+        None
+    }
+}
+
+/// Emulator controls, as opposed to CHIP-8 keys.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Hotkey {
+    SpeedUp,
+    SlowDown,
+    Pause,
+    FastForward,
+}
+
+/// A platform that can also show the display, driven by the emulator loop.
+pub trait Frontend: Platform {
+    fn is_open(&self) -> bool;
+
+    /// Hotkeys pressed since the last call. `FastForward` is reported on every
+    /// call while it is held.
+    fn hotkeys(&mut self) -> Vec<Hotkey>;
+
+    /// Shows a short line about the emulator, such as its speed.
+    fn status(&mut self, _status: &str) {}
+
+    /// Whether `present` shows the colours in the buffer rather than only
+    /// which pixels are lit.
+    fn shows_colour(&self) -> bool {
+        false
+    }
+
+    /// Shows the chip's state, for frontends with a debug view.
+    fn inspect(&mut self, _chip: &Chip) -> Result<(), Box<dyn Error>> {
+        Ok(())
+    }
+
+    fn present(
+        &mut self,
+        buffer: &[u32],
+        width: usize,
+        height: usize,
+    ) -> Result<(), Box<dyn Error>>;
+}
+
+/// A platform without a display, where keys are set directly.
+#[derive(Default)]
+pub struct Headless {
+    pub keys: [bool; 16],
+    pub second_keys: [bool; 16],
+    pub port: Option<u8>,
+}
+
+impl Platform for Headless {
+    fn is_key_down(&self, key: u8) -> bool {
+        self.keys.get(key as usize).copied().unwrap_or(false)
+    }
+
+    fn is_second_key_down(&self, key: u8) -> bool {
+        self.second_keys.get(key as usize).copied().unwrap_or(false)
+    }
+
+    fn read_port(&self) -> Option<u8> {
+        self.port
+    }
+}
@@ -0,0 +1,205 @@
+use std::{collections::HashMap, fmt::Write};
+
+use crate::{dump, instructions::Instruction, variant::Variant};
+
+/// Counts executed instructions by address, by opcode class and by call stack.
+pub struct Profiler {
+    hits: Vec<u64>,
+    classes: HashMap<&'static str, u64>,
+    edges: HashMap<(u16, u16), u64>,
+    stacks: HashMap<Vec<u16>, u64>,
+    // Entry points of the subroutines currently running, outermost first.
+    frames: Vec<u16>,
+    total: u64,
+}
+
+impl Profiler {
+    /// A profile of code in the first `size` bytes, where 64K covers any PC.
+    pub fn new(start: u16, size: usize) -> Profiler {
+        Profiler {
+            hits: vec![0; size],
+            classes: HashMap::new(),
+            edges: HashMap::new(),
+            stacks: HashMap::new(),
+            frames: vec![start],
+            total: 0,
+        }
+    }
+
+    /// Records the instruction at `pc`, before it runs.
+    pub fn record(&mut self, pc: u16, opcode: u16) {
+        self.total += 1;
+        if let Some(hits) = self.hits.get_mut(pc as usize) {
+            *hits += 1;
+        }
+        *self.classes.entry(class(opcode)).or_default() += 1;
+        *self.stacks.entry(self.frames.clone()).or_default() += 1;
+
+        match opcode {
+            0x00EE if self.frames.len() > 1 => {
+                self.frames.pop();
+            }
+            0x2000..=0x2FFF => {
+                let target = opcode & 0x0FFF;
+                let caller = self.frames.last().copied().unwrap_or_default();
+                *self.edges.entry((caller, target)).or_default() += 1;
+                self.frames.push(target);
+            }
+            _ => {}
+        }
+    }
+
+    pub fn total(&self) -> u64 {
+        self.total
+    }
+
+    pub fn hits(&self, pc: u16) -> u64 {
+        self.hits.get(pc as usize).copied().unwrap_or(0)
+    }
+
+    /// A plain text report of the `top` hottest addresses, the opcode classes
+    /// and the call graph, disassembling hot addresses from `mem` for
+    /// `variant`.
+    pub fn report(&self, mem: &[u8], top: usize, variant: Variant) -> String {
+        let mut report = String::new();
+        let percent = |count: u64| 100.0 * count as f64 / self.total.max(1) as f64;
+        let _ = writeln!(report, "Profile: {} instructions\n", self.total);
+
+        let _ = writeln!(report, "Hot spots:");
+        let mut hot: Vec<(usize, u64)> = self
+            .hits
+            .iter()
+            .copied()
+            .enumerate()
+            .filter(|&(_, hits)| hits > 0)
+            .collect();
+        hot.sort_by(|a, b| b.1.cmp(&a.1).then(a.0.cmp(&b.0)));
+        for &(pc, hits) in hot.iter().take(top) {
+            let text = mem
+                .get(pc..pc + 2)
+                .map(|bytes| dump::mnemonic_for(&Instruction::new(bytes), variant))
+                .unwrap_or_default();
+            let _ = writeln!(
+                report,
+                "  {pc:04X}  {hits:>10}  {:>5.1}%  {text}",
+                percent(hits)
+            );
+        }
+
+        let _ = writeln!(report, "\nOpcode classes:");
+        let mut classes: Vec<_> = self.classes.iter().collect();
+        classes.sort_by(|a, b| b.1.cmp(a.1).then(a.0.cmp(b.0)));
+        for (class, &count) in classes {
+            let _ = writeln!(
+                report,
+                "  {class:<6}  {count:>10}  {:>5.1}%",
+                percent(count)
+            );
+        }
+
+        let _ = writeln!(report, "\nCalls:");
+        let mut edges: Vec<_> = self.edges.iter().collect();
+        edges.sort_by(|a, b| b.1.cmp(a.1).then(a.0.cmp(b.0)));
+        for ((caller, callee), count) in edges {
+            let _ = writeln!(report, "  {caller:04X} -> {callee:04X}  {count:>10}");
+        }
+        report
+    }
+
+    /// Call stacks in the folded format read by flamegraph tools: one line per
+    /// stack, frames separated by `;`, followed by the instruction count.
+    pub fn folded(&self) -> String {
+        let mut lines: Vec<String> = self
+            .stacks
+            .iter()
+            .map(|(stack, count)| {
+                let frames: Vec<String> = stack.iter().map(|pc| format!("{pc:04X}")).collect();
+                format!("{} {count}", frames.join(";"))
+            })
+            .collect();
+        lines.sort();
+        lines.join("\n") + "\n"
+    }
+}
+
+/// The opcode pattern an instruction belongs to, e.g. `8XY4` or `FX1E`.
+pub fn class(opcode: u16) -> &'static str {
+    match opcode >> 12 {
+        0x0 => match opcode {
+            0x00E0 => "00E0",
+            0x00EE => "00EE",
+            _ => "0NNN",
+        },
+        0x1 => "1NNN",
+        0x2 => "2NNN",
+        0x3 => "3XNN",
+        0x4 => "4XNN",
+        0x5 => "5XY0",
+        0x6 => "6XNN",
+        0x7 => "7XNN",
+        0x8 => match opcode & 0xF {
+            0x0 => "8XY0",
+            0x1 => "8XY1",
+            0x2 => "8XY2",
+            0x3 => "8XY3",
+            0x4 => "8XY4",
+            0x5 => "8XY5",
+            0x6 => "8XY6",
+            0x7 => "8XY7",
+            0xE => "8XYE",
+            _ => "8XY?",
+        },
+        0x9 => "9XY0",
+        0xA => "ANNN",
+        0xB => "BNNN",
+        0xC => "CXNN",
+        0xD => "DXYN",
+        0xE => match opcode & 0xFF {
+            0x9E => "EX9E",
+            0xA1 => "EXA1",
+            _ => "EX??",
+        },
+        _ => match opcode & 0xFF {
+            0x07 => "FX07",
+            0x0A => "FX0A",
+            0x15 => "FX15",
+            0x18 => "FX18",
+            0x1E => "FX1E",
+            0x29 => "FX29",
+            0x33 => "FX33",
+            0x55 => "FX55",
+            0x65 => "FX65",
+            _ => "FX??",
+        },
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{class, Profiler};
+    use crate::variant::Variant;
+
+    #[test]
+    fn test_class() {
+        assert_eq!(class(0x00E0), "00E0");
+        assert_eq!(class(0x8124), "8XY4");
+        assert_eq!(class(0xF31E), "FX1E");
+    }
+
+    #[test]
+    fn test_calls_and_folded() {
+        let mut profiler = Profiler::new(0x200, 4096);
+        profiler.record(0x200, 0x2300);
+        profiler.record(0x300, 0x6001);
+        profiler.record(0x302, 0x00EE);
+        profiler.record(0x202, 0x1202);
+        profiler.record(0x202, 0x1202);
+
+        assert_eq!(profiler.total(), 5);
+        assert_eq!(profiler.hits(0x202), 2);
+        assert_eq!(profiler.folded(), "0200 3\n0200;0300 2\n");
+        assert!(profiler
+            .report(&[0; 4096], 3, Variant::Chip8)
+            .contains("0200 -> 0300           1"));
+    }
+}
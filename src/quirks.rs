@@ -0,0 +1,77 @@
+/// Behaviours that differ between CHIP-8 interpreters.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Quirks {
+    /// 8XY6/8XYE shift VX in place instead of copying VY into it first.
+    pub shift: bool,
+    /// 8XY1/8XY2/8XY3 reset VF to 0.
+    pub vf_reset: bool,
+    /// FX55/FX65 leave I pointing past the last register.
+    pub memory: bool,
+    /// BNNN jumps to XNN + VX instead of NNN + V0.
+    pub jumping: bool,
+    /// Sprites are clipped at the screen edges instead of wrapping.
+    pub clipping: bool,
+}
+
+// Matches the interpreter before quirks were configurable; the `shift` feature
+// used to be the only switch.
+#[allow(clippy::derivable_impls)]
+impl Default for Quirks {
+    fn default() -> Self {
+        Quirks {
+            shift: cfg!(feature = "shift"),
+            vf_reset: false,
+            memory: false,
+            jumping: false,
+            clipping: false,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, clap::ValueEnum)]
+pub enum Profile {
+    /// Original COSMAC VIP CHIP-8
+    Chip8,
+    /// Modern SUPER-CHIP
+    Schip,
+    /// XO-CHIP
+    Xochip,
+}
+
+impl Profile {
+    pub const ALL: [Profile; 3] = [Profile::Chip8, Profile::Schip, Profile::Xochip];
+
+    pub fn quirks(self) -> Quirks {
+        match self {
+            Profile::Chip8 => Quirks {
+                shift: false,
+                vf_reset: true,
+                memory: true,
+                jumping: false,
+                clipping: true,
+            },
+            Profile::Schip => Quirks {
+                shift: true,
+                vf_reset: false,
+                memory: false,
+                jumping: true,
+                clipping: true,
+            },
+            Profile::Xochip => Quirks {
+                shift: false,
+                vf_reset: false,
+                memory: true,
+                jumping: false,
+                clipping: false,
+            },
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Profile::Chip8 => "chip8",
+            Profile::Schip => "schip",
+            Profile::Xochip => "xochip",
+        }
+    }
+}
@@ -0,0 +1,222 @@
+use crate::{
+    chip::{Chip, Fault},
+    op::Op,
+    platform::Platform,
+};
+
+const MEM_SIZE: usize = 4096;
+// Longest run of instructions translated into one block.
+const MAX_BLOCK: usize = 64;
+
+type Compiled = Box<dyn Fn(&mut Chip, &mut [u32], &dyn Platform) -> Result<(), Fault>>;
+
+/// Straight-line code up to and including the first instruction that may
+/// branch, wait for a key or write to memory.
+struct Block {
+    ops: Vec<Compiled>,
+}
+
+/// Runs chip code as blocks of closures, translated the first time PC reaches
+/// them. Simple instructions get their own closure; everything else calls back
+/// into `Chip::execute`, so both engines always agree.
+///
+/// The recompiler never traces, profiles or records coverage: when any of them
+/// is on it steps the interpreter instead.
+pub struct Recompiler {
+    blocks: Vec<Option<Block>>,
+    generation: u64,
+}
+
+impl Default for Recompiler {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Recompiler {
+    pub fn new() -> Recompiler {
+        Recompiler {
+            blocks: (0..MEM_SIZE).map(|_| None).collect(),
+            generation: 0,
+        }
+    }
+
+    /// Runs `cycles` instructions, exactly as that many `Chip::step` calls
+    /// would, stopping at the first fault.
+    pub fn run(
+        &mut self,
+        chip: &mut Chip,
+        cycles: usize,
+        buffer: &mut [u32],
+        platform: &dyn Platform,
+    ) -> Result<(), Fault> {
+        if chip.trace || chip.profiler.is_some() || chip.coverage.is_some() {
+            return (0..cycles).try_for_each(|_| chip.step(buffer, platform));
+        }
+
+        let mut remaining = cycles;
+        while remaining > 0 {
+            if chip.code_generation() != self.generation {
+                self.blocks.iter_mut().for_each(|block| *block = None);
+                self.generation = chip.code_generation();
+            }
+            let pc = chip.pc;
+            let Some(slot) = self.blocks.get_mut(pc as usize) else {
+                return chip.step(buffer, platform);
+            };
+            let block = slot.get_or_insert_with(|| compile(chip, pc));
+            // Blocks cannot be left halfway, so the end of a frame, or code
+            // running off the end of memory, is left to the interpreter.
+            if block.ops.is_empty() || block.ops.len() > remaining {
+                chip.step(buffer, platform)?;
+                remaining -= 1;
+                continue;
+            }
+            // Instructions count as run as they run, not when translated.
+            for op in &block.ops {
+                chip.mark_executed(chip.pc);
+                op(chip, buffer, platform)?;
+                remaining -= 1;
+            }
+        }
+        Ok(())
+    }
+
+    /// Runs one 60 Hz frame of `cycles` instructions, like `Chip::frame`.
+    pub fn frame(
+        &mut self,
+        chip: &mut Chip,
+        cycles: usize,
+        buffer: &mut [u32],
+        platform: &dyn Platform,
+    ) -> Result<(), Fault> {
+        self.run(chip, cycles, buffer, platform)?;
+        chip.tick_timers();
+        Ok(())
+    }
+}
+
+fn compile(chip: &mut Chip, start: u16) -> Block {
+    let mut ops = Vec::new();
+    let mut pc = start as usize;
+    while ops.len() < MAX_BLOCK {
+        let Ok(op) = chip.decode(pc as u16) else {
+            break;
+        };
+        ops.push(translate(op));
+        if ends_block(op) {
+            break;
+        }
+        pc += 2;
+    }
+    Block { ops }
+}
+
+fn translate(op: Op) -> Compiled {
+    match op {
+        Op::LoadImm { x, nn } => Box::new(move |chip, _, _| {
+            chip.v[x] = nn;
+            chip.pc += 0x02;
+            Ok(())
+        }),
+        Op::AddImm { x, nn } => Box::new(move |chip, _, _| {
+            chip.v[x] = chip.v[x].wrapping_add(nn);
+            chip.pc += 0x02;
+            Ok(())
+        }),
+        Op::Move { x, y } => Box::new(move |chip, _, _| {
+            chip.v[x] = chip.v[y];
+            chip.pc += 0x02;
+            Ok(())
+        }),
+        Op::LoadI(nnn) => Box::new(move |chip, _, _| {
+            chip.i = nnn as u32;
+            chip.pc += 0x02;
+            Ok(())
+        }),
+        Op::Jump(nnn) => Box::new(move |chip, _, _| {
+            chip.pc = nnn;
+            Ok(())
+        }),
+        _ => Box::new(move |chip, buffer, platform| chip.execute(op, buffer, platform)),
+    }
+}
+
+fn ends_block(op: Op) -> bool {
+    matches!(
+        op,
+        Op::Ret
+            | Op::Jump(_)
+            | Op::Call(_)
+            | Op::JumpOffset { .. }
+            | Op::SkipEqImm { .. }
+            | Op::SkipNeImm { .. }
+            | Op::SkipEq { .. }
+            | Op::SkipNe { .. }
+            | Op::SkipKey(_)
+            | Op::SkipNoKey(_)
+            | Op::SkipKey2(_)
+            | Op::SkipNoKey2(_)
+            | Op::WaitKey(_)
+            | Op::Input(_)
+            | Op::LoadLongI(_)
+            | Op::Bcd(_)
+            | Op::Store(_)
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Recompiler;
+    use crate::{chip::Chip, platform::Headless};
+
+    #[test]
+    fn test_self_modifying_code() {
+        // 6103 A201 6042 F055 1200: the store rewrites the first instruction.
+        let rom = [0x61, 0x03, 0xA2, 0x01, 0x60, 0x42, 0xF0, 0x55, 0x12, 0x00];
+        let mut chip = Chip::new();
+        chip.trace = false;
+        chip.load_bytes(&rom).unwrap();
+        let mut buffer = [0u32; 64 * 32];
+        let mut recompiler = Recompiler::new();
+
+        recompiler
+            .run(&mut chip, 6, &mut buffer, &Headless::default())
+            .unwrap();
+
+        assert_eq!(chip.v[1], 0x42);
+        assert_eq!(chip.pc, 0x202);
+    }
+
+    #[test]
+    fn test_translated_is_not_run() {
+        // 6005 6107 1206 A202 6061 6142 F155 1200: the store rewrites 6107,
+        // which was translated with the first block but never ran.
+        let rom = [
+            0x60, 0x05, 0x61, 0x07, 0x12, 0x06, 0xA2, 0x02, 0x60, 0x61, 0x61, 0x42, 0xF1, 0x55,
+            0x12, 0x00,
+        ];
+        let mut chip = Chip::new();
+        chip.trace = false;
+        chip.load_bytes(&rom).unwrap();
+        let mut buffer = [0u32; 64 * 32];
+        let mut recompiler = Recompiler::new();
+        let platform = Headless::default();
+
+        recompiler
+            .run(&mut chip, 1, &mut buffer, &platform)
+            .unwrap();
+        chip.code_writes = Some(Vec::new());
+        chip.pc = 0x206;
+        recompiler
+            .run(&mut chip, 5, &mut buffer, &platform)
+            .unwrap();
+        assert_eq!(chip.code_writes.take(), Some(Vec::new()));
+
+        recompiler
+            .run(&mut chip, 3, &mut buffer, &platform)
+            .unwrap();
+        assert_eq!(chip.v[1], 0x42);
+        assert_eq!(chip.pc, 0x206);
+    }
+}
@@ -0,0 +1,146 @@
+use std::{
+    fs,
+    io::{self, Cursor, Read},
+    path::Path,
+};
+
+use zip::ZipArchive;
+
+use crate::{chip::LoadError, variant::Variant};
+
+/// Reads a rom file: `-` for stdin, a path to a file, or a path into a zip
+/// archive. `games.zip/pong.ch8` picks a member by name, and `games.zip` on
+/// its own picks the only `.ch8` in it.
+pub fn read(path: &Path) -> Result<Vec<u8>, LoadError> {
+    if path == Path::new("-") {
+        return read_capped(io::stdin(), None);
+    }
+    if path.is_file() {
+        let bytes = fs::read(path)?;
+        return if is_zip(&bytes) {
+            from_zip(bytes, None)
+        } else {
+            Ok(bytes)
+        };
+    }
+    // The nearest file up the path is the archive, the rest the member.
+    if let Some(archive) = path.ancestors().skip(1).find(|dir| dir.is_file()) {
+        let bytes = fs::read(archive)?;
+        if is_zip(&bytes) {
+            let member = path.strip_prefix(archive).expect("an ancestor is a prefix");
+            let name: Vec<_> = member
+                .components()
+                .map(|part| part.as_os_str().to_string_lossy())
+                .collect();
+            return from_zip(bytes, Some(&name.join("/")));
+        }
+    }
+    // Not found, with the error saying so.
+    Ok(fs::read(path)?)
+}
+
+fn is_zip(bytes: &[u8]) -> bool {
+    // A local file header, or the end of an empty archive.
+    bytes.starts_with(b"PK\x03\x04") || bytes.starts_with(b"PK\x05\x06")
+}
+
+fn from_zip(bytes: Vec<u8>, member: Option<&str>) -> Result<Vec<u8>, LoadError> {
+    let mut archive = ZipArchive::new(Cursor::new(bytes))?;
+    let name = match member {
+        Some(name) => name.to_string(),
+        None => {
+            let mut roms: Vec<&str> = archive
+                .file_names()
+                .filter(|name| name.to_ascii_lowercase().ends_with(".ch8"))
+                .collect();
+            roms.sort_unstable();
+            match roms[..] {
+                [rom] => rom.to_string(),
+                [] => return Err(LoadError::Member("no .ch8 rom in the archive".to_string())),
+                _ => {
+                    return Err(LoadError::Member(format!(
+                        "several roms in the archive, name one of {}",
+                        roms.join(", ")
+                    )))
+                }
+            }
+        }
+    };
+    let file = archive
+        .by_name(&name)
+        .map_err(|_| LoadError::Member(format!("no {name} in the archive")))?;
+    let size = file.size();
+    read_capped(file, Some(size))
+}
+
+// Reads no more than the largest memory holds, so a zip bomb or an endless
+// pipe is not read in full before being found too large.
+fn read_capped(reader: impl Read, size: Option<u64>) -> Result<Vec<u8>, LoadError> {
+    let max = Variant::Megachip.memory_size();
+    let mut rom = Vec::new();
+    reader.take(max as u64 + 1).read_to_end(&mut rom)?;
+    if rom.len() > max {
+        let size = size.map_or(rom.len(), |size| size as usize);
+        return Err(LoadError::TooLarge { size, max });
+    }
+    Ok(rom)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::{Cursor, Write};
+
+    use zip::{write::SimpleFileOptions, CompressionMethod, ZipWriter};
+
+    use super::{from_zip, is_zip};
+    use crate::chip::LoadError;
+
+    fn archive(files: &[(&str, &[u8])]) -> Vec<u8> {
+        let mut zip = ZipWriter::new(Cursor::new(Vec::new()));
+        let options = SimpleFileOptions::default().compression_method(CompressionMethod::Deflated);
+        for (name, contents) in files {
+            zip.start_file(*name, options).unwrap();
+            zip.write_all(contents).unwrap();
+        }
+        zip.finish().unwrap().into_inner()
+    }
+
+    #[test]
+    fn test_zip() {
+        let zip = archive(&[("README.txt", b"Pong"), ("games/PONG.CH8", &[0x12, 0x00])]);
+        assert!(is_zip(&zip));
+
+        assert_eq!(from_zip(zip.clone(), None).unwrap(), [0x12, 0x00]);
+        assert_eq!(from_zip(zip.clone(), Some("README.txt")).unwrap(), b"Pong");
+        assert!(matches!(
+            from_zip(zip, Some("pong.ch8")),
+            Err(LoadError::Member(_))
+        ));
+    }
+
+    #[test]
+    fn test_zip_bomb() {
+        let zip = archive(&[("bomb.ch8", &vec![0; 0x100_0001])]);
+
+        assert!(matches!(
+            from_zip(zip, None),
+            Err(LoadError::TooLarge {
+                size: 0x100_0001,
+                max: 0x100_0000
+            })
+        ));
+    }
+
+    #[test]
+    fn test_zip_without_one_rom() {
+        let empty = archive(&[]);
+        let several = archive(&[("a.ch8", &[]), ("b.ch8", &[])]);
+        assert!(is_zip(&empty));
+
+        assert!(matches!(from_zip(empty, None), Err(LoadError::Member(_))));
+        let Err(LoadError::Member(message)) = from_zip(several, None) else {
+            panic!("expected an error naming the roms");
+        };
+        assert!(message.ends_with("a.ch8, b.ch8"));
+    }
+}
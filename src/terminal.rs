@@ -0,0 +1,216 @@
+use std::{
+    error::Error,
+    io::{self, Read, Stdout, Write},
+    sync::mpsc::{self, Receiver, TryRecvError},
+    thread,
+    time::{Duration, Instant},
+};
+
+use termion::{
+    cursor,
+    event::Key,
+    input::TermRead,
+    raw::{IntoRawMode, RawTerminal},
+    screen::{AlternateScreen, IntoAlternateScreen},
+};
+
+use crate::platform::{Frontend, Hotkey, Platform};
+
+// Terminals only report key presses, so a key counts as held until it has not
+// been seen for this long. Auto-repeat keeps it held while the key stays down.
+const HOLD: Duration = Duration::from_millis(200);
+
+const FRAME: Duration = Duration::from_nanos(1_000_000_000 / 60);
+
+const KEYMAP: [char; 16] = [
+    '1', '2', '3', '4', 'q', 'w', 'e', 'r', 'a', 's', 'd', 'f', 'z', 'x', 'c', 'v',
+];
+
+#[derive(Clone, Copy, Debug, clap::ValueEnum)]
+pub enum Glyphs {
+    /// One cell per 1x2 pixels using ▀ ▄ █
+    HalfBlock,
+    /// One cell per 2x4 pixels using braille dots
+    Braille,
+}
+
+pub struct Terminal {
+    stdout: AlternateScreen<RawTerminal<Stdout>>,
+    // Bytes typed at the terminal, read on another thread.
+    input: Receiver<u8>,
+    glyphs: Glyphs,
+    held: [Option<Instant>; 16],
+    fast_forward: Option<Instant>,
+    hotkeys: Vec<Hotkey>,
+    status: String,
+    open: bool,
+    last: Vec<u32>,
+    next_frame: Instant,
+}
+
+impl Terminal {
+    pub fn new(glyphs: Glyphs) -> Result<Terminal, Box<dyn Error>> {
+        // Keys come from the terminal itself rather than stdin, which may be
+        // the rom piped in.
+        let tty = termion::get_tty()
+            .map_err(|e| format!("cannot open the terminal to read keys: {e}"))?;
+        let (send, input) = mpsc::channel();
+        thread::spawn(move || {
+            for byte in io::BufReader::new(tty).bytes().map_while(Result::ok) {
+                if send.send(byte).is_err() {
+                    return;
+                }
+            }
+        });
+        let mut stdout = io::stdout().into_raw_mode()?.into_alternate_screen()?;
+        write!(stdout, "{}{}", cursor::Hide, termion::clear::All)?;
+
+        Ok(Terminal {
+            stdout,
+            input,
+            glyphs,
+            held: [None; 16],
+            fast_forward: None,
+            hotkeys: Vec::new(),
+            status: String::new(),
+            open: true,
+            last: Vec::new(),
+            next_frame: Instant::now(),
+        })
+    }
+
+    fn poll_keys(&mut self) {
+        let mut bytes = Vec::new();
+        loop {
+            match self.input.try_recv() {
+                Ok(byte) => bytes.push(byte),
+                Err(TryRecvError::Empty) => break,
+                Err(TryRecvError::Disconnected) => {
+                    self.open = false;
+                    break;
+                }
+            }
+        }
+
+        let now = Instant::now();
+        for key in bytes.as_slice().keys().map_while(Result::ok) {
+            match key {
+                Key::Esc | Key::Ctrl('c') => self.open = false,
+                Key::Char('=') | Key::Char('+') => self.hotkeys.push(Hotkey::SpeedUp),
+                Key::Char('-') => self.hotkeys.push(Hotkey::SlowDown),
+                Key::Char('p') => self.hotkeys.push(Hotkey::Pause),
+                Key::Char('\t') => self.fast_forward = Some(now),
+                Key::Char(c) => {
+                    if let Some(k) = KEYMAP.iter().position(|&m| m == c.to_ascii_lowercase()) {
+                        self.held[k] = Some(now);
+                    }
+                }
+                _ => {}
+            }
+        }
+    }
+
+    fn render(&self, buffer: &[u32], width: usize, height: usize) -> String {
+        let on = |x: usize, y: usize| x < width && y < height && buffer[y * width + x] != 0;
+        let mut frame = String::new();
+
+        match self.glyphs {
+            Glyphs::HalfBlock => {
+                for y in (0..height).step_by(2) {
+                    for x in 0..width {
+                        frame.push(match (on(x, y), on(x, y + 1)) {
+                            (false, false) => ' ',
+                            (true, false) => '▀',
+                            (false, true) => '▄',
+                            (true, true) => '█',
+                        });
+                    }
+                    frame.push_str("\r\n");
+                }
+            }
+            Glyphs::Braille => {
+                const DOTS: [[u32; 4]; 2] = [[0x01, 0x02, 0x04, 0x40], [0x08, 0x10, 0x20, 0x80]];
+                for y in (0..height).step_by(4) {
+                    for x in (0..width).step_by(2) {
+                        let mut bits = 0;
+                        for (dx, column) in DOTS.iter().enumerate() {
+                            for (dy, dot) in column.iter().enumerate() {
+                                if on(x + dx, y + dy) {
+                                    bits |= dot;
+                                }
+                            }
+                        }
+                        frame.push(char::from_u32(0x2800 + bits).unwrap_or(' '));
+                    }
+                    frame.push_str("\r\n");
+                }
+            }
+        }
+        frame
+    }
+}
+
+impl Drop for Terminal {
+    fn drop(&mut self) {
+        let _ = write!(self.stdout, "{}", cursor::Show);
+        let _ = self.stdout.flush();
+    }
+}
+
+impl Platform for Terminal {
+    fn is_key_down(&self, key: u8) -> bool {
+        self.held
+            .get(key as usize)
+            .copied()
+            .flatten()
+            .is_some_and(|t| t.elapsed() < HOLD)
+    }
+}
+
+impl Frontend for Terminal {
+    fn is_open(&self) -> bool {
+        self.open
+    }
+
+    fn hotkeys(&mut self) -> Vec<Hotkey> {
+        let mut hotkeys = std::mem::take(&mut self.hotkeys);
+        if self.fast_forward.is_some_and(|t| t.elapsed() < HOLD) {
+            hotkeys.push(Hotkey::FastForward);
+        }
+        hotkeys
+    }
+
+    fn status(&mut self, status: &str) {
+        self.status = status.to_string();
+        self.last.clear();
+    }
+
+    fn present(
+        &mut self,
+        buffer: &[u32],
+        width: usize,
+        height: usize,
+    ) -> Result<(), Box<dyn Error>> {
+        self.poll_keys();
+        if self.last != buffer {
+            let frame = self.render(buffer, width, height);
+            write!(
+                self.stdout,
+                "{}{}{}{}",
+                cursor::Goto(1, 1),
+                frame,
+                termion::clear::CurrentLine,
+                self.status
+            )?;
+            self.stdout.flush()?;
+            self.last = buffer.to_vec();
+        }
+
+        let now = Instant::now();
+        if self.next_frame > now {
+            std::thread::sleep(self.next_frame - now);
+        }
+        self.next_frame = self.next_frame.max(now) + FRAME;
+        Ok(())
+    }
+}
@@ -0,0 +1,66 @@
+/// CHIP-8 dialects that add or change instructions.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, clap::ValueEnum)]
+pub enum Variant {
+    /// The original instruction set
+    #[default]
+    Chip8,
+    /// CHIP-8X, for the VIP with the VP-590 colour board and a second keypad
+    Chip8x,
+    /// The two-page 64x64 display, for roms starting with 1260
+    Hires,
+    /// MegaChip: 256x192 colour sprites, sampled sound and 16M of memory
+    Megachip,
+}
+
+impl Variant {
+    /// Where roms for the variant are loaded and started.
+    pub fn start(self) -> u16 {
+        match self {
+            Variant::Chip8 | Variant::Hires | Variant::Megachip => 0x200,
+            Variant::Chip8x => 0x300,
+        }
+    }
+
+    /// Width and height of the display.
+    pub fn size(self) -> (usize, usize) {
+        match self {
+            Variant::Chip8 | Variant::Chip8x => (64, 32),
+            Variant::Hires => (64, 64),
+            Variant::Megachip => (256, 192),
+        }
+    }
+
+    /// Width and height of the display DXYN draws monochrome sprites on,
+    /// scaled up to fill the whole display.
+    pub fn plane(self) -> (usize, usize) {
+        match self {
+            Variant::Hires => (64, 64),
+            _ => (64, 32),
+        }
+    }
+
+    pub fn memory_size(self) -> usize {
+        match self {
+            Variant::Megachip => 0x100_0000,
+            _ => 0x1000,
+        }
+    }
+
+    /// The variant a rom loaded at 0x200 announces in its first bytes, if any.
+    /// Hi-res roms start by jumping over the interpreter changes they carry.
+    pub fn detect(rom: &[u8]) -> Option<Variant> {
+        rom.starts_with(&[0x12, 0x60]).then_some(Variant::Hires)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Variant;
+
+    #[test]
+    fn test_detect() {
+        assert_eq!(Variant::detect(&[0x12, 0x60, 0x00]), Some(Variant::Hires));
+        assert_eq!(Variant::detect(&[0x12, 0x62]), None);
+        assert_eq!(Variant::detect(&[0x12]), None);
+    }
+}
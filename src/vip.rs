@@ -0,0 +1,213 @@
+use crate::{
+    chip::{Chip, Fault},
+    op::Op,
+    platform::Platform,
+};
+
+// Machine cycles in one 60 Hz frame: the CDP1802 runs at 1.7609 MHz and takes
+// eight clocks per machine cycle.
+const FRAME: i64 = 3668;
+// Cycles the CDP1861 steals for display DMA each frame, 8 bytes for each of
+// 128 scan lines.
+const DMA: i64 = 1024;
+// The interrupt routine that starts DMA and counts the timers down.
+const INTERRUPT: i64 = 46;
+// The interpreter loop that fetches an instruction and jumps to its handler.
+const FETCH: u32 = 40;
+
+/// Machine cycles the COSMAC VIP interpreter spends on `op`, given the chip's
+/// state just before it runs. The counts are those of the interpreter's
+/// routines as disassembled and timed by Laurence Scotford, so skips cost more
+/// when taken and DXYN and FX33 depend on their operands.
+pub fn cost(op: Op, chip: &Chip, platform: &dyn Platform) -> u32 {
+    let skip = |taken: bool| if taken { 4 } else { 0 };
+    FETCH
+        + match op {
+            Op::Cls => 3078,
+            Op::Ret => 10,
+            Op::Jump(_) => 12,
+            Op::Call(_) => 26,
+            // A machine code subroutine; its own cost is unknown.
+            Op::Sys(_) => 26,
+            // Crossing a page takes one more branch.
+            Op::JumpOffset { nnn, .. } => 22 + 2 * ((nnn & 0xFF) + chip.v[0] as u16 > 0xFF) as u32,
+            Op::SkipEqImm { x, nn } => 10 + skip(chip.v[x] == nn),
+            Op::SkipNeImm { x, nn } => 10 + skip(chip.v[x] != nn),
+            Op::SkipEq { x, y } => 14 + skip(chip.v[x] == chip.v[y]),
+            Op::SkipNe { x, y } => 14 + skip(chip.v[x] != chip.v[y]),
+            Op::SkipKey(x) => 14 + skip(platform.is_key_down(chip.v[x])),
+            Op::SkipNoKey(x) => 14 + skip(!platform.is_key_down(chip.v[x])),
+            Op::LoadImm { .. } => 6,
+            Op::AddImm { .. } | Op::GetDelay(_) | Op::SetDelay(_) | Op::SetSound(_) => 10,
+            // Each poll of the keypad while waiting.
+            Op::WaitKey(_) => 10,
+            Op::Move { .. } => 12,
+            Op::Or { .. }
+            | Op::And { .. }
+            | Op::Xor { .. }
+            | Op::Add { .. }
+            | Op::Sub { .. }
+            | Op::ShiftRight { .. }
+            | Op::SubReverse { .. }
+            | Op::ShiftLeft { .. } => 44,
+            Op::LoadI(_) => 12,
+            Op::Random { .. } => 36,
+            Op::AddI(_) => 16,
+            // The digits are found by repeated subtraction.
+            Op::Bcd(x) => {
+                let value = chip.v[x] as u32;
+                84 + 16 * (value / 100 + value / 10 % 10 + value % 10)
+            }
+            Op::Store(x) | Op::Load(x) => 14 + 14 * (x as u32 + 1),
+            // Each row is shifted into place one bit at a time, then XORed
+            // into one byte of display memory, or two when not byte aligned.
+            Op::Draw { x, n, .. } => {
+                let shift = (chip.v[x] % 8) as u32;
+                let bytes = if shift == 0 { 1 } else { 2 };
+                68 + n as u32 * (46 + 20 * shift) + 34 + n as u32 * 22 * bytes
+            }
+            // CHIP-8X ran on a VIP too, but these are only guesses.
+            Op::CycleBackground => 24,
+            Op::AddColour { .. } => 44,
+            Op::SetColour { .. } => 80,
+            Op::SkipKey2(_) | Op::SkipNoKey2(_) => 16,
+            Op::Output(_) | Op::Input(_) => 10,
+            // MegaChip never ran on a VIP.
+            Op::MegaOff
+            | Op::MegaOn
+            | Op::LoadLongI(_)
+            | Op::Palette(_)
+            | Op::SpriteWidth(_)
+            | Op::SpriteHeight(_)
+            | Op::ScreenAlpha(_)
+            | Op::PlaySample(_)
+            | Op::StopSample
+            | Op::Blend(_)
+            | Op::CollisionColour(_) => 0,
+            Op::Unknown(_) => 0,
+        }
+}
+
+/// Paces the interpreter like a COSMAC VIP: each frame runs the instructions
+/// that fit into the machine cycles the display leaves free, and DXYN waits
+/// for the next frame before drawing, so at most one sprite is drawn a frame.
+pub struct VipTiming {
+    // Cycles left over from, or borrowed against, the frames run so far.
+    balance: i64,
+}
+
+impl Default for VipTiming {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl VipTiming {
+    pub fn new() -> VipTiming {
+        VipTiming { balance: 0 }
+    }
+
+    /// Runs one 60 Hz frame, like `Chip::frame` but for as many instructions
+    /// as the frame's machine cycles allow.
+    pub fn frame(
+        &mut self,
+        chip: &mut Chip,
+        buffer: &mut [u32],
+        platform: &dyn Platform,
+    ) -> Result<(), Fault> {
+        self.balance += FRAME - DMA - INTERRUPT;
+        let mut first = true;
+        while self.balance > 0 {
+            let op = chip.fetch(chip.pc)?;
+            if matches!(op, Op::Draw { .. }) && !first {
+                // The rest of the frame is spent waiting for the interrupt,
+                // and the sprite is drawn at the start of the next one.
+                self.balance = 0;
+                break;
+            }
+            let cost = cost(op, chip, platform) as i64;
+            chip.step(buffer, platform)?;
+            self.balance -= cost;
+            first = false;
+        }
+        chip.tick_timers();
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{cost, VipTiming, DMA, FETCH, FRAME, INTERRUPT};
+    use crate::{chip::Chip, op::Op, platform::Headless};
+
+    #[test]
+    fn test_draw_cost() {
+        let mut chip = Chip::new();
+        let keys = Headless::default();
+        let draw = |n| Op::Draw { x: 0, y: 1, n };
+
+        assert_eq!(
+            cost(draw(5), &chip, &keys),
+            FETCH + 68 + 5 * 46 + 34 + 5 * 22
+        );
+        chip.v[0] = 3;
+        assert_eq!(
+            cost(draw(5), &chip, &keys),
+            FETCH + 68 + 5 * (46 + 60) + 34 + 5 * 44
+        );
+    }
+
+    #[test]
+    fn test_operand_costs() {
+        let mut chip = Chip::new();
+        let mut keys = Headless::default();
+
+        assert_eq!(cost(Op::Cls, &chip, &keys), FETCH + 3078);
+        chip.v[1] = 199;
+        assert_eq!(cost(Op::Bcd(1), &chip, &keys), FETCH + 84 + 16 * 19);
+        assert_eq!(
+            cost(Op::SkipEqImm { x: 1, nn: 199 }, &chip, &keys),
+            cost(Op::SkipEqImm { x: 1, nn: 0 }, &chip, &keys) + 4
+        );
+        let not_taken = cost(Op::SkipKey(2), &chip, &keys);
+        keys.keys[0] = true;
+        assert_eq!(cost(Op::SkipKey(2), &chip, &keys), not_taken + 4);
+    }
+
+    #[test]
+    fn test_frame_budget() {
+        let mut chip = Chip::new();
+        chip.trace = false;
+        // 7001 1200: count loops in V0.
+        chip.load_bytes(&[0x70, 0x01, 0x12, 0x00]).unwrap();
+        let mut buffer = [0u32; 64 * 32];
+        let mut timing = VipTiming::new();
+
+        timing
+            .frame(&mut chip, &mut buffer, &Headless::default())
+            .unwrap();
+
+        let per_loop = (2 * FETCH + 10 + 12) as i64;
+        let loops = (FRAME - DMA - INTERRUPT + per_loop - 1) / per_loop;
+        assert_eq!(chip.v[0] as i64, loops);
+    }
+
+    #[test]
+    fn test_display_wait() {
+        let mut chip = Chip::new();
+        chip.trace = false;
+        // 7001 D001 1200: one draw per loop.
+        chip.load_bytes(&[0x70, 0x01, 0xD0, 0x01, 0x12, 0x00])
+            .unwrap();
+        let mut buffer = [0u32; 64 * 32];
+        let mut timing = VipTiming::new();
+
+        for _ in 0..10 {
+            timing
+                .frame(&mut chip, &mut buffer, &Headless::default())
+                .unwrap();
+        }
+
+        assert_eq!(chip.v[0], 10);
+    }
+}
@@ -0,0 +1,337 @@
+use std::error::Error;
+
+use minifb::{Key, KeyRepeat, Scale, ScaleMode, Window, WindowOptions};
+
+use crate::{
+    chip::Chip,
+    database::Action,
+    panel::Panel,
+    platform::{Frontend, Hotkey, Platform},
+};
+
+pub struct Windowed {
+    window: Window,
+    title: String,
+    panel: Option<Panel>,
+    // Extra keys for CHIP-8 keys, on top of the keypad.
+    bindings: Vec<(Key, u8)>,
+}
+
+impl Windowed {
+    /// `scale` is how many screen pixels wide a CHIP-8 pixel starts out, a
+    /// power of two up to 32.
+    pub fn new(
+        title: &str,
+        width: usize,
+        height: usize,
+        scale: usize,
+    ) -> Result<Windowed, Box<dyn Error>> {
+        let scale = match scale {
+            1 => Scale::X1,
+            2 => Scale::X2,
+            4 => Scale::X4,
+            8 => Scale::X8,
+            16 => Scale::X16,
+            32 => Scale::X32,
+            _ => return Err(format!("cannot scale the window by {scale}").into()),
+        };
+        let mut window = Window::new(
+            title,
+            width,
+            height,
+            WindowOptions {
+                resize: true,
+                scale,
+                scale_mode: ScaleMode::AspectRatioStretch,
+                ..WindowOptions::default()
+            },
+        )?;
+        window.set_target_fps(60);
+
+        Ok(Windowed {
+            window,
+            title: title.to_string(),
+            panel: None,
+            bindings: Vec::new(),
+        })
+    }
+
+    /// Binds the arrow keys, space and shift to the CHIP-8 keys a rom uses
+    /// for those actions.
+    pub fn bind(&mut self, keys: &[(Action, u8)]) {
+        self.bindings = keys
+            .iter()
+            .map(|&(action, key)| {
+                let bound = match action {
+                    Action::Up => Key::Up,
+                    Action::Down => Key::Down,
+                    Action::Left => Key::Left,
+                    Action::Right => Key::Right,
+                    Action::A => Key::Space,
+                    Action::B => Key::LeftShift,
+                };
+                (bound, key)
+            })
+            .collect();
+    }
+
+    /// Binds keyboard keys, by name, to CHIP-8 keys on top of the other
+    /// bindings.
+    pub fn bind_named(&mut self, keys: &[(u8, String)]) -> Result<(), Box<dyn Error>> {
+        for (key, name) in keys {
+            let bound = key_named(name).ok_or_else(|| format!("unknown key `{name}`"))?;
+            self.bindings.push((bound, *key));
+        }
+        Ok(())
+    }
+
+    /// Opens the state panel next to the display.
+    pub fn show_panel(&mut self) -> Result<(), Box<dyn Error>> {
+        self.panel = Some(Panel::new()?);
+        Ok(())
+    }
+}
+
+impl Platform for Windowed {
+    fn is_key_down(&self, key: u8) -> bool {
+        Keypad::try_from(key).is_ok_and(|keypad| self.window.is_key_down(keypad.0))
+            || self
+                .bindings
+                .iter()
+                .any(|&(bound, chip8)| chip8 == key && self.window.is_key_down(bound))
+    }
+
+    fn is_second_key_down(&self, key: u8) -> bool {
+        SECOND_KEYPAD
+            .get(key as usize)
+            .is_some_and(|&key| self.window.is_key_down(key))
+    }
+}
+
+// The CHIP-8X second keypad, laid out like the first one four columns to the
+// right.
+const SECOND_KEYPAD: [Key; 16] = [
+    Key::Key5,
+    Key::Key6,
+    Key::Key7,
+    Key::Key8,
+    Key::T,
+    Key::Y,
+    Key::U,
+    Key::I,
+    Key::G,
+    Key::H,
+    Key::J,
+    Key::K,
+    Key::B,
+    Key::N,
+    Key::M,
+    Key::Comma,
+];
+
+impl Frontend for Windowed {
+    fn is_open(&self) -> bool {
+        self.window.is_open() && !self.window.is_key_down(Key::Escape)
+    }
+
+    fn hotkeys(&mut self) -> Vec<Hotkey> {
+        let mut hotkeys: Vec<Hotkey> = self
+            .window
+            .get_keys_pressed(KeyRepeat::Yes)
+            .into_iter()
+            .filter_map(|key| match key {
+                Key::Equal | Key::NumPadPlus => Some(Hotkey::SpeedUp),
+                Key::Minus | Key::NumPadMinus => Some(Hotkey::SlowDown),
+                Key::P => Some(Hotkey::Pause),
+                _ => None,
+            })
+            .collect();
+        if self.window.is_key_down(Key::Tab) {
+            hotkeys.push(Hotkey::FastForward);
+        }
+        hotkeys
+    }
+
+    fn shows_colour(&self) -> bool {
+        true
+    }
+
+    fn inspect(&mut self, chip: &Chip) -> Result<(), Box<dyn Error>> {
+        if self.panel.as_ref().is_some_and(|panel| !panel.is_open()) {
+            self.panel = None;
+        }
+        if let Some(panel) = &mut self.panel {
+            panel.update(chip)?;
+        }
+        Ok(())
+    }
+
+    fn status(&mut self, status: &str) {
+        self.window.set_title(&format!("{} - {status}", self.title));
+    }
+
+    fn present(
+        &mut self,
+        buffer: &[u32],
+        width: usize,
+        height: usize,
+    ) -> Result<(), Box<dyn Error>> {
+        self.window.update_with_buffer(buffer, width, height)?;
+        Ok(())
+    }
+}
+
+// Keys that can be bound by name: letters, digits, arrows and a few others.
+fn key_named(name: &str) -> Option<Key> {
+    const NAMED: [Key; 22] = [
+        Key::Up,
+        Key::Down,
+        Key::Left,
+        Key::Right,
+        Key::Space,
+        Key::Enter,
+        Key::Backspace,
+        Key::LeftShift,
+        Key::RightShift,
+        Key::LeftCtrl,
+        Key::RightCtrl,
+        Key::LeftAlt,
+        Key::RightAlt,
+        Key::Comma,
+        Key::Period,
+        Key::Slash,
+        Key::Semicolon,
+        Key::Apostrophe,
+        Key::LeftBracket,
+        Key::RightBracket,
+        Key::Backslash,
+        Key::Backquote,
+    ];
+    const LETTERS: [Key; 26] = [
+        Key::A,
+        Key::B,
+        Key::C,
+        Key::D,
+        Key::E,
+        Key::F,
+        Key::G,
+        Key::H,
+        Key::I,
+        Key::J,
+        Key::K,
+        Key::L,
+        Key::M,
+        Key::N,
+        Key::O,
+        Key::P,
+        Key::Q,
+        Key::R,
+        Key::S,
+        Key::T,
+        Key::U,
+        Key::V,
+        Key::W,
+        Key::X,
+        Key::Y,
+        Key::Z,
+    ];
+    const DIGITS: [Key; 10] = [
+        Key::Key0,
+        Key::Key1,
+        Key::Key2,
+        Key::Key3,
+        Key::Key4,
+        Key::Key5,
+        Key::Key6,
+        Key::Key7,
+        Key::Key8,
+        Key::Key9,
+    ];
+    if let [c] = name.as_bytes() {
+        return match c.to_ascii_uppercase() {
+            c @ b'A'..=b'Z' => Some(LETTERS[(c - b'A') as usize]),
+            c @ b'0'..=b'9' => Some(DIGITS[(c - b'0') as usize]),
+            _ => None,
+        };
+    }
+    NAMED
+        .into_iter()
+        .find(|key| format!("{key:?}").eq_ignore_ascii_case(name))
+}
+
+pub struct Keypad(Key);
+
+impl TryFrom<u8> for Keypad {
+    type Error = u8;
+
+    fn try_from(value: u8) -> Result<Self, Self::Error> {
+        Ok(match value {
+            0x0 => Keypad(Key::Key1),
+            0x1 => Keypad(Key::Key2),
+            0x2 => Keypad(Key::Key3),
+            0x3 => Keypad(Key::Key4),
+            0x4 => Keypad(Key::Q),
+            0x5 => Keypad(Key::W),
+            0x6 => Keypad(Key::E),
+            0x7 => Keypad(Key::R),
+            0x8 => Keypad(Key::A),
+            0x9 => Keypad(Key::S),
+            0xA => Keypad(Key::D),
+            0xB => Keypad(Key::F),
+            0xC => Keypad(Key::Z),
+            0xD => Keypad(Key::X),
+            0xE => Keypad(Key::C),
+            0xF => Keypad(Key::V),
+            _ => return Err(value),
+        })
+    }
+}
+
+impl TryFrom<Keypad> for u8 {
+    type Error = Key;
+
+    fn try_from(value: Keypad) -> Result<Self, Self::Error> {
+        Ok(match value.0 {
+            Key::Key1 => 0x0,
+            Key::Key2 => 0x1,
+            Key::Key3 => 0x2,
+            Key::Key4 => 0x3,
+            Key::Q => 0x4,
+            Key::W => 0x5,
+            Key::E => 0x6,
+            Key::R => 0x7,
+            Key::A => 0x8,
+            Key::S => 0x9,
+            Key::D => 0xA,
+            Key::F => 0xB,
+            Key::Z => 0xC,
+            Key::X => 0xD,
+            Key::C => 0xE,
+            Key::V => 0xF,
+            key => return Err(key),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{key_named, Keypad};
+    use minifb::Key;
+
+    #[test]
+    fn test_keypad() {
+        for key in 0x0..=0xF {
+            assert_eq!(u8::try_from(Keypad::try_from(key).unwrap()), Ok(key));
+        }
+        assert!(Keypad::try_from(0x10).is_err());
+    }
+
+    #[test]
+    fn test_key_named() {
+        assert_eq!(key_named("up"), Some(Key::Up));
+        assert_eq!(key_named("LeftShift"), Some(Key::LeftShift));
+        assert_eq!(key_named("q"), Some(Key::Q));
+        assert_eq!(key_named("7"), Some(Key::Key7));
+        assert_eq!(key_named("Escape"), None);
+    }
+}
@@ -0,0 +1,170 @@
+//! Random programs for the tests that run the same code on two interpreters.
+//! Every program stays inside itself and inside memory: jumps land on code,
+//! calls nest no deeper than the stack and return, and I stays low enough that
+//! nothing reads or writes past the end within a few hundred instructions.
+
+use rusty_chip8::{chip::Chip, platform::Headless, quirks::Quirks};
+
+// The main program is this many units of one to three instructions. Jumps
+// land on the start of a unit, and a skip only ever skips the instruction
+// after it in its own unit.
+const UNITS: usize = 32;
+// Subroutine `s` may call any later one, so calls nest at most this deep.
+const SUBROUTINES: usize = 4;
+const SUBROUTINE_UNITS: usize = 6;
+
+#[derive(Clone, Copy)]
+enum Slot {
+    Op(u16),
+    Jump(usize),
+    Call(usize),
+    // Sets V0 and V2 to the offset before BNNN, so it lands on the unit with
+    // or without the jumping quirk: NNN is in 0x2XX, which makes X 2.
+    JumpOffset(usize, u16),
+}
+
+impl Slot {
+    fn len(self) -> u16 {
+        match self {
+            Slot::JumpOffset(..) => 6,
+            _ => 2,
+        }
+    }
+}
+
+pub fn quirks(rng: &mut fastrand::Rng) -> Quirks {
+    Quirks {
+        shift: rng.bool(),
+        vf_reset: rng.bool(),
+        memory: rng.bool(),
+        jumping: rng.bool(),
+        clipping: rng.bool(),
+    }
+}
+
+fn skip(rng: &mut fastrand::Rng) -> u16 {
+    let x = rng.u16(0..16) << 8;
+    let y = rng.u16(0..16) << 4;
+    let nn = rng.u16(0..256);
+    match rng.u8(0..5) {
+        0 => 0x3000 | x | nn,
+        1 => 0x4000 | x | nn,
+        2 => 0x5000 | x | y,
+        3 => 0x9000 | x | y,
+        _ => 0xE000 | x | [0x9E, 0xA1][rng.usize(0..2)],
+    }
+}
+
+// An instruction that goes on to the next one.
+fn straight(rng: &mut fastrand::Rng) -> u16 {
+    let x = rng.u16(0..16) << 8;
+    let y = rng.u16(0..16) << 4;
+    let nn = rng.u16(0..256);
+    match rng.u8(0..10) {
+        0 => 0x6000 | x | nn,
+        1 => 0x7000 | x | nn,
+        2..=3 => 0x8000 | x | y | [0, 1, 2, 3, 4, 5, 6, 7, 0xE][rng.usize(0..9)],
+        4 => 0xA000 | rng.u16(0x400..0x800),
+        5 => 0xC000 | x | nn,
+        6 => 0xD000 | x | y | rng.u16(0..16),
+        7 => 0xF000 | x | [0x07, 0x0A, 0x15, 0x18, 0x33][rng.usize(0..5)],
+        _ => 0xF000 | x | [0x55, 0x65][rng.usize(0..2)],
+    }
+}
+
+fn unit(rng: &mut fastrand::Rng, calls: std::ops::Range<usize>, jumps: bool) -> Vec<Slot> {
+    match rng.u8(0..12) {
+        0..=2 => vec![Slot::Op(skip(rng)), Slot::Op(straight(rng))],
+        3 if jumps => vec![Slot::Jump(rng.usize(0..UNITS))],
+        4 if jumps => {
+            // Every unit before the target is at least two bytes long.
+            let unit = rng.usize(0..UNITS);
+            vec![Slot::JumpOffset(
+                unit,
+                rng.u16(0..=(2 * unit as u16).min(0xFF)),
+            )]
+        }
+        5 if !calls.is_empty() => vec![Slot::Call(rng.usize(calls))],
+        // I is set first so adding to it cannot carry it off the end.
+        6 => vec![
+            Slot::Op(0xA000 | rng.u16(0x400..0x800)),
+            Slot::Op(0xF01E | rng.u16(0..16) << 8),
+        ],
+        _ => vec![Slot::Op(straight(rng))],
+    }
+}
+
+/// A random program of jumps, skips, calls and the rest, to load at 0x200.
+pub fn program(rng: &mut fastrand::Rng) -> Vec<u8> {
+    // Starting with 1260 would make the chip hi-res.
+    let mut main = vec![vec![Slot::Op(straight(rng))]];
+    main.extend((1..UNITS).map(|_| unit(rng, 0..SUBROUTINES, true)));
+    // Loops rather than falling into the subroutines.
+    main.push(vec![Slot::Jump(0)]);
+    let subroutines: Vec<Vec<Slot>> = (0..SUBROUTINES)
+        .map(|s| {
+            let mut body: Vec<Slot> = (0..SUBROUTINE_UNITS)
+                .flat_map(|_| unit(rng, s + 1..SUBROUTINES, false))
+                .collect();
+            body.push(Slot::Op(0x00EE));
+            body
+        })
+        .collect();
+
+    let mut address = 0x200;
+    let mut place = |len: u16| {
+        address += len;
+        address - len
+    };
+    let units: Vec<u16> = main
+        .iter()
+        .map(|unit| place(unit.iter().map(|slot| slot.len()).sum()))
+        .collect();
+    let entries: Vec<u16> = subroutines
+        .iter()
+        .map(|body| place(body.iter().map(|slot| slot.len()).sum()))
+        .collect();
+
+    main.iter()
+        .chain(&subroutines)
+        .flatten()
+        .flat_map(|&slot| match slot {
+            Slot::Op(opcode) => vec![opcode],
+            Slot::Jump(unit) => vec![0x1000 | units[unit]],
+            Slot::Call(s) => vec![0x2000 | entries[s]],
+            Slot::JumpOffset(unit, offset) => {
+                vec![
+                    0x6000 | offset,
+                    0x6200 | offset,
+                    0xB000 | (units[unit] - offset),
+                ]
+            }
+        })
+        .flat_map(u16::to_be_bytes)
+        .collect()
+}
+
+/// A chip running a random program, with random registers, quirks and data
+/// from 0x400, and a keypad that is either empty or randomly pressed.
+pub fn random_chip(seed: u64) -> (Chip, Headless) {
+    let mut rng = fastrand::Rng::with_seed(seed);
+    let program = program(&mut rng);
+
+    let mut chip = Chip::new();
+    chip.trace = false;
+    chip.quirks = quirks(&mut rng);
+    chip.load_bytes(&program).unwrap();
+    rng.fill(&mut chip.v);
+    chip.i = rng.u32(0x400..0x800);
+    chip.dt = rng.u8(..);
+    chip.st = rng.u8(..);
+    let data: Vec<u8> = (0x400..0x1000).map(|_| rng.u8(..)).collect();
+    chip.write(0x400, &data);
+    chip.rng = fastrand::Rng::with_seed(seed);
+
+    let mut platform = Headless::default();
+    if rng.bool() {
+        platform.keys.iter_mut().for_each(|key| *key = rng.bool());
+    }
+    (chip, platform)
+}
@@ -0,0 +1,240 @@
+//! Runs random programs on `Chip` and on a deliberately naive model written
+//! straight from the CHIP-8 specification, comparing them after every
+//! instruction.
+
+mod common;
+
+use rusty_chip8::{chip::Chip, platform::Headless, quirks::Quirks};
+
+const SEEDS: u64 = 500;
+const STEPS: usize = 100;
+const CYCLES_PER_FRAME: usize = 11;
+
+/// The reference interpreter. Every instruction reads its operands before
+/// writing anything, and VF is written last.
+struct Reference {
+    v: [u8; 16],
+    i: u32,
+    pc: u16,
+    stack: Vec<u16>,
+    dt: u8,
+    st: u8,
+    mem: Vec<u8>,
+    screen: Vec<bool>,
+    quirks: Quirks,
+    keys: [bool; 16],
+    rng: fastrand::Rng,
+}
+
+impl Reference {
+    fn key(&self, vx: u8) -> bool {
+        vx < 16 && self.keys[vx as usize]
+    }
+
+    fn step(&mut self) {
+        let hi = self.mem[self.pc as usize];
+        let lo = self.mem[self.pc as usize + 1];
+        let opcode = (hi as u16) << 8 | lo as u16;
+        let x = (hi & 0xF) as usize;
+        let y = (lo >> 4) as usize;
+        let n = lo & 0xF;
+        let nn = lo;
+        let nnn = opcode & 0xFFF;
+        let vx = self.v[x];
+        let vy = self.v[y];
+        let mut next = self.pc + 2;
+
+        match (hi >> 4, n) {
+            (0x0, _) if opcode == 0x00EE => next = self.stack.pop().unwrap(),
+            (0x1, _) => next = nnn,
+            (0x2, _) => {
+                self.stack.push(next);
+                next = nnn;
+            }
+            (0x3, _) if vx == nn => next += 2,
+            (0x4, _) if vx != nn => next += 2,
+            (0x5, 0) if vx == vy => next += 2,
+            (0x6, _) => self.v[x] = nn,
+            (0x7, _) => self.v[x] = vx.wrapping_add(nn),
+            (0x8, 0x0) => self.v[x] = vy,
+            (0x8, 0x1..=0x3) => {
+                self.v[x] = match n {
+                    0x1 => vx | vy,
+                    0x2 => vx & vy,
+                    _ => vx ^ vy,
+                };
+                if self.quirks.vf_reset {
+                    self.v[0xF] = 0;
+                }
+            }
+            (0x8, 0x4) => {
+                let sum = vx as u16 + vy as u16;
+                self.v[x] = sum as u8;
+                self.v[0xF] = (sum > 0xFF) as u8;
+            }
+            (0x8, 0x5) => {
+                self.v[x] = vx.wrapping_sub(vy);
+                self.v[0xF] = (vx >= vy) as u8;
+            }
+            (0x8, 0x7) => {
+                self.v[x] = vy.wrapping_sub(vx);
+                self.v[0xF] = (vy >= vx) as u8;
+            }
+            (0x8, 0x6) => {
+                let value = if self.quirks.shift { vx } else { vy };
+                self.v[x] = value >> 1;
+                self.v[0xF] = value & 1;
+            }
+            (0x8, 0xE) => {
+                let value = if self.quirks.shift { vx } else { vy };
+                self.v[x] = value << 1;
+                self.v[0xF] = value >> 7;
+            }
+            (0x9, 0) if vx != vy => next += 2,
+            (0xA, _) => self.i = nnn as u32,
+            (0xB, _) => next = nnn + if self.quirks.jumping { vx } else { self.v[0] } as u16,
+            (0xC, _) => self.v[x] = self.rng.u8(..) & nn,
+            (0xD, _) => {
+                let mut collision = false;
+                for row in 0..n as usize {
+                    let sprite = self.mem[self.i as usize + row];
+                    for col in 0..8 {
+                        let px = (vx % 64) as usize + col;
+                        let py = (vy % 32) as usize + row;
+                        if self.quirks.clipping && (px >= 64 || py >= 32) {
+                            continue;
+                        }
+                        let pixel = &mut self.screen[(py % 32) * 64 + px % 64];
+                        if sprite & (0x80 >> col) != 0 {
+                            collision |= *pixel;
+                            *pixel = !*pixel;
+                        }
+                    }
+                }
+                self.v[0xF] = collision as u8;
+            }
+            (0xE, _) if nn == 0x9E && self.key(vx) => next += 2,
+            (0xE, _) if nn == 0xA1 && !self.key(vx) => next += 2,
+            (0xF, _) => match nn {
+                0x07 => self.v[x] = self.dt,
+                0x0A => match (0..16).find(|&k| self.keys[k as usize]) {
+                    Some(key) => self.v[x] = key,
+                    None => next = self.pc,
+                },
+                0x15 => self.dt = vx,
+                0x18 => self.st = vx,
+                0x1E => self.i += vx as u32,
+                0x33 => {
+                    let i = self.i as usize;
+                    self.mem[i] = vx / 100;
+                    self.mem[i + 1] = vx / 10 % 10;
+                    self.mem[i + 2] = vx % 10;
+                }
+                0x55 | 0x65 => {
+                    for r in 0..=x {
+                        let address = self.i as usize + r;
+                        if nn == 0x55 {
+                            self.mem[address] = self.v[r];
+                        } else {
+                            self.v[r] = self.mem[address];
+                        }
+                    }
+                    if self.quirks.memory {
+                        self.i += x as u32 + 1;
+                    }
+                }
+                _ => {}
+            },
+            _ => {}
+        }
+        self.pc = next;
+    }
+
+    fn tick_timers(&mut self) {
+        self.dt = self.dt.saturating_sub(1);
+        self.st = self.st.saturating_sub(1);
+    }
+}
+
+fn setup(seed: u64) -> (Chip, Headless, Reference) {
+    let (chip, platform) = common::random_chip(seed);
+    let reference = Reference {
+        v: chip.v,
+        i: chip.i,
+        pc: chip.pc,
+        stack: Vec::new(),
+        dt: chip.dt,
+        st: chip.st,
+        mem: chip.mem.to_vec(),
+        screen: vec![false; 64 * 32],
+        quirks: chip.quirks,
+        keys: platform.keys,
+        rng: chip.rng.clone(),
+    };
+    (chip, platform, reference)
+}
+
+// What differs between the two, if anything.
+fn divergence(chip: &Chip, buffer: &[u32], reference: &Reference) -> Option<String> {
+    if let Some(r) = (0..16).find(|&r| chip.v[r] != reference.v[r]) {
+        return Some(format!(
+            "V{r:X} is {:02X}, expected {:02X}",
+            chip.v[r], reference.v[r]
+        ));
+    }
+    let registers = [
+        ("I", chip.i, reference.i),
+        ("PC", chip.pc as u32, reference.pc as u32),
+        ("DT", chip.dt as u32, reference.dt as u32),
+        ("ST", chip.st as u32, reference.st as u32),
+    ];
+    if let Some((name, actual, expected)) = registers.iter().find(|(_, a, e)| a != e) {
+        return Some(format!("{name} is {actual:03X}, expected {expected:03X}"));
+    }
+    if chip.stack[..chip.sp] != reference.stack[..] {
+        return Some(format!(
+            "stack is {:03X?}, expected {:03X?}",
+            &chip.stack[..chip.sp],
+            reference.stack
+        ));
+    }
+    if chip.mem[..] != reference.mem[..] {
+        let a = (0..chip.mem.len())
+            .find(|&a| chip.mem[a] != reference.mem[a])
+            .unwrap();
+        return Some(format!(
+            "memory at {a:03X} is {:02X}, expected {:02X}",
+            chip.mem[a], reference.mem[a]
+        ));
+    }
+    if let Some(p) = (0..64 * 32).find(|&p| (buffer[p] != 0) != reference.screen[p]) {
+        return Some(format!("pixel ({}, {}) differs", p % 64, p / 64));
+    }
+    None
+}
+
+#[test]
+fn test_matches_reference() {
+    for seed in 0..SEEDS {
+        let (mut chip, platform, mut reference) = setup(seed);
+        let mut buffer = vec![0u32; 64 * 32];
+
+        for step in 0..STEPS {
+            let pc = chip.pc as usize;
+            let opcode = u16::from_be_bytes([chip.mem[pc], chip.mem[pc + 1]]);
+            chip.step(&mut buffer, &platform).unwrap();
+            reference.step();
+            if step % CYCLES_PER_FRAME == CYCLES_PER_FRAME - 1 {
+                chip.tick_timers();
+                reference.tick_timers();
+            }
+
+            if let Some(difference) = divergence(&chip, &buffer, &reference) {
+                panic!(
+                    "seed {seed}, step {step}: {opcode:04X} at {pc:03X} with {:?}: {difference}",
+                    chip.quirks
+                );
+            }
+        }
+    }
+}
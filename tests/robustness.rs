@@ -0,0 +1,112 @@
+//! The property the `execute` fuzz target checks, over seeded random roms so it
+//! also runs under `cargo test`: any rom runs without panicking, and the
+//! interpreter and the recompiler agree on what it does.
+
+mod common;
+
+use rusty_chip8::{chip::Chip, platform::Headless, recompile::Recompiler, variant::Variant};
+
+const SEEDS: u64 = 300;
+const FRAMES: usize = 60;
+const CYCLES_PER_FRAME: usize = 11;
+
+fn chip(rng: &mut fastrand::Rng) -> Chip {
+    let mut chip = Chip::new();
+    chip.trace = false;
+    chip.rng = fastrand::Rng::with_seed(rng.u64(..));
+    chip.quirks = common::quirks(rng);
+    chip.variant = [
+        Variant::Chip8,
+        Variant::Chip8x,
+        Variant::Hires,
+        Variant::Megachip,
+    ][rng.usize(0..4)];
+    chip.start = chip.variant.start();
+    let rom: Vec<u8> = (0..rng.usize(0..0x400)).map(|_| rng.u8(..)).collect();
+    chip.load_bytes(&rom).unwrap();
+    chip
+}
+
+#[test]
+fn test_random_roms() {
+    for seed in 0..SEEDS {
+        let mut interpreted = chip(&mut fastrand::Rng::with_seed(seed));
+        let mut recompiled = chip(&mut fastrand::Rng::with_seed(seed));
+        let mut recompiler = Recompiler::new();
+        // CHIP-8 roms that happen to start with 1260 get the hi-res display.
+        let (width, height) = interpreted.variant.size();
+        let mut interpreted_buffer = vec![0u32; width * height];
+        let mut recompiled_buffer = vec![0u32; width * height];
+        let mut rng = fastrand::Rng::with_seed(seed);
+
+        for frame in 0..FRAMES {
+            let mut platform = Headless::default();
+            platform
+                .keys
+                .iter_mut()
+                .for_each(|key| *key = rng.u8(..8) == 0);
+
+            let expected = interpreted.frame(CYCLES_PER_FRAME, &mut interpreted_buffer, &platform);
+            let actual = recompiler.frame(
+                &mut recompiled,
+                CYCLES_PER_FRAME,
+                &mut recompiled_buffer,
+                &platform,
+            );
+            assert_eq!(expected, actual, "seed {seed}, frame {frame}");
+            assert_eq!(
+                interpreted.to_string(),
+                recompiled.to_string(),
+                "seed {seed}, frame {frame}"
+            );
+            assert!(
+                interpreted_buffer == recompiled_buffer,
+                "seed {seed}, frame {frame}"
+            );
+            if expected.is_err() {
+                break;
+            }
+        }
+        // Once per rom, as MegaChip has 16M of it.
+        assert!(interpreted.mem == recompiled.mem, "seed {seed}");
+    }
+}
+
+fn state(chip: &Chip) -> String {
+    format!("{chip}\n   Stack: {:X?}", chip.stack)
+}
+
+// Random programs rather than random bytes, so they run for long enough to
+// reach calls, skips and blocks that jump back into themselves.
+#[test]
+fn test_random_programs() {
+    for seed in 0..200 {
+        let (mut expected, platform) = common::random_chip(seed);
+        let (mut actual, _) = common::random_chip(seed);
+        // Hundreds of instructions would take I off the end with the quirk.
+        expected.quirks.memory = false;
+        actual.quirks.memory = false;
+        let mut expected_buffer = [0u32; 64 * 32];
+        let mut actual_buffer = [0u32; 64 * 32];
+        let mut recompiler = Recompiler::new();
+
+        for frame in 0..20 {
+            let cycles = 1 + (seed as usize * 7 + frame * 13) % 40;
+            expected
+                .frame(cycles, &mut expected_buffer, &platform)
+                .unwrap();
+            recompiler
+                .frame(&mut actual, cycles, &mut actual_buffer, &platform)
+                .unwrap();
+            assert_eq!(
+                state(&expected),
+                state(&actual),
+                "seed {seed} diverged in frame {frame}"
+            );
+            assert!(
+                expected_buffer == actual_buffer,
+                "seed {seed} frame {frame}"
+            );
+        }
+    }
+}
@@ -0,0 +1,55 @@
+use rusty_chip8::{
+    chip::Chip,
+    conformance::{hash, render, run_headless},
+};
+
+const FRAMES: usize = 500;
+const CYCLES_PER_FRAME: usize = 11;
+const SEED: u64 = 0xC8;
+
+// Golden hashes of the display after FRAMES frames. When a change to the
+// interpreter is meant to alter what a ROM draws, check the new screen printed
+// by the failing test and replace the hash with the one it reports.
+const GOLDEN: [(&str, u64); 8] = [
+    ("IBM_Logo.ch8", 0x1f1d341cab07e169),
+    ("Zero.ch8", 0x07d44aaf6caf7159),
+    ("Trip8.ch8", 0x7d0ac25dfc5ccade),
+    ("Sierpinski.ch8", 0x78ad39f2bae951ea),
+    ("Maze.ch8", 0x44663a8cb4c25325),
+    ("Stars.ch8", 0x8867d1a02e72a042),
+    ("particle_demo.ch8", 0xba14381ff819eade),
+    ("space_invaders.ch8", 0x38fd40c3541566ce),
+];
+
+fn run(rom: &str) -> Vec<u32> {
+    let mut chip = Chip::new();
+    chip.trace = false;
+    chip.rng = fastrand::Rng::with_seed(SEED);
+    chip.load(format!("{}/roms/{rom}", env!("CARGO_MANIFEST_DIR")))
+        .unwrap();
+    run_headless(&mut chip, FRAMES, CYCLES_PER_FRAME).unwrap()
+}
+
+#[test]
+fn test_golden_displays() {
+    let mismatches: Vec<String> = GOLDEN
+        .iter()
+        .filter_map(|&(rom, golden)| {
+            let buffer = run(rom);
+            let actual = hash(&buffer);
+            (actual != golden).then(|| {
+                format!(
+                    "{rom}: expected {golden:#018x}, got {actual:#018x}\n{}",
+                    render(&buffer)
+                )
+            })
+        })
+        .collect();
+
+    assert!(mismatches.is_empty(), "\n{}", mismatches.join("\n\n"));
+}
+
+#[test]
+fn test_deterministic() {
+    assert_eq!(run("Maze.ch8"), run("Maze.ch8"));
+}